@@ -0,0 +1,82 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+pub struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, byte: u8) {
+        self.register = byte;
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn is_dac_enabled(&self) -> bool {
+        (self.register & 0xF8) != 0
+    }
+
+    fn get_period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn is_increasing(&self) -> bool {
+        (self.register & 0x08) != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = (self.register & 0xF0) >> 4;
+        self.timer = self.get_period();
+    }
+
+    pub fn clock(&mut self) {
+        let period: u8 = self.get_period();
+        if period == 0 {
+            return;
+        }
+
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = period;
+            if self.is_increasing() && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.is_increasing() && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+}
+
+impl Stateful for Envelope {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_u8(self.volume);
+        state.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.register = state.read_u8()?;
+        self.volume = state.read_u8_max(15)?;
+        self.timer = state.read_u8_max(7)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+pub struct LengthCounter {
+    counter: u16,
+    max: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub fn new(max: u16) -> LengthCounter {
+        LengthCounter {
+            counter: 0,
+            max,
+            enabled: false,
+        }
+    }
+
+    pub fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Returns true when the counter reaches zero and the channel must be disabled
+    pub fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            self.counter == 0
+        } else {
+            false
+        }
+    }
+
+    // Enabling the counter while the next frame sequencer step doesn't clock
+    // the length units causes an extra clock
+    pub fn set_enabled(&mut self, enabled: bool, frame_step: u8) -> bool {
+        let extra_clock: bool = !self.enabled && enabled && (frame_step & 0x1) != 0;
+        self.enabled = enabled;
+        extra_clock && self.clock()
+    }
+
+    pub fn trigger(&mut self, frame_step: u8) {
+        if self.counter == 0 {
+            self.counter = self.max;
+            if self.enabled && (frame_step & 0x1) != 0 {
+                self.counter -= 1;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.enabled = false;
+    }
+}
+
+impl Stateful for LengthCounter {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        state.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.counter = state.read_u16_max(self.max)?;
+        self.enabled = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use noise_channel::NoiseChannel;
+use pulse_channel::PulseChannel;
+use resampler::Resampler;
+use std::error::Error;
+use wave_channel::WaveChannel;
+
+mod envelope;
+mod length_counter;
+mod noise_channel;
+mod pulse_channel;
+mod resampler;
+mod sweep;
+mod wave_channel;
+
+// The mixer output is sampled once per M-cycle
+const CLOCK_RATE: u32 = 1048576;
+pub const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+pub trait Channel {
+    fn tick(&mut self);
+    fn clock_length(&mut self);
+    fn is_enabled(&self) -> bool;
+    fn is_dac_enabled(&self) -> bool;
+    fn output(&self) -> u8;
+}
+
+pub struct Apu {
+    ch1: PulseChannel,
+    ch2: PulseChannel,
+    ch3: WaveChannel,
+    ch4: NoiseChannel,
+    nr50: u8,
+    nr51: u8,
+    enabled: bool,
+    frame_step: u8,
+    div_bit: bool,
+    left: i16,
+    right: i16,
+    t_cycles: u8,
+    clock: u32,
+    sample_rate: u32,
+    left_resampler: Resampler,
+    right_resampler: Resampler,
+    samples: Vec<i16>,
+}
+
+impl Apu {
+    pub fn new() -> Apu {
+        let mut apu: Apu = Apu {
+            ch1: PulseChannel::new(true),
+            ch2: PulseChannel::new(false),
+            ch3: WaveChannel::new(),
+            ch4: NoiseChannel::new(),
+            nr50: 0x77,
+            nr51: 0xF3,
+            enabled: true,
+            frame_step: 0,
+            div_bit: false,
+            left: 0,
+            right: 0,
+            t_cycles: 0,
+            clock: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            left_resampler: Resampler::new(CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            right_resampler: Resampler::new(CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            samples: Vec::new(),
+        };
+        // The boot rom leaves channel 1 enabled with its envelope faded out to 0
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF12, 0x08);
+        apu.write(0xFF13, 0xC1);
+        apu.write(0xFF14, 0x87);
+        apu.write(0xFF12, 0xF3);
+        apu
+    }
+
+    pub fn tick(&mut self, cycles: u8, div: u8) {
+        let div_bit: bool = (div & 0x10) != 0;
+        if self.enabled && self.div_bit && !div_bit {
+            self.step_frame_sequencer();
+        }
+        self.div_bit = div_bit;
+
+        for _ in 0..cycles {
+            if self.enabled {
+                self.ch1.tick();
+                self.ch2.tick();
+                self.ch3.tick();
+                self.ch4.tick();
+            }
+
+            self.t_cycles += 1;
+            if self.t_cycles == 4 {
+                self.t_cycles = 0;
+                self.sample();
+            }
+        }
+    }
+
+    pub fn get_output(&self) -> (i16, i16) {
+        (self.left, self.right)
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.left_resampler = Resampler::new(CLOCK_RATE, sample_rate);
+        self.right_resampler = Resampler::new(CLOCK_RATE, sample_rate);
+        self.left = 0;
+        self.right = 0;
+        self.clock = 0;
+        self.samples.clear();
+    }
+
+    pub fn end_frame(&mut self) {
+        self.left_resampler.end_frame(self.clock);
+        self.right_resampler.end_frame(self.clock);
+        self.clock = 0;
+
+        let start: usize = self.samples.len();
+        let available: usize = self.left_resampler.available();
+        if available == 0 {
+            return;
+        }
+        self.samples.resize(start + available * 2, 0);
+        self.left_resampler
+            .read_samples(&mut self.samples[start..], 2);
+        self.right_resampler
+            .read_samples(&mut self.samples[(start + 1)..], 2);
+
+        // Keep at most one second of audio if nobody drains the samples
+        let limit: usize = (self.sample_rate as usize) * 2;
+        if self.samples.len() > limit {
+            self.samples.drain(0..(self.samples.len() - limit));
+        }
+    }
+
+    pub fn drain_samples(&mut self, samples: &mut Vec<i16>) {
+        samples.append(&mut self.samples);
+    }
+
+    fn sample(&mut self) {
+        let (old_left, old_right): (i16, i16) = (self.left, self.right);
+        self.mix();
+        if self.left != old_left {
+            self.left_resampler
+                .add_delta(self.clock, (self.left as i32) - (old_left as i32));
+        }
+        if self.right != old_right {
+            self.right_resampler
+                .add_delta(self.clock, (self.right as i32) - (old_right as i32));
+        }
+        self.clock += 1;
+    }
+
+    fn step_frame_sequencer(&mut self) {
+        match self.frame_step {
+            0 | 4 => self.clock_lengths(),
+            2 | 6 => {
+                self.clock_lengths();
+                self.ch1.clock_sweep();
+            }
+            7 => {
+                self.ch1.clock_envelope();
+                self.ch2.clock_envelope();
+                self.ch4.clock_envelope();
+            }
+            _ => {}
+        }
+        self.frame_step = (self.frame_step + 1) & 0x7;
+    }
+
+    fn clock_lengths(&mut self) {
+        self.ch1.clock_length();
+        self.ch2.clock_length();
+        self.ch3.clock_length();
+        self.ch4.clock_length();
+    }
+
+    fn get_dac_output(channel: &dyn Channel) -> i16 {
+        if channel.is_dac_enabled() {
+            (channel.output() as i16) * 2 - 15
+        } else {
+            0
+        }
+    }
+
+    fn mix(&mut self) {
+        let outputs: [i16; 4] = [
+            Apu::get_dac_output(&self.ch1),
+            Apu::get_dac_output(&self.ch2),
+            Apu::get_dac_output(&self.ch3),
+            Apu::get_dac_output(&self.ch4),
+        ];
+        let mut left: i16 = 0;
+        let mut right: i16 = 0;
+
+        for (i, output) in outputs.iter().enumerate() {
+            if (self.nr51 & (0x10 << i)) != 0 {
+                left += output;
+            }
+            if (self.nr51 & (0x01 << i)) != 0 {
+                right += output;
+            }
+        }
+
+        // 4 channels * 15 * 8 volume steps * 64 fits in an i16
+        self.left = left * ((((self.nr50 & 0x70) >> 4) + 1) as i16) * 64;
+        self.right = right * (((self.nr50 & 0x07) + 1) as i16) * 64;
+    }
+
+    fn power_off(&mut self) {
+        self.ch1.power_off();
+        self.ch2.power_off();
+        self.ch3.power_off();
+        self.ch4.power_off();
+        self.nr50 = 0;
+        self.nr51 = 0;
+        self.enabled = false;
+    }
+
+    fn power_on(&mut self) {
+        self.frame_step = 0;
+        self.enabled = true;
+    }
+
+    fn read_nr52(&self) -> u8 {
+        let power: u8 = if self.enabled { 0x80 } else { 0x00 };
+        let status: [bool; 4] = [
+            self.ch1.is_enabled(),
+            self.ch2.is_enabled(),
+            self.ch3.is_enabled(),
+            self.ch4.is_enabled(),
+        ];
+        status
+            .iter()
+            .enumerate()
+            .fold(power | 0x70, |acc, (i, enabled)| {
+                if *enabled {
+                    acc | (1 << i)
+                } else {
+                    acc
+                }
+            })
+    }
+
+    fn write_nr52(&mut self, byte: u8) {
+        let enabled: bool = (byte & 0x80) != 0;
+        if self.enabled && !enabled {
+            self.power_off();
+        } else if !self.enabled && enabled {
+            self.power_on();
+        }
+    }
+
+    // While powered off only NR52, the wave ram and the length counters are writable
+    fn write_powered_off(&mut self, location: u16, byte: u8) {
+        match location {
+            0xFF11 => self.ch1.write_length(byte),
+            0xFF16 => self.ch2.write_length(byte),
+            0xFF1B => self.ch3.write_length(byte),
+            0xFF20 => self.ch4.write_length(byte),
+            0xFF26 => self.write_nr52(byte),
+            0xFF30..=0xFF3F => self.ch3.write_wave_ram((location - 0xFF30) as u8, byte),
+            _ => {}
+        }
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Addressable for Apu {
+    fn write(&mut self, location: u16, byte: u8) {
+        if !self.enabled {
+            self.write_powered_off(location, byte);
+            return;
+        }
+
+        match location {
+            0xFF10..=0xFF14 => self
+                .ch1
+                .write((location - 0xFF10) as u8, byte, self.frame_step),
+            0xFF15 => {}
+            0xFF16..=0xFF19 => self
+                .ch2
+                .write((location - 0xFF15) as u8, byte, self.frame_step),
+            0xFF1A..=0xFF1E => self
+                .ch3
+                .write((location - 0xFF1A) as u8, byte, self.frame_step),
+            0xFF1F => {}
+            0xFF20..=0xFF23 => self
+                .ch4
+                .write((location - 0xFF1F) as u8, byte, self.frame_step),
+            0xFF24 => self.nr50 = byte,
+            0xFF25 => self.nr51 = byte,
+            0xFF26 => self.write_nr52(byte),
+            0xFF27..=0xFF2F => {}
+            0xFF30..=0xFF3F => self.ch3.write_wave_ram((location - 0xFF30) as u8, byte),
+            _ => panic!("APU unsupported write to {:#04X}", location),
+        }
+    }
+
+    fn read(&self, location: u16) -> u8 {
+        match location {
+            0xFF10..=0xFF14 => self.ch1.read((location - 0xFF10) as u8),
+            0xFF15..=0xFF19 => self.ch2.read((location - 0xFF15) as u8),
+            0xFF1A..=0xFF1E => self.ch3.read((location - 0xFF1A) as u8),
+            0xFF1F => 0xFF,
+            0xFF20..=0xFF23 => self.ch4.read((location - 0xFF1F) as u8),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            0xFF26 => self.read_nr52(),
+            0xFF27..=0xFF2F => 0xFF,
+            0xFF30..=0xFF3F => self.ch3.read_wave_ram((location - 0xFF30) as u8),
+            _ => panic!("APU unsupported read from {:#04X}", location),
+        }
+    }
+}
+
+impl Stateful for Apu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.ch1.save_state(state);
+        self.ch2.save_state(state);
+        self.ch3.save_state(state);
+        self.ch4.save_state(state);
+        state.write_u8(self.nr50);
+        state.write_u8(self.nr51);
+        state.write_bool(self.enabled);
+        state.write_u8(self.frame_step);
+        state.write_bool(self.div_bit);
+        state.write_u16(self.left as u16);
+        state.write_u16(self.right as u16);
+        state.write_u8(self.t_cycles);
+        state.write_u32(self.clock);
+        state.write_u32(self.sample_rate);
+        self.left_resampler.save_state(state);
+        self.right_resampler.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.ch1.load_state(state)?;
+        self.ch2.load_state(state)?;
+        self.ch3.load_state(state)?;
+        self.ch4.load_state(state)?;
+        self.nr50 = state.read_u8()?;
+        self.nr51 = state.read_u8()?;
+        self.enabled = state.read_bool()?;
+        self.frame_step = state.read_u8_max(7)?;
+        self.div_bit = state.read_bool()?;
+        self.left = state.read_u16()? as i16;
+        self.right = state.read_u16()? as i16;
+        self.t_cycles = state.read_u8_max(3)?;
+        // A frame never lasts more than a second
+        self.clock = state.read_u32_max(CLOCK_RATE)?;
+        let sample_rate: u32 = state.read_u32()?;
+        if sample_rate != self.sample_rate {
+            return Err(format!(
+                "The save state was made at {} Hz, the audio output runs at {} Hz",
+                sample_rate, self.sample_rate
+            )
+            .into());
+        }
+        self.left_resampler.load_state(state)?;
+        self.right_resampler.load_state(state)?;
+        // Both sides are read into the same interleaved samples
+        if self.left_resampler.get_offset() != self.right_resampler.get_offset() {
+            return Err("The resamplers are out of step in the save state".into());
+        }
+        Ok(())
+    }
+}
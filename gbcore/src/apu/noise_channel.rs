@@ -0,0 +1,160 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::Channel;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+pub struct NoiseChannel {
+    length: LengthCounter,
+    envelope: Envelope,
+    polynomial: u8,
+    timer: u32,
+    lfsr: u16,
+    enabled: bool,
+}
+
+impl NoiseChannel {
+    pub fn new() -> NoiseChannel {
+        NoiseChannel {
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            polynomial: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            enabled: false,
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn get_period(&self) -> u32 {
+        DIVISORS[(self.polynomial & 0x07) as usize] << ((self.polynomial & 0xF0) >> 4)
+    }
+
+    fn is_short_mode(&self) -> bool {
+        (self.polynomial & 0x08) != 0
+    }
+
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.timer = self.get_period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+        self.length.trigger(frame_step);
+    }
+
+    pub fn power_off(&mut self) {
+        let length: LengthCounter = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        *self = NoiseChannel::new();
+        self.length = length;
+        self.length.reset();
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            1 => 0xFF,
+            2 => self.envelope.read(),
+            3 => self.polynomial,
+            4 => {
+                if self.length.is_enabled() {
+                    0xFF
+                } else {
+                    0xBF
+                }
+            }
+            _ => panic!("NOISE unsupported read from NR4{}", register),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, byte: u8, frame_step: u8) {
+        match register {
+            1 => self.length.load((byte & 0x3F) as u16),
+            2 => {
+                self.envelope.write(byte);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.polynomial = byte,
+            4 => {
+                if self.length.set_enabled((byte & 0x40) != 0, frame_step) {
+                    self.enabled = false;
+                }
+                if (byte & 0x80) != 0 {
+                    self.trigger(frame_step);
+                }
+            }
+            _ => panic!("NOISE unsupported write to NR4{}", register),
+        }
+    }
+
+    pub fn write_length(&mut self, byte: u8) {
+        self.length.load((byte & 0x3F) as u16);
+    }
+}
+
+impl Channel for NoiseChannel {
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = self.get_period();
+            // Clock shifts 14 and 15 stop the LFSR
+            if (self.polynomial >> 4) < 14 {
+                let xor: u16 = (self.lfsr & 0x1) ^ ((self.lfsr & 0x2) >> 1);
+                self.lfsr = (self.lfsr >> 1) | (xor << 14);
+                if self.is_short_mode() {
+                    self.lfsr = (self.lfsr & !0x40) | (xor << 6);
+                }
+            }
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn is_dac_enabled(&self) -> bool {
+        self.envelope.is_dac_enabled()
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.lfsr & 0x1) == 0 {
+            self.envelope.get_volume()
+        } else {
+            0
+        }
+    }
+}
+
+impl Stateful for NoiseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_u8(self.polynomial);
+        state.write_u32(self.timer);
+        state.write_u16(self.lfsr);
+        state.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.polynomial = state.read_u8()?;
+        self.timer = state.read_u32_max(DIVISORS[7] << 15)?;
+        self.lfsr = state.read_u16_max(0x7FFF)?;
+        self.enabled = state.read_bool()?;
+        Ok(())
+    }
+}
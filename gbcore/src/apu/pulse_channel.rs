@@ -0,0 +1,194 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::sweep::Sweep;
+use crate::apu::sweep::SweepResult;
+use crate::apu::Channel;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+
+pub struct PulseChannel {
+    sweep: Option<Sweep>,
+    length: LengthCounter,
+    envelope: Envelope,
+    duty: u8,
+    frequency: u16,
+    timer: u16,
+    duty_position: u8,
+    enabled: bool,
+}
+
+impl PulseChannel {
+    pub fn new(with_sweep: bool) -> PulseChannel {
+        PulseChannel {
+            sweep: if with_sweep { Some(Sweep::new()) } else { None },
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            duty: 0,
+            frequency: 0,
+            timer: 0,
+            duty_position: 0,
+            enabled: false,
+        }
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if let Some(sweep) = &mut self.sweep {
+            match sweep.clock() {
+                SweepResult::Update(frequency) => self.frequency = frequency,
+                SweepResult::Overflow => self.enabled = false,
+                SweepResult::NoOp => {}
+            }
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.timer = (2048 - self.frequency) * 4;
+        self.envelope.trigger();
+        self.length.trigger(frame_step);
+        if let Some(sweep) = &mut self.sweep {
+            if !sweep.trigger(self.frequency) {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn power_off(&mut self) {
+        let with_sweep: bool = self.sweep.is_some();
+        let length: LengthCounter = std::mem::replace(&mut self.length, LengthCounter::new(64));
+        *self = PulseChannel::new(with_sweep);
+        self.length = length;
+        self.length.reset();
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0 => match &self.sweep {
+                Some(sweep) => 0x80 | sweep.read(),
+                None => 0xFF,
+            },
+            1 => (self.duty << 6) | 0x3F,
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            4 => {
+                if self.length.is_enabled() {
+                    0xFF
+                } else {
+                    0xBF
+                }
+            }
+            _ => panic!("PULSE unsupported read from NRx{}", register),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, byte: u8, frame_step: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    if sweep.write(byte) {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = (byte & 0xC0) >> 6;
+                self.length.load((byte & 0x3F) as u16);
+            }
+            2 => {
+                self.envelope.write(byte);
+                if !self.envelope.is_dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x700) | (byte as u16),
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((byte & 0x07) as u16) << 8);
+                if self.length.set_enabled((byte & 0x40) != 0, frame_step) {
+                    self.enabled = false;
+                }
+                if (byte & 0x80) != 0 {
+                    self.trigger(frame_step);
+                }
+            }
+            _ => panic!("PULSE unsupported write to NRx{}", register),
+        }
+    }
+
+    pub fn write_length(&mut self, byte: u8) {
+        self.length.load((byte & 0x3F) as u16);
+    }
+}
+
+impl Channel for PulseChannel {
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency) * 4;
+            self.duty_position = (self.duty_position + 1) & 0x7;
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn is_dac_enabled(&self) -> bool {
+        self.envelope.is_dac_enabled()
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled {
+            DUTY_TABLE[self.duty as usize][self.duty_position as usize] * self.envelope.get_volume()
+        } else {
+            0
+        }
+    }
+}
+
+impl Stateful for PulseChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        if let Some(sweep) = &self.sweep {
+            sweep.save_state(state);
+        }
+        self.length.save_state(state);
+        self.envelope.save_state(state);
+        state.write_u8(self.duty);
+        state.write_u16(self.frequency);
+        state.write_u16(self.timer);
+        state.write_u8(self.duty_position);
+        state.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        if let Some(sweep) = &mut self.sweep {
+            sweep.load_state(state)?;
+        }
+        self.length.load_state(state)?;
+        self.envelope.load_state(state)?;
+        self.duty = state.read_u8_max(3)?;
+        self.frequency = state.read_u16_max(0x7FF)?;
+        self.timer = state.read_u16_max(2048 * 4)?;
+        self.duty_position = state.read_u8_max(7)?;
+        self.enabled = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+
+// Band-limited step synthesis: every amplitude change is added to the output
+// as a windowed sinc step, which is then integrated when reading the samples
+const PHASE_BITS: u32 = 5;
+const PHASES: usize = 1 << PHASE_BITS;
+const WIDTH: usize = 16;
+const KERNEL_BITS: u32 = 15;
+const FRAC_BITS: u32 = 32;
+const BASS_SHIFT: u32 = 9;
+const CUTOFF: f64 = 0.9;
+// Far above anything the mixer produces, keeps the sums of a corrupted state from overflowing
+const MAX_LEVEL: i64 = 1 << 40;
+
+pub struct Resampler {
+    kernel: Vec<[i64; WIDTH]>,
+    factor: u64,
+    offset: u64,
+    buffer: Vec<i64>,
+    integrator: i64,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Resampler {
+        Resampler {
+            kernel: Resampler::build_kernel(),
+            factor: ((sample_rate as u64) << FRAC_BITS) / (clock_rate as u64),
+            offset: 0,
+            buffer: vec![0; WIDTH],
+            integrator: 0,
+        }
+    }
+
+    fn build_kernel() -> Vec<[i64; WIDTH]> {
+        let mut kernel: Vec<[i64; WIDTH]> = vec![[0; WIDTH]; PHASES];
+
+        for (phase, taps) in kernel.iter_mut().enumerate() {
+            let fraction: f64 = (phase as f64) / (PHASES as f64);
+            let mut values: [f64; WIDTH] = [0.0; WIDTH];
+
+            for (i, value) in values.iter_mut().enumerate() {
+                let x: f64 = (i as f64) - ((WIDTH / 2 - 1) as f64) - fraction;
+                let sinc: f64 = if x == 0.0 {
+                    CUTOFF
+                } else {
+                    (PI * CUTOFF * x).sin() / (PI * x)
+                };
+                let n: f64 = (x + (WIDTH / 2) as f64) / (WIDTH as f64);
+                let window: f64 = 0.42 - 0.5 * (2.0 * PI * n).cos() + 0.08 * (4.0 * PI * n).cos();
+                *value = sinc * window;
+            }
+
+            // Every phase has to sum exactly to 1 or the integrated steps would drift
+            let sum: f64 = values.iter().sum();
+            let mut total: i64 = 0;
+            for (i, value) in values.iter().enumerate() {
+                taps[i] = ((value / sum) * ((1 << KERNEL_BITS) as f64)).round() as i64;
+                total += taps[i];
+            }
+            taps[WIDTH / 2 - 1] += (1 << KERNEL_BITS) - total;
+        }
+        kernel
+    }
+
+    pub fn add_delta(&mut self, clock: u32, delta: i32) {
+        let position: u64 = self.offset + (clock as u64) * self.factor;
+        let index: usize = (position >> FRAC_BITS) as usize;
+        let phase: usize = ((position >> (FRAC_BITS - PHASE_BITS)) as usize) & (PHASES - 1);
+
+        if index + WIDTH > self.buffer.len() {
+            self.buffer.resize(index + WIDTH, 0);
+        }
+
+        for (i, tap) in self.kernel[phase].iter().enumerate() {
+            self.buffer[index + i] += (delta as i64) * tap;
+        }
+    }
+
+    pub fn end_frame(&mut self, clocks: u32) {
+        self.offset += (clocks as u64) * self.factor;
+        let available: usize = self.available();
+        if available + WIDTH > self.buffer.len() {
+            self.buffer.resize(available + WIDTH, 0);
+        }
+    }
+
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn available(&self) -> usize {
+        (self.offset >> FRAC_BITS) as usize
+    }
+
+    pub fn read_samples(&mut self, samples: &mut [i16], stride: usize) {
+        let available: usize = self.available();
+
+        for i in 0..available {
+            self.integrator += self.buffer[i];
+            let sample: i64 = self.integrator >> KERNEL_BITS;
+            self.integrator -= self.integrator >> BASS_SHIFT;
+            samples[i * stride] = sample.clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+        }
+
+        self.buffer.drain(0..available);
+        self.buffer.resize(self.buffer.len().max(WIDTH), 0);
+        self.offset -= (available as u64) << FRAC_BITS;
+    }
+}
+
+// The kernel and the rate are settings, only the pending output is saved
+impl Stateful for Resampler {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u64(self.offset);
+        state.write_u64(self.integrator as u64);
+        state.write_u32(self.buffer.len() as u32);
+        for value in &self.buffer {
+            state.write_u64(*value as u64);
+        }
+    }
+
+    // Between frames every sample has been read, only the tail of the last steps is pending
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        let offset: u64 = state.read_u64()?;
+        let integrator: i64 = state.read_u64()? as i64;
+        let len: usize = state.read_u32()? as usize;
+        if len > WIDTH * 2 || (offset >> FRAC_BITS) as usize > len {
+            return Err("Invalid resampler buffer in save state".into());
+        }
+        let mut buffer: Vec<i64> = Vec::new();
+        for _ in 0..len {
+            buffer.push(state.read_u64()? as i64);
+        }
+        let levels: RangeInclusive<i64> = -MAX_LEVEL..=MAX_LEVEL;
+        if !levels.contains(&integrator) || buffer.iter().any(|value| !levels.contains(value)) {
+            return Err("Invalid resampler level in save state".into());
+        }
+        buffer.resize(buffer.len().max(WIDTH), 0);
+        self.offset = offset;
+        self.integrator = integrator;
+        self.buffer = buffer;
+        Ok(())
+    }
+}
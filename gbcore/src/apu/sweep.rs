@@ -0,0 +1,125 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+pub enum SweepResult {
+    Update(u16),
+    Overflow,
+    NoOp,
+}
+
+pub struct Sweep {
+    register: u8,
+    shadow: u16,
+    timer: u8,
+    enabled: bool,
+    negate_used: bool,
+}
+
+impl Sweep {
+    pub fn new() -> Sweep {
+        Sweep {
+            register: 0,
+            shadow: 0,
+            timer: 0,
+            enabled: false,
+            negate_used: false,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    // Returns true if clearing the negate mode after a negate calculation
+    // has to disable the channel
+    pub fn write(&mut self, byte: u8) -> bool {
+        self.register = byte;
+        self.negate_used && !self.is_negate()
+    }
+
+    fn get_period(&self) -> u8 {
+        (self.register & 0x70) >> 4
+    }
+
+    fn is_negate(&self) -> bool {
+        (self.register & 0x08) != 0
+    }
+
+    fn get_shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = match self.get_period() {
+            0 => 8,
+            period => period,
+        };
+    }
+
+    fn calculate(&mut self) -> u16 {
+        let delta: u16 = self.shadow >> self.get_shift();
+        if self.is_negate() {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+
+    // Returns false if the overflow check disables the channel right away
+    pub fn trigger(&mut self, frequency: u16) -> bool {
+        self.shadow = frequency;
+        self.negate_used = false;
+        self.reload_timer();
+        self.enabled = self.get_period() != 0 || self.get_shift() != 0;
+        self.get_shift() == 0 || self.calculate() <= 0x7FF
+    }
+
+    pub fn clock(&mut self) -> SweepResult {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer != 0 {
+            return SweepResult::NoOp;
+        }
+
+        self.reload_timer();
+        if !self.enabled || self.get_period() == 0 {
+            return SweepResult::NoOp;
+        }
+
+        let frequency: u16 = self.calculate();
+        if frequency > 0x7FF {
+            SweepResult::Overflow
+        } else if self.get_shift() != 0 {
+            self.shadow = frequency;
+            if self.calculate() > 0x7FF {
+                SweepResult::Overflow
+            } else {
+                SweepResult::Update(frequency)
+            }
+        } else {
+            SweepResult::NoOp
+        }
+    }
+}
+
+impl Stateful for Sweep {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_u16(self.shadow);
+        state.write_u8(self.timer);
+        state.write_bool(self.enabled);
+        state.write_bool(self.negate_used);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.register = state.read_u8()?;
+        self.shadow = state.read_u16_max(0x7FF)?;
+        self.timer = state.read_u8_max(8)?;
+        self.enabled = state.read_bool()?;
+        self.negate_used = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::apu::length_counter::LengthCounter;
+use crate::apu::Channel;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+pub struct WaveChannel {
+    dac_enabled: bool,
+    length: LengthCounter,
+    volume_code: u8,
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample_buffer: u8,
+    enabled: bool,
+    wave_ram: [u8; 16],
+}
+
+impl WaveChannel {
+    pub fn new() -> WaveChannel {
+        WaveChannel {
+            dac_enabled: false,
+            length: LengthCounter::new(256),
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample_buffer: 0,
+            enabled: false,
+            wave_ram: [0; 16],
+        }
+    }
+
+    fn trigger(&mut self, frame_step: u8) {
+        self.enabled = self.dac_enabled;
+        self.timer = (2048 - self.frequency) * 2;
+        self.position = 0;
+        self.length.trigger(frame_step);
+    }
+
+    pub fn power_off(&mut self) {
+        let wave_ram: [u8; 16] = self.wave_ram;
+        let length: LengthCounter = std::mem::replace(&mut self.length, LengthCounter::new(256));
+        *self = WaveChannel::new();
+        self.wave_ram = wave_ram;
+        self.length = length;
+        self.length.reset();
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            0 => {
+                if self.dac_enabled {
+                    0xFF
+                } else {
+                    0x7F
+                }
+            }
+            1 => 0xFF,
+            2 => (self.volume_code << 5) | 0x9F,
+            3 => 0xFF,
+            4 => {
+                if self.length.is_enabled() {
+                    0xFF
+                } else {
+                    0xBF
+                }
+            }
+            _ => panic!("WAVE unsupported read from NR3{}", register),
+        }
+    }
+
+    pub fn write(&mut self, register: u8, byte: u8, frame_step: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = (byte & 0x80) != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(byte as u16),
+            2 => self.volume_code = (byte & 0x60) >> 5,
+            3 => self.frequency = (self.frequency & 0x700) | (byte as u16),
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | (((byte & 0x07) as u16) << 8);
+                if self.length.set_enabled((byte & 0x40) != 0, frame_step) {
+                    self.enabled = false;
+                }
+                if (byte & 0x80) != 0 {
+                    self.trigger(frame_step);
+                }
+            }
+            _ => panic!("WAVE unsupported write to NR3{}", register),
+        }
+    }
+
+    pub fn write_length(&mut self, byte: u8) {
+        self.length.load(byte as u16);
+    }
+
+    // While the channel is playing the CPU only sees the byte being read by the channel
+    pub fn read_wave_ram(&self, index: u8) -> u8 {
+        if self.enabled {
+            self.wave_ram[(self.position / 2) as usize]
+        } else {
+            self.wave_ram[index as usize]
+        }
+    }
+
+    pub fn write_wave_ram(&mut self, index: u8, byte: u8) {
+        if self.enabled {
+            self.wave_ram[(self.position / 2) as usize] = byte;
+        } else {
+            self.wave_ram[index as usize] = byte;
+        }
+    }
+}
+
+impl Channel for WaveChannel {
+    fn tick(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+        if self.timer == 0 {
+            self.timer = (2048 - self.frequency) * 2;
+            self.position = (self.position + 1) & 0x1F;
+            let byte: u8 = self.wave_ram[(self.position / 2) as usize];
+            self.sample_buffer = if (self.position & 0x1) == 0 {
+                byte >> 4
+            } else {
+                byte & 0x0F
+            };
+        }
+    }
+
+    fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn is_dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled {
+            match self.volume_code {
+                0 => 0,
+                code => self.sample_buffer >> (code - 1),
+            }
+        } else {
+            0
+        }
+    }
+}
+
+impl Stateful for WaveChannel {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.dac_enabled);
+        self.length.save_state(state);
+        state.write_u8(self.volume_code);
+        state.write_u16(self.frequency);
+        state.write_u16(self.timer);
+        state.write_u8(self.position);
+        state.write_u8(self.sample_buffer);
+        state.write_bool(self.enabled);
+        state.write_bytes(&self.wave_ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.dac_enabled = state.read_bool()?;
+        self.length.load_state(state)?;
+        self.volume_code = state.read_u8_max(3)?;
+        self.frequency = state.read_u16_max(0x7FF)?;
+        self.timer = state.read_u16_max(2048 * 2)?;
+        self.position = state.read_u8_max(0x1F)?;
+        self.sample_buffer = state.read_u8_max(0x0F)?;
+        self.enabled = state.read_bool()?;
+        state.read_bytes_into(&mut self.wave_ram)?;
+        Ok(())
+    }
+}
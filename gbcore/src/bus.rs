@@ -0,0 +1,97 @@
+use crate::cpu::Bus;
+use crate::mmu::address_spaces::Addressable;
+use crate::mmu::Mmu;
+use crate::ppu::{LcdBuffer, Ppu};
+
+// The bus seen by the CPU while running a frame, every M-cycle spent by the CPU
+// advances OAM DMA, the PPU, the timers, the serial port and the APU
+pub struct SystemBus<'a> {
+    pub mmu: &'a mut Mmu,
+    pub ppu: &'a mut Ppu,
+    pub buffer: &'a mut LcdBuffer,
+    pub unrestricted_access: bool,
+    // CPU cycles spent halted by VRAM DMA
+    pub stalled_cycles: u32,
+}
+
+impl SystemBus<'_> {
+    // VRAM and OAM are out of reach for the CPU while the PPU uses them
+    fn is_blocked(&self, location: u16) -> bool {
+        !self.unrestricted_access
+            && match location {
+                0x8000..=0x9FFF => self.ppu.is_vram_blocked(self.mmu),
+                0xFE00..=0xFE9F => self.ppu.is_oam_blocked(self.mmu),
+                _ => false,
+            }
+    }
+
+    fn step(&mut self) {
+        self.mmu.dma_tick();
+        let (dots, div): (u8, u8) = self.mmu.get_dots_and_div();
+        self.ppu.tick(self.mmu, self.buffer, dots);
+        if self.mmu.io.timers.tick(4) {
+            self.mmu.io.request_timer_interrupt();
+        }
+        if self.mmu.io.serial.tick(4) {
+            self.mmu.io.request_serial_interrupt();
+        }
+        self.mmu.io.apu.tick(dots, div);
+    }
+}
+
+impl Addressable for SystemBus<'_> {
+    fn write(&mut self, location: u16, byte: u8) {
+        if self.mmu.get_dma_conflict(location).is_none() && !self.is_blocked(location) {
+            self.mmu.write(location, byte);
+            // HBlank DMA started during HBlank copies its first block right away
+            if location == 0xFF55 && self.ppu.is_in_hblank(self.mmu) {
+                self.mmu.start_hblank();
+            }
+        }
+    }
+
+    fn read(&self, location: u16) -> u8 {
+        if let Some(byte) = self.mmu.get_dma_conflict(location) {
+            byte
+        } else if self.is_blocked(location) {
+            0xFF
+        } else {
+            self.mmu.read(location)
+        }
+    }
+}
+
+impl Bus for SystemBus<'_> {
+    fn get_ie(&self) -> u8 {
+        self.mmu.ie_flag
+    }
+
+    fn get_if(&self) -> u8 {
+        self.mmu.io.if_flag
+    }
+
+    fn set_if(&mut self, value: u8) {
+        self.mmu.io.if_flag = value;
+    }
+
+    // The CPU is halted while VRAM DMA copies a block, for 32 dots in both speeds
+    fn cycle(&mut self) {
+        self.step();
+        while self.mmu.hdma_tick() {
+            let stall: u32 = if self.mmu.is_double_speed() { 16 } else { 8 };
+            for _ in 0..stall {
+                self.step();
+            }
+            self.stalled_cycles += stall * 4;
+        }
+    }
+
+    fn stop(&mut self) -> bool {
+        self.mmu.io.timers.reset_div();
+        self.mmu.switch_speed()
+    }
+
+    fn is_joypad_low(&self) -> bool {
+        (self.mmu.io.joypad.read(0xFF00) & 0x0F) != 0x0F
+    }
+}
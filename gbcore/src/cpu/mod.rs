@@ -1,7 +1,24 @@
-use crate::mmu::{address_spaces::Addressable, Mmu};
+use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
 use registers::{Flag, Register16, Register8, Registers};
-
-mod registers;
+use std::error::Error;
+
+pub mod registers;
+
+// Everything the CPU needs from the memory bus, the interrupt registers are
+// separate so that reading them doesn't show up as a memory access
+pub trait Bus: Addressable {
+    fn get_ie(&self) -> u8;
+    fn get_if(&self) -> u8;
+    fn set_if(&mut self, value: u8);
+    // Advances everything but the CPU by one M-cycle
+    fn cycle(&mut self);
+    // Called when executing STOP, resets DIV. Returns true when it switched the CPU
+    // speed instead of entering STOP mode
+    fn stop(&mut self) -> bool;
+    // Whether a selected joypad line is low, which ends STOP mode
+    fn is_joypad_low(&self) -> bool;
+}
 
 const OP_CYCLES: &'static [u8] = &[
     4, 12, 8, 8, 4, 4, 8, 4, 20, 8, 8, 8, 4, 4, 8, 4, 4, 12, 8, 8, 4, 4, 8, 4, 12, 8, 8, 8, 4, 4,
@@ -46,6 +63,12 @@ pub struct Cpu {
     pub ops: u128,
     ime: Ime,
     halted: bool,
+    halt_bug: bool,
+    stopped: bool,
+    // Cycles of the current instruction already run on the bus
+    synced: u8,
+    trap: Option<u8>,
+    trapped: bool,
 }
 
 impl Cpu {
@@ -56,123 +79,205 @@ impl Cpu {
             ops: 0,
             ime: Ime::Disabled,
             halted: false,
+            halt_bug: false,
+            stopped: false,
+            synced: 0,
+            trap: None,
+            trapped: false,
         }
     }
 
-    pub fn tick(&mut self, mmu: &mut Mmu) -> u8 {
+    // The registers as left by the Color boot ROM
+    pub fn new_cgb() -> Cpu {
+        let mut cpu: Cpu = Cpu::new();
+        cpu.reg = Registers::new_cgb();
+        cpu
+    }
+
+    pub fn get_registers(&self) -> &Registers {
+        &self.reg
+    }
+
+    pub fn get_registers_mut(&mut self) -> &mut Registers {
+        &mut self.reg
+    }
+
+    // A pending EI counts as enabled, it takes effect before the next instruction
+    pub fn get_ime(&self) -> bool {
+        self.ime != Ime::Disabled
+    }
+
+    pub fn set_ime(&mut self, enabled: bool) {
+        self.ime = if enabled { Ime::Enabled } else { Ime::Disabled };
+    }
+
+    // Stops execution after running the given opcode, used by test ROMs as a breakpoint
+    pub fn set_trap(&mut self, opcode: Option<u8>) {
+        self.trap = opcode;
+        self.trapped = false;
+    }
+
+    // In STOP mode the main clock is stopped, only a joypad press resumes execution
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn is_trapped(&self) -> bool {
+        self.trapped
+    }
+
+    pub fn clear_trapped(&mut self) {
+        self.trapped = false;
+    }
+
+    pub fn tick<B: Bus>(&mut self, bus: &mut B) -> u8 {
         let start: u128 = self.cycles;
 
-        if self.ime == Ime::Pending {
-            self.ime = Ime::Enabled;
+        if self.stopped {
+            if !bus.is_joypad_low() {
+                self.step_cycles(4);
+                return 4;
+            }
+            self.stopped = false;
         }
 
-        if !self.halted {
-            self.check_interrupts(mmu);
-            let op: u8 = self.consume_u8(mmu);
-            self.decode(mmu, op);
-        } else {
-            if (mmu.ie_flag & mmu.io.if_flag) != 0 {
+        // Leaving HALT takes a cycle, a pending interrupt is dispatched on the next tick
+        if self.halted {
+            if self.is_interrupt_pending(bus) {
                 self.halted = false;
             }
-            self.step_cycles(8);
+            self.step_cycles(4);
+            self.cycle(bus);
+            self.synced = 0;
+            return 4;
+        }
+
+        let ime_pending: bool = self.ime == Ime::Pending;
+        self.check_interrupts(bus);
+        let op: u8 = self.consume_u8(bus);
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.reg.pc = self.reg.pc.wrapping_sub(1);
         }
+        self.decode(bus, op);
+        if self.trap == Some(op) {
+            self.trapped = true;
+        }
+
+        // EI takes effect after the instruction following it
+        if ime_pending && self.ime == Ime::Pending {
+            self.ime = Ime::Enabled;
+        }
+
+        // Internal cycles at the end of the instruction aren't run by the instruction itself
+        while (self.synced as u128) < self.cycles - start {
+            self.cycle(bus);
+        }
+        self.cycles = start + self.synced as u128;
+        let cycles: u8 = self.synced;
+        self.synced = 0;
+        cycles
+    }
 
-        (self.cycles - start) as u8
+    fn is_interrupt_pending<B: Bus>(&self, bus: &B) -> bool {
+        (bus.get_ie() & bus.get_if() & 0x1F) != 0
     }
 
-    fn check_interrupts(&mut self, mmu: &mut Mmu) {
-        let ie_f: u8 = mmu.ie_flag;
-        let if_f: u8 = mmu.io.if_flag;
+    fn check_interrupts<B: Bus>(&mut self, bus: &mut B) {
+        let ie_f: u8 = bus.get_ie();
+        let if_f: u8 = bus.get_if();
 
         if self.ime == Ime::Enabled {
             if ((ie_f & 0x01) & (if_f & 0x01)) != 0 {
-                self.handle_interrupt(mmu, 0x0040, 0x01);
+                self.handle_interrupt(bus, 0x0040, 0x01);
             } else if ((ie_f & 0x02) & (if_f & 0x02)) != 0 {
-                self.handle_interrupt(mmu, 0x0048, 0x02);
+                self.handle_interrupt(bus, 0x0048, 0x02);
             } else if ((ie_f & 0x04) & (if_f & 0x04)) != 0 {
-                self.handle_interrupt(mmu, 0x0050, 0x04);
+                self.handle_interrupt(bus, 0x0050, 0x04);
             } else if ((ie_f & 0x08) & (if_f & 0x08)) != 0 {
-                self.handle_interrupt(mmu, 0x0058, 0x08);
+                self.handle_interrupt(bus, 0x0058, 0x08);
             } else if ((ie_f & 0x10) & (if_f & 0x10)) != 0 {
-                self.handle_interrupt(mmu, 0x0060, 0x10);
+                self.handle_interrupt(bus, 0x0060, 0x10);
             }
         }
     }
 
-    fn handle_interrupt(&mut self, mmu: &mut Mmu, int: u16, bit: u8) {
-        self.stack_push_u16(mmu, self.reg.pc);
-        mmu.io.if_flag ^= bit;
+    fn handle_interrupt<B: Bus>(&mut self, bus: &mut B, int: u16, bit: u8) {
+        self.cycle(bus);
+        self.stack_push_u16(bus, self.reg.pc);
+        bus.set_if(bus.get_if() ^ bit);
         self.reg.pc = int;
         self.ime = Ime::Disabled;
+        self.cycle(bus);
         self.step_cycles(20);
     }
 
-    fn decode(&mut self, mmu: &mut Mmu, op: u8) {
+    fn decode<B: Bus>(&mut self, bus: &mut B, op: u8) {
         //println!("Executing {:#01x}", op);
         match op {
             0x00 => self.nop(),
-            0x01 => self.ld_r16_u16(mmu, Register16::BC),
-            0x02 => self.ld_ir16_r8(mmu, Register16::BC, Register8::A, PostOp::None),
+            0x01 => self.ld_r16_u16(bus, Register16::BC),
+            0x02 => self.ld_ir16_r8(bus, Register16::BC, Register8::A, PostOp::None),
             0x03 => self.inc_r16(Register16::BC),
             0x04 => self.inc_r8(Register8::B),
             0x05 => self.dec_r8(Register8::B),
-            0x06 => self.ld_r8_u8(mmu, Register8::B),
+            0x06 => self.ld_r8_u8(bus, Register8::B),
             0x07 => self.rlca(),
-            0x08 => self.ld_iu16_r16(mmu, Register16::SP),
+            0x08 => self.ld_iu16_r16(bus, Register16::SP),
             0x09 => self.add_r16_r16(Register16::HL, Register16::BC),
-            0x0A => self.ld_r8_ir16(mmu, Register8::A, Register16::BC, PostOp::None),
+            0x0A => self.ld_r8_ir16(bus, Register8::A, Register16::BC, PostOp::None),
             0x0B => self.dec_r16(Register16::BC),
             0x0C => self.inc_r8(Register8::C),
             0x0D => self.dec_r8(Register8::C),
-            0x0E => self.ld_r8_u8(mmu, Register8::C),
+            0x0E => self.ld_r8_u8(bus, Register8::C),
             0x0F => self.rrca(),
-            0x10 => self.stop(),
-            0x11 => self.ld_r16_u16(mmu, Register16::DE),
-            0x12 => self.ld_ir16_r8(mmu, Register16::DE, Register8::A, PostOp::None),
+            0x10 => self.stop(bus),
+            0x11 => self.ld_r16_u16(bus, Register16::DE),
+            0x12 => self.ld_ir16_r8(bus, Register16::DE, Register8::A, PostOp::None),
             0x13 => self.inc_r16(Register16::DE),
             0x14 => self.inc_r8(Register8::D),
             0x15 => self.dec_r8(Register8::D),
-            0x16 => self.ld_r8_u8(mmu, Register8::D),
+            0x16 => self.ld_r8_u8(bus, Register8::D),
             0x17 => self.rla(),
-            0x18 => self.jr_i8(mmu),
+            0x18 => self.jr_i8(bus),
             0x19 => self.add_r16_r16(Register16::HL, Register16::DE),
-            0x1A => self.ld_r8_ir16(mmu, Register8::A, Register16::DE, PostOp::None),
+            0x1A => self.ld_r8_ir16(bus, Register8::A, Register16::DE, PostOp::None),
             0x1B => self.dec_r16(Register16::DE),
             0x1C => self.inc_r8(Register8::E),
             0x1D => self.dec_r8(Register8::E),
-            0x1E => self.ld_r8_u8(mmu, Register8::E),
+            0x1E => self.ld_r8_u8(bus, Register8::E),
             0x1F => self.rra(),
-            0x20 => self.jr_f_i8(mmu, Flag::NZ),
-            0x21 => self.ld_r16_u16(mmu, Register16::HL),
-            0x22 => self.ld_ir16_r8(mmu, Register16::HL, Register8::A, PostOp::Inc),
+            0x20 => self.jr_f_i8(bus, Flag::NZ),
+            0x21 => self.ld_r16_u16(bus, Register16::HL),
+            0x22 => self.ld_ir16_r8(bus, Register16::HL, Register8::A, PostOp::Inc),
             0x23 => self.inc_r16(Register16::HL),
             0x24 => self.inc_r8(Register8::H),
             0x25 => self.dec_r8(Register8::H),
-            0x26 => self.ld_r8_u8(mmu, Register8::H),
+            0x26 => self.ld_r8_u8(bus, Register8::H),
             0x27 => self.daa(),
-            0x28 => self.jr_f_i8(mmu, Flag::Z),
+            0x28 => self.jr_f_i8(bus, Flag::Z),
             0x29 => self.add_r16_r16(Register16::HL, Register16::HL),
-            0x2A => self.ld_r8_ir16(mmu, Register8::A, Register16::HL, PostOp::Inc),
+            0x2A => self.ld_r8_ir16(bus, Register8::A, Register16::HL, PostOp::Inc),
             0x2B => self.dec_r16(Register16::HL),
             0x2C => self.inc_r8(Register8::L),
             0x2D => self.dec_r8(Register8::L),
-            0x2E => self.ld_r8_u8(mmu, Register8::L),
+            0x2E => self.ld_r8_u8(bus, Register8::L),
             0x2F => self.cpl(),
-            0x30 => self.jr_f_i8(mmu, Flag::NC),
-            0x31 => self.ld_r16_u16(mmu, Register16::SP),
-            0x32 => self.ld_ir16_r8(mmu, Register16::HL, Register8::A, PostOp::Dec),
+            0x30 => self.jr_f_i8(bus, Flag::NC),
+            0x31 => self.ld_r16_u16(bus, Register16::SP),
+            0x32 => self.ld_ir16_r8(bus, Register16::HL, Register8::A, PostOp::Dec),
             0x33 => self.inc_r16(Register16::SP),
-            0x34 => self.inc_ir16(mmu, Register16::HL),
-            0x35 => self.dec_ir16(mmu, Register16::HL),
-            0x36 => self.ld_ir16_u8(mmu, Register16::HL),
+            0x34 => self.inc_ir16(bus, Register16::HL),
+            0x35 => self.dec_ir16(bus, Register16::HL),
+            0x36 => self.ld_ir16_u8(bus, Register16::HL),
             0x37 => self.scf(),
-            0x38 => self.jr_f_i8(mmu, Flag::C),
+            0x38 => self.jr_f_i8(bus, Flag::C),
             0x39 => self.add_r16_r16(Register16::HL, Register16::SP),
-            0x3A => self.ld_r8_ir16(mmu, Register8::A, Register16::HL, PostOp::Dec),
+            0x3A => self.ld_r8_ir16(bus, Register8::A, Register16::HL, PostOp::Dec),
             0x3B => self.dec_r16(Register16::SP),
             0x3C => self.inc_r8(Register8::A),
             0x3D => self.dec_r8(Register8::A),
-            0x3E => self.ld_r8_u8(mmu, Register8::A),
+            0x3E => self.ld_r8_u8(bus, Register8::A),
             0x3F => self.ccf(),
             0x40 => self.ld_r8_r8(Register8::B, Register8::B),
             0x41 => self.ld_r8_r8(Register8::B, Register8::C),
@@ -180,7 +285,7 @@ impl Cpu {
             0x43 => self.ld_r8_r8(Register8::B, Register8::E),
             0x44 => self.ld_r8_r8(Register8::B, Register8::H),
             0x45 => self.ld_r8_r8(Register8::B, Register8::L),
-            0x46 => self.ld_r8_ir16(mmu, Register8::B, Register16::HL, PostOp::None),
+            0x46 => self.ld_r8_ir16(bus, Register8::B, Register16::HL, PostOp::None),
             0x47 => self.ld_r8_r8(Register8::B, Register8::A),
             0x48 => self.ld_r8_r8(Register8::C, Register8::B),
             0x49 => self.ld_r8_r8(Register8::C, Register8::C),
@@ -188,7 +293,7 @@ impl Cpu {
             0x4B => self.ld_r8_r8(Register8::C, Register8::E),
             0x4C => self.ld_r8_r8(Register8::C, Register8::H),
             0x4D => self.ld_r8_r8(Register8::C, Register8::L),
-            0x4E => self.ld_r8_ir16(mmu, Register8::C, Register16::HL, PostOp::None),
+            0x4E => self.ld_r8_ir16(bus, Register8::C, Register16::HL, PostOp::None),
             0x4F => self.ld_r8_r8(Register8::C, Register8::A),
             0x50 => self.ld_r8_r8(Register8::D, Register8::B),
             0x51 => self.ld_r8_r8(Register8::D, Register8::C),
@@ -196,7 +301,7 @@ impl Cpu {
             0x53 => self.ld_r8_r8(Register8::D, Register8::E),
             0x54 => self.ld_r8_r8(Register8::D, Register8::H),
             0x55 => self.ld_r8_r8(Register8::D, Register8::L),
-            0x56 => self.ld_r8_ir16(mmu, Register8::D, Register16::HL, PostOp::None),
+            0x56 => self.ld_r8_ir16(bus, Register8::D, Register16::HL, PostOp::None),
             0x57 => self.ld_r8_r8(Register8::D, Register8::A),
             0x58 => self.ld_r8_r8(Register8::E, Register8::B),
             0x59 => self.ld_r8_r8(Register8::E, Register8::C),
@@ -204,7 +309,7 @@ impl Cpu {
             0x5B => self.ld_r8_r8(Register8::E, Register8::E),
             0x5C => self.ld_r8_r8(Register8::E, Register8::H),
             0x5D => self.ld_r8_r8(Register8::E, Register8::L),
-            0x5E => self.ld_r8_ir16(mmu, Register8::E, Register16::HL, PostOp::None),
+            0x5E => self.ld_r8_ir16(bus, Register8::E, Register16::HL, PostOp::None),
             0x5F => self.ld_r8_r8(Register8::E, Register8::A),
             0x60 => self.ld_r8_r8(Register8::H, Register8::B),
             0x61 => self.ld_r8_r8(Register8::H, Register8::C),
@@ -212,7 +317,7 @@ impl Cpu {
             0x63 => self.ld_r8_r8(Register8::H, Register8::E),
             0x64 => self.ld_r8_r8(Register8::H, Register8::H),
             0x65 => self.ld_r8_r8(Register8::H, Register8::L),
-            0x66 => self.ld_r8_ir16(mmu, Register8::H, Register16::HL, PostOp::None),
+            0x66 => self.ld_r8_ir16(bus, Register8::H, Register16::HL, PostOp::None),
             0x67 => self.ld_r8_r8(Register8::H, Register8::A),
             0x68 => self.ld_r8_r8(Register8::L, Register8::B),
             0x69 => self.ld_r8_r8(Register8::L, Register8::C),
@@ -220,23 +325,23 @@ impl Cpu {
             0x6B => self.ld_r8_r8(Register8::L, Register8::E),
             0x6C => self.ld_r8_r8(Register8::L, Register8::H),
             0x6D => self.ld_r8_r8(Register8::L, Register8::L),
-            0x6E => self.ld_r8_ir16(mmu, Register8::L, Register16::HL, PostOp::None),
+            0x6E => self.ld_r8_ir16(bus, Register8::L, Register16::HL, PostOp::None),
             0x6F => self.ld_r8_r8(Register8::L, Register8::A),
-            0x70 => self.ld_ir16_r8(mmu, Register16::HL, Register8::B, PostOp::None),
-            0x71 => self.ld_ir16_r8(mmu, Register16::HL, Register8::C, PostOp::None),
-            0x72 => self.ld_ir16_r8(mmu, Register16::HL, Register8::D, PostOp::None),
-            0x73 => self.ld_ir16_r8(mmu, Register16::HL, Register8::E, PostOp::None),
-            0x74 => self.ld_ir16_r8(mmu, Register16::HL, Register8::H, PostOp::None),
-            0x75 => self.ld_ir16_r8(mmu, Register16::HL, Register8::L, PostOp::None),
-            0x76 => self.halt(),
-            0x77 => self.ld_ir16_r8(mmu, Register16::HL, Register8::A, PostOp::None),
+            0x70 => self.ld_ir16_r8(bus, Register16::HL, Register8::B, PostOp::None),
+            0x71 => self.ld_ir16_r8(bus, Register16::HL, Register8::C, PostOp::None),
+            0x72 => self.ld_ir16_r8(bus, Register16::HL, Register8::D, PostOp::None),
+            0x73 => self.ld_ir16_r8(bus, Register16::HL, Register8::E, PostOp::None),
+            0x74 => self.ld_ir16_r8(bus, Register16::HL, Register8::H, PostOp::None),
+            0x75 => self.ld_ir16_r8(bus, Register16::HL, Register8::L, PostOp::None),
+            0x76 => self.halt(bus),
+            0x77 => self.ld_ir16_r8(bus, Register16::HL, Register8::A, PostOp::None),
             0x78 => self.ld_r8_r8(Register8::A, Register8::B),
             0x79 => self.ld_r8_r8(Register8::A, Register8::C),
             0x7A => self.ld_r8_r8(Register8::A, Register8::D),
             0x7B => self.ld_r8_r8(Register8::A, Register8::E),
             0x7C => self.ld_r8_r8(Register8::A, Register8::H),
             0x7D => self.ld_r8_r8(Register8::A, Register8::L),
-            0x7E => self.ld_r8_ir16(mmu, Register8::A, Register16::HL, PostOp::None),
+            0x7E => self.ld_r8_ir16(bus, Register8::A, Register16::HL, PostOp::None),
             0x7F => self.ld_r8_r8(Register8::A, Register8::A),
             0x80 => self.add_r8(Register8::B),
             0x81 => self.add_r8(Register8::C),
@@ -244,7 +349,7 @@ impl Cpu {
             0x83 => self.add_r8(Register8::E),
             0x84 => self.add_r8(Register8::H),
             0x85 => self.add_r8(Register8::L),
-            0x86 => self.add_ir16(mmu, Register16::HL),
+            0x86 => self.add_ir16(bus, Register16::HL),
             0x87 => self.add_r8(Register8::A),
             0x88 => self.adc_r8(Register8::B),
             0x89 => self.adc_r8(Register8::C),
@@ -252,7 +357,7 @@ impl Cpu {
             0x8B => self.adc_r8(Register8::E),
             0x8C => self.adc_r8(Register8::H),
             0x8D => self.adc_r8(Register8::L),
-            0x8E => self.adc_ir16(mmu, Register16::HL),
+            0x8E => self.adc_ir16(bus, Register16::HL),
             0x8F => self.adc_r8(Register8::A),
             0x90 => self.sub_r8(Register8::B),
             0x91 => self.sub_r8(Register8::C),
@@ -260,7 +365,7 @@ impl Cpu {
             0x93 => self.sub_r8(Register8::E),
             0x94 => self.sub_r8(Register8::H),
             0x95 => self.sub_r8(Register8::L),
-            0x96 => self.sub_ir16(mmu, Register16::HL),
+            0x96 => self.sub_ir16(bus, Register16::HL),
             0x97 => self.sub_r8(Register8::A),
             0x98 => self.sbc_r8(Register8::B),
             0x99 => self.sbc_r8(Register8::C),
@@ -268,7 +373,7 @@ impl Cpu {
             0x9B => self.sbc_r8(Register8::E),
             0x9C => self.sbc_r8(Register8::H),
             0x9D => self.sbc_r8(Register8::L),
-            0x9E => self.sbc_ir16(mmu, Register16::HL),
+            0x9E => self.sbc_ir16(bus, Register16::HL),
             0x9F => self.sbc_r8(Register8::A),
             0xA0 => self.and_r8(Register8::B),
             0xA1 => self.and_r8(Register8::C),
@@ -276,7 +381,7 @@ impl Cpu {
             0xA3 => self.and_r8(Register8::E),
             0xA4 => self.and_r8(Register8::H),
             0xA5 => self.and_r8(Register8::L),
-            0xA6 => self.and_ir16(mmu, Register16::HL),
+            0xA6 => self.and_ir16(bus, Register16::HL),
             0xA7 => self.and_r8(Register8::A),
             0xA8 => self.xor_r8(Register8::B),
             0xA9 => self.xor_r8(Register8::C),
@@ -284,7 +389,7 @@ impl Cpu {
             0xAB => self.xor_r8(Register8::E),
             0xAC => self.xor_r8(Register8::H),
             0xAD => self.xor_r8(Register8::L),
-            0xAE => self.xor_ir16(mmu, Register16::HL),
+            0xAE => self.xor_ir16(bus, Register16::HL),
             0xAF => self.xor_r8(Register8::A),
             0xB0 => self.or_r8(Register8::B),
             0xB1 => self.or_r8(Register8::C),
@@ -292,7 +397,7 @@ impl Cpu {
             0xB3 => self.or_r8(Register8::E),
             0xB4 => self.or_r8(Register8::H),
             0xB5 => self.or_r8(Register8::L),
-            0xB6 => self.or_ir16(mmu, Register16::HL),
+            0xB6 => self.or_ir16(bus, Register16::HL),
             0xB7 => self.or_r8(Register8::A),
             0xB8 => self.cp_r8(Register8::B),
             0xB9 => self.cp_r8(Register8::C),
@@ -300,81 +405,81 @@ impl Cpu {
             0xBB => self.cp_r8(Register8::E),
             0xBC => self.cp_r8(Register8::H),
             0xBD => self.cp_r8(Register8::L),
-            0xBE => self.cp_ir16(mmu, Register16::HL),
+            0xBE => self.cp_ir16(bus, Register16::HL),
             0xBF => self.cp_r8(Register8::A),
-            0xC0 => self.ret_f(mmu, Flag::NZ),
-            0xC1 => self.pop_r16(mmu, Register16::BC),
-            0xC2 => self.jp_f_u16(mmu, Flag::NZ),
-            0xC3 => self.jp_u16(mmu),
-            0xC4 => self.call_f_u16(mmu, Flag::NZ),
-            0xC5 => self.push_r16(mmu, Register16::BC),
-            0xC6 => self.add_u8(mmu),
-            0xC7 => self.rst_f8(mmu, 0x00),
-            0xC8 => self.ret_f(mmu, Flag::Z),
-            0xC9 => self.ret(mmu),
-            0xCA => self.jp_f_u16(mmu, Flag::Z),
+            0xC0 => self.ret_f(bus, Flag::NZ),
+            0xC1 => self.pop_r16(bus, Register16::BC),
+            0xC2 => self.jp_f_u16(bus, Flag::NZ),
+            0xC3 => self.jp_u16(bus),
+            0xC4 => self.call_f_u16(bus, Flag::NZ),
+            0xC5 => self.push_r16(bus, Register16::BC),
+            0xC6 => self.add_u8(bus),
+            0xC7 => self.rst_f8(bus, 0x00),
+            0xC8 => self.ret_f(bus, Flag::Z),
+            0xC9 => self.ret(bus),
+            0xCA => self.jp_f_u16(bus, Flag::Z),
             0xCB => {
-                let op: u8 = self.consume_u8(mmu);
-                self.decode_cb(mmu, op);
+                let op: u8 = self.consume_u8(bus);
+                self.decode_cb(bus, op);
             }
-            0xCC => self.call_f_u16(mmu, Flag::Z),
-            0xCD => self.call_u16(mmu),
-            0xCE => self.adc_u8(mmu),
-            0xCF => self.rst_f8(mmu, 0x08),
-            0xD0 => self.ret_f(mmu, Flag::NC),
-            0xD1 => self.pop_r16(mmu, Register16::DE),
-            0xD2 => self.jp_f_u16(mmu, Flag::NC),
+            0xCC => self.call_f_u16(bus, Flag::Z),
+            0xCD => self.call_u16(bus),
+            0xCE => self.adc_u8(bus),
+            0xCF => self.rst_f8(bus, 0x08),
+            0xD0 => self.ret_f(bus, Flag::NC),
+            0xD1 => self.pop_r16(bus, Register16::DE),
+            0xD2 => self.jp_f_u16(bus, Flag::NC),
             0xD3 => self.nop(),
-            0xD4 => self.call_f_u16(mmu, Flag::NC),
-            0xD5 => self.push_r16(mmu, Register16::DE),
-            0xD6 => self.sub_u8(mmu),
-            0xD7 => self.rst_f8(mmu, 0x10),
-            0xD8 => self.ret_f(mmu, Flag::C),
-            0xD9 => self.reti(mmu),
-            0xDA => self.jp_f_u16(mmu, Flag::C),
+            0xD4 => self.call_f_u16(bus, Flag::NC),
+            0xD5 => self.push_r16(bus, Register16::DE),
+            0xD6 => self.sub_u8(bus),
+            0xD7 => self.rst_f8(bus, 0x10),
+            0xD8 => self.ret_f(bus, Flag::C),
+            0xD9 => self.reti(bus),
+            0xDA => self.jp_f_u16(bus, Flag::C),
             0xDB => self.nop(),
-            0xDC => self.call_f_u16(mmu, Flag::C),
+            0xDC => self.call_f_u16(bus, Flag::C),
             0xDD => self.nop(),
-            0xDE => self.sbc_u8(mmu),
-            0xDF => self.rst_f8(mmu, 0x18),
-            0xE0 => self.ldh_iu8_r8(mmu, Register8::A),
-            0xE1 => self.pop_r16(mmu, Register16::HL),
-            0xE2 => self.ld_ir8_r8(mmu, Register8::C, Register8::A),
+            0xDE => self.sbc_u8(bus),
+            0xDF => self.rst_f8(bus, 0x18),
+            0xE0 => self.ldh_iu8_r8(bus, Register8::A),
+            0xE1 => self.pop_r16(bus, Register16::HL),
+            0xE2 => self.ld_ir8_r8(bus, Register8::C, Register8::A),
             0xE3 => self.nop(),
             0xE4 => self.nop(),
-            0xE5 => self.push_r16(mmu, Register16::HL),
-            0xE6 => self.and_u8(mmu),
-            0xE7 => self.rst_f8(mmu, 0x20),
-            0xE8 => self.add_r16_i8(mmu, Register16::SP),
+            0xE5 => self.push_r16(bus, Register16::HL),
+            0xE6 => self.and_u8(bus),
+            0xE7 => self.rst_f8(bus, 0x20),
+            0xE8 => self.add_r16_i8(bus, Register16::SP),
             0xE9 => self.jp_r16(Register16::HL),
-            0xEA => self.ld_iu16_r8(mmu, Register8::A),
+            0xEA => self.ld_iu16_r8(bus, Register8::A),
             0xEB => self.nop(),
             0xEC => self.nop(),
             0xED => self.nop(),
-            0xEE => self.xor_u8(mmu),
-            0xEF => self.rst_f8(mmu, 0x28),
-            0xF0 => self.ldh_r8_iu8(mmu, Register8::A),
-            0xF1 => self.pop_r16(mmu, Register16::AF),
-            0xF2 => self.ld_r8_ir8(mmu, Register8::A, Register8::C),
+            0xEE => self.xor_u8(bus),
+            0xEF => self.rst_f8(bus, 0x28),
+            0xF0 => self.ldh_r8_iu8(bus, Register8::A),
+            0xF1 => self.pop_r16(bus, Register16::AF),
+            0xF2 => self.ld_r8_ir8(bus, Register8::A, Register8::C),
             0xF3 => self.di(),
             0xF4 => self.nop(),
-            0xF5 => self.push_r16(mmu, Register16::AF),
-            0xF6 => self.or_u8(mmu),
-            0xF7 => self.rst_f8(mmu, 0x30),
-            0xF8 => self.ld_r16_r16_i8(mmu, Register16::HL, Register16::SP),
+            0xF5 => self.push_r16(bus, Register16::AF),
+            0xF6 => self.or_u8(bus),
+            0xF7 => self.rst_f8(bus, 0x30),
+            0xF8 => self.ld_r16_r16_i8(bus, Register16::HL, Register16::SP),
             0xF9 => self.ld_r16_r16(Register16::SP, Register16::HL),
-            0xFA => self.ld_r8_iu16(mmu, Register8::A),
+            0xFA => self.ld_r8_iu16(bus, Register8::A),
             0xFB => self.ei(),
             0xFC => self.nop(),
             0xFD => self.nop(),
-            0xFE => self.cp_u8(mmu),
-            0xFF => self.rst_f8(mmu, 0x38),
+            0xFE => self.cp_u8(bus),
+            0xFF => self.rst_f8(bus, 0x38),
         }
         self.step_cycles(OP_CYCLES[op as usize]);
         self.ops += 1;
     }
 
-    fn decode_cb(&mut self, mmu: &mut Mmu, op: u8) {
+    fn decode_cb<B: Bus>(&mut self, bus: &mut B, op: u8) {
         match op {
             0x00 => self.rlc_r8(Register8::B),
             0x01 => self.rlc_r8(Register8::C),
@@ -382,7 +487,7 @@ impl Cpu {
             0x03 => self.rlc_r8(Register8::E),
             0x04 => self.rlc_r8(Register8::H),
             0x05 => self.rlc_r8(Register8::L),
-            0x06 => self.rlc_ir16(mmu, Register16::HL),
+            0x06 => self.rlc_ir16(bus, Register16::HL),
             0x07 => self.rlc_r8(Register8::A),
             0x08 => self.rrc_r8(Register8::B),
             0x09 => self.rrc_r8(Register8::C),
@@ -390,7 +495,7 @@ impl Cpu {
             0x0B => self.rrc_r8(Register8::E),
             0x0C => self.rrc_r8(Register8::H),
             0x0D => self.rrc_r8(Register8::L),
-            0x0E => self.rrc_ir16(mmu, Register16::HL),
+            0x0E => self.rrc_ir16(bus, Register16::HL),
             0x0F => self.rrc_r8(Register8::A),
             0x10 => self.rl_r8(Register8::B),
             0x11 => self.rl_r8(Register8::C),
@@ -398,7 +503,7 @@ impl Cpu {
             0x13 => self.rl_r8(Register8::E),
             0x14 => self.rl_r8(Register8::H),
             0x15 => self.rl_r8(Register8::L),
-            0x16 => self.rl_ir16(mmu, Register16::HL),
+            0x16 => self.rl_ir16(bus, Register16::HL),
             0x17 => self.rl_r8(Register8::A),
             0x18 => self.rr_r8(Register8::B),
             0x19 => self.rr_r8(Register8::C),
@@ -406,7 +511,7 @@ impl Cpu {
             0x1B => self.rr_r8(Register8::E),
             0x1C => self.rr_r8(Register8::H),
             0x1D => self.rr_r8(Register8::L),
-            0x1E => self.rr_ir16(mmu, Register16::HL),
+            0x1E => self.rr_ir16(bus, Register16::HL),
             0x1F => self.rr_r8(Register8::A),
             0x20 => self.sla_r8(Register8::B),
             0x21 => self.sla_r8(Register8::C),
@@ -414,7 +519,7 @@ impl Cpu {
             0x23 => self.sla_r8(Register8::E),
             0x24 => self.sla_r8(Register8::H),
             0x25 => self.sla_r8(Register8::L),
-            0x26 => self.sla_ir16(mmu, Register16::HL),
+            0x26 => self.sla_ir16(bus, Register16::HL),
             0x27 => self.sla_r8(Register8::A),
             0x28 => self.sra_r8(Register8::B),
             0x29 => self.sra_r8(Register8::C),
@@ -422,7 +527,7 @@ impl Cpu {
             0x2B => self.sra_r8(Register8::E),
             0x2C => self.sra_r8(Register8::H),
             0x2D => self.sra_r8(Register8::L),
-            0x2E => self.sra_ir16(mmu, Register16::HL),
+            0x2E => self.sra_ir16(bus, Register16::HL),
             0x2F => self.sra_r8(Register8::A),
             0x30 => self.swap_r8(Register8::B),
             0x31 => self.swap_r8(Register8::C),
@@ -430,7 +535,7 @@ impl Cpu {
             0x33 => self.swap_r8(Register8::E),
             0x34 => self.swap_r8(Register8::H),
             0x35 => self.swap_r8(Register8::L),
-            0x36 => self.swap_ir16(mmu, Register16::HL),
+            0x36 => self.swap_ir16(bus, Register16::HL),
             0x37 => self.swap_r8(Register8::A),
             0x38 => self.srl_r8(Register8::B),
             0x39 => self.srl_r8(Register8::C),
@@ -438,7 +543,7 @@ impl Cpu {
             0x3B => self.srl_r8(Register8::E),
             0x3C => self.srl_r8(Register8::H),
             0x3D => self.srl_r8(Register8::L),
-            0x3E => self.srl_ir16(mmu, Register16::HL),
+            0x3E => self.srl_ir16(bus, Register16::HL),
             0x3F => self.srl_r8(Register8::A),
             0x40 => self.bit_b_r8(0, Register8::B),
             0x41 => self.bit_b_r8(0, Register8::C),
@@ -446,7 +551,7 @@ impl Cpu {
             0x43 => self.bit_b_r8(0, Register8::E),
             0x44 => self.bit_b_r8(0, Register8::H),
             0x45 => self.bit_b_r8(0, Register8::L),
-            0x46 => self.bit_b_ir16(mmu, 0, Register16::HL),
+            0x46 => self.bit_b_ir16(bus, 0, Register16::HL),
             0x47 => self.bit_b_r8(0, Register8::A),
             0x48 => self.bit_b_r8(1, Register8::B),
             0x49 => self.bit_b_r8(1, Register8::C),
@@ -454,7 +559,7 @@ impl Cpu {
             0x4B => self.bit_b_r8(1, Register8::E),
             0x4C => self.bit_b_r8(1, Register8::H),
             0x4D => self.bit_b_r8(1, Register8::L),
-            0x4E => self.bit_b_ir16(mmu, 1, Register16::HL),
+            0x4E => self.bit_b_ir16(bus, 1, Register16::HL),
             0x4F => self.bit_b_r8(1, Register8::A),
             0x50 => self.bit_b_r8(2, Register8::B),
             0x51 => self.bit_b_r8(2, Register8::C),
@@ -462,7 +567,7 @@ impl Cpu {
             0x53 => self.bit_b_r8(2, Register8::E),
             0x54 => self.bit_b_r8(2, Register8::H),
             0x55 => self.bit_b_r8(2, Register8::L),
-            0x56 => self.bit_b_ir16(mmu, 2, Register16::HL),
+            0x56 => self.bit_b_ir16(bus, 2, Register16::HL),
             0x57 => self.bit_b_r8(2, Register8::A),
             0x58 => self.bit_b_r8(3, Register8::B),
             0x59 => self.bit_b_r8(3, Register8::C),
@@ -470,7 +575,7 @@ impl Cpu {
             0x5B => self.bit_b_r8(3, Register8::E),
             0x5C => self.bit_b_r8(3, Register8::H),
             0x5D => self.bit_b_r8(3, Register8::L),
-            0x5E => self.bit_b_ir16(mmu, 3, Register16::HL),
+            0x5E => self.bit_b_ir16(bus, 3, Register16::HL),
             0x5F => self.bit_b_r8(3, Register8::A),
             0x60 => self.bit_b_r8(4, Register8::B),
             0x61 => self.bit_b_r8(4, Register8::C),
@@ -478,7 +583,7 @@ impl Cpu {
             0x63 => self.bit_b_r8(4, Register8::E),
             0x64 => self.bit_b_r8(4, Register8::H),
             0x65 => self.bit_b_r8(4, Register8::L),
-            0x66 => self.bit_b_ir16(mmu, 4, Register16::HL),
+            0x66 => self.bit_b_ir16(bus, 4, Register16::HL),
             0x67 => self.bit_b_r8(4, Register8::A),
             0x68 => self.bit_b_r8(5, Register8::B),
             0x69 => self.bit_b_r8(5, Register8::C),
@@ -486,7 +591,7 @@ impl Cpu {
             0x6B => self.bit_b_r8(5, Register8::E),
             0x6C => self.bit_b_r8(5, Register8::H),
             0x6D => self.bit_b_r8(5, Register8::L),
-            0x6E => self.bit_b_ir16(mmu, 5, Register16::HL),
+            0x6E => self.bit_b_ir16(bus, 5, Register16::HL),
             0x6F => self.bit_b_r8(5, Register8::A),
             0x70 => self.bit_b_r8(6, Register8::B),
             0x71 => self.bit_b_r8(6, Register8::C),
@@ -494,7 +599,7 @@ impl Cpu {
             0x73 => self.bit_b_r8(6, Register8::E),
             0x74 => self.bit_b_r8(6, Register8::H),
             0x75 => self.bit_b_r8(6, Register8::L),
-            0x76 => self.bit_b_ir16(mmu, 6, Register16::HL),
+            0x76 => self.bit_b_ir16(bus, 6, Register16::HL),
             0x77 => self.bit_b_r8(6, Register8::A),
             0x78 => self.bit_b_r8(7, Register8::B),
             0x79 => self.bit_b_r8(7, Register8::C),
@@ -502,7 +607,7 @@ impl Cpu {
             0x7B => self.bit_b_r8(7, Register8::E),
             0x7C => self.bit_b_r8(7, Register8::H),
             0x7D => self.bit_b_r8(7, Register8::L),
-            0x7E => self.bit_b_ir16(mmu, 7, Register16::HL),
+            0x7E => self.bit_b_ir16(bus, 7, Register16::HL),
             0x7F => self.bit_b_r8(7, Register8::A),
             0x80 => self.res_b_r8(0, Register8::B),
             0x81 => self.res_b_r8(0, Register8::C),
@@ -510,7 +615,7 @@ impl Cpu {
             0x83 => self.res_b_r8(0, Register8::E),
             0x84 => self.res_b_r8(0, Register8::H),
             0x85 => self.res_b_r8(0, Register8::L),
-            0x86 => self.res_b_ir16(mmu, 0, Register16::HL),
+            0x86 => self.res_b_ir16(bus, 0, Register16::HL),
             0x87 => self.res_b_r8(0, Register8::A),
             0x88 => self.res_b_r8(1, Register8::B),
             0x89 => self.res_b_r8(1, Register8::C),
@@ -518,7 +623,7 @@ impl Cpu {
             0x8B => self.res_b_r8(1, Register8::E),
             0x8C => self.res_b_r8(1, Register8::H),
             0x8D => self.res_b_r8(1, Register8::L),
-            0x8E => self.res_b_ir16(mmu, 1, Register16::HL),
+            0x8E => self.res_b_ir16(bus, 1, Register16::HL),
             0x8F => self.res_b_r8(1, Register8::A),
             0x90 => self.res_b_r8(2, Register8::B),
             0x91 => self.res_b_r8(2, Register8::C),
@@ -526,7 +631,7 @@ impl Cpu {
             0x93 => self.res_b_r8(2, Register8::E),
             0x94 => self.res_b_r8(2, Register8::H),
             0x95 => self.res_b_r8(2, Register8::L),
-            0x96 => self.res_b_ir16(mmu, 2, Register16::HL),
+            0x96 => self.res_b_ir16(bus, 2, Register16::HL),
             0x97 => self.res_b_r8(2, Register8::A),
             0x98 => self.res_b_r8(3, Register8::B),
             0x99 => self.res_b_r8(3, Register8::C),
@@ -534,7 +639,7 @@ impl Cpu {
             0x9B => self.res_b_r8(3, Register8::E),
             0x9C => self.res_b_r8(3, Register8::H),
             0x9D => self.res_b_r8(3, Register8::L),
-            0x9E => self.res_b_ir16(mmu, 3, Register16::HL),
+            0x9E => self.res_b_ir16(bus, 3, Register16::HL),
             0x9F => self.res_b_r8(3, Register8::A),
             0xA0 => self.res_b_r8(4, Register8::B),
             0xA1 => self.res_b_r8(4, Register8::C),
@@ -542,7 +647,7 @@ impl Cpu {
             0xA3 => self.res_b_r8(4, Register8::E),
             0xA4 => self.res_b_r8(4, Register8::H),
             0xA5 => self.res_b_r8(4, Register8::L),
-            0xA6 => self.res_b_ir16(mmu, 4, Register16::HL),
+            0xA6 => self.res_b_ir16(bus, 4, Register16::HL),
             0xA7 => self.res_b_r8(4, Register8::A),
             0xA8 => self.res_b_r8(5, Register8::B),
             0xA9 => self.res_b_r8(5, Register8::C),
@@ -550,7 +655,7 @@ impl Cpu {
             0xAB => self.res_b_r8(5, Register8::E),
             0xAC => self.res_b_r8(5, Register8::H),
             0xAD => self.res_b_r8(5, Register8::L),
-            0xAE => self.res_b_ir16(mmu, 5, Register16::HL),
+            0xAE => self.res_b_ir16(bus, 5, Register16::HL),
             0xAF => self.res_b_r8(5, Register8::A),
             0xB0 => self.res_b_r8(6, Register8::B),
             0xB1 => self.res_b_r8(6, Register8::C),
@@ -558,7 +663,7 @@ impl Cpu {
             0xB3 => self.res_b_r8(6, Register8::E),
             0xB4 => self.res_b_r8(6, Register8::H),
             0xB5 => self.res_b_r8(6, Register8::L),
-            0xB6 => self.res_b_ir16(mmu, 6, Register16::HL),
+            0xB6 => self.res_b_ir16(bus, 6, Register16::HL),
             0xB7 => self.res_b_r8(6, Register8::A),
             0xB8 => self.res_b_r8(7, Register8::B),
             0xB9 => self.res_b_r8(7, Register8::C),
@@ -566,7 +671,7 @@ impl Cpu {
             0xBB => self.res_b_r8(7, Register8::E),
             0xBC => self.res_b_r8(7, Register8::H),
             0xBD => self.res_b_r8(7, Register8::L),
-            0xBE => self.res_b_ir16(mmu, 7, Register16::HL),
+            0xBE => self.res_b_ir16(bus, 7, Register16::HL),
             0xBF => self.res_b_r8(7, Register8::A),
             0xC0 => self.set_b_r8(0, Register8::B),
             0xC1 => self.set_b_r8(0, Register8::C),
@@ -574,7 +679,7 @@ impl Cpu {
             0xC3 => self.set_b_r8(0, Register8::E),
             0xC4 => self.set_b_r8(0, Register8::H),
             0xC5 => self.set_b_r8(0, Register8::L),
-            0xC6 => self.set_b_ir16(mmu, 0, Register16::HL),
+            0xC6 => self.set_b_ir16(bus, 0, Register16::HL),
             0xC7 => self.set_b_r8(0, Register8::A),
             0xC8 => self.set_b_r8(1, Register8::B),
             0xC9 => self.set_b_r8(1, Register8::C),
@@ -582,7 +687,7 @@ impl Cpu {
             0xCB => self.set_b_r8(1, Register8::E),
             0xCC => self.set_b_r8(1, Register8::H),
             0xCD => self.set_b_r8(1, Register8::L),
-            0xCE => self.set_b_ir16(mmu, 1, Register16::HL),
+            0xCE => self.set_b_ir16(bus, 1, Register16::HL),
             0xCF => self.set_b_r8(1, Register8::A),
             0xD0 => self.set_b_r8(2, Register8::B),
             0xD1 => self.set_b_r8(2, Register8::C),
@@ -590,7 +695,7 @@ impl Cpu {
             0xD3 => self.set_b_r8(2, Register8::E),
             0xD4 => self.set_b_r8(2, Register8::H),
             0xD5 => self.set_b_r8(2, Register8::L),
-            0xD6 => self.set_b_ir16(mmu, 2, Register16::HL),
+            0xD6 => self.set_b_ir16(bus, 2, Register16::HL),
             0xD7 => self.set_b_r8(2, Register8::A),
             0xD8 => self.set_b_r8(3, Register8::B),
             0xD9 => self.set_b_r8(3, Register8::C),
@@ -598,7 +703,7 @@ impl Cpu {
             0xDB => self.set_b_r8(3, Register8::E),
             0xDC => self.set_b_r8(3, Register8::H),
             0xDD => self.set_b_r8(3, Register8::L),
-            0xDE => self.set_b_ir16(mmu, 3, Register16::HL),
+            0xDE => self.set_b_ir16(bus, 3, Register16::HL),
             0xDF => self.set_b_r8(3, Register8::A),
             0xE0 => self.set_b_r8(4, Register8::B),
             0xE1 => self.set_b_r8(4, Register8::C),
@@ -606,7 +711,7 @@ impl Cpu {
             0xE3 => self.set_b_r8(4, Register8::E),
             0xE4 => self.set_b_r8(4, Register8::H),
             0xE5 => self.set_b_r8(4, Register8::L),
-            0xE6 => self.set_b_ir16(mmu, 4, Register16::HL),
+            0xE6 => self.set_b_ir16(bus, 4, Register16::HL),
             0xE7 => self.set_b_r8(4, Register8::A),
             0xE8 => self.set_b_r8(5, Register8::B),
             0xE9 => self.set_b_r8(5, Register8::C),
@@ -614,7 +719,7 @@ impl Cpu {
             0xEB => self.set_b_r8(5, Register8::E),
             0xEC => self.set_b_r8(5, Register8::H),
             0xED => self.set_b_r8(5, Register8::L),
-            0xEE => self.set_b_ir16(mmu, 5, Register16::HL),
+            0xEE => self.set_b_ir16(bus, 5, Register16::HL),
             0xEF => self.set_b_r8(5, Register8::A),
             0xF0 => self.set_b_r8(6, Register8::B),
             0xF1 => self.set_b_r8(6, Register8::C),
@@ -622,7 +727,7 @@ impl Cpu {
             0xF3 => self.set_b_r8(6, Register8::E),
             0xF4 => self.set_b_r8(6, Register8::H),
             0xF5 => self.set_b_r8(6, Register8::L),
-            0xF6 => self.set_b_ir16(mmu, 6, Register16::HL),
+            0xF6 => self.set_b_ir16(bus, 6, Register16::HL),
             0xF7 => self.set_b_r8(6, Register8::A),
             0xF8 => self.set_b_r8(7, Register8::B),
             0xF9 => self.set_b_r8(7, Register8::C),
@@ -630,7 +735,7 @@ impl Cpu {
             0xFB => self.set_b_r8(7, Register8::E),
             0xFC => self.set_b_r8(7, Register8::H),
             0xFD => self.set_b_r8(7, Register8::L),
-            0xFE => self.set_b_ir16(mmu, 7, Register16::HL),
+            0xFE => self.set_b_ir16(bus, 7, Register16::HL),
             0xFF => self.set_b_r8(7, Register8::A),
         }
         self.step_cycles(CB_OP_CYCLES[op as usize]);
@@ -640,44 +745,68 @@ impl Cpu {
         self.cycles = self.cycles.wrapping_add(steps as u128);
     }
 
-    fn consume_u8(&mut self, mmu: &mut Mmu) -> u8 {
-        let r: u8 = mmu.read(self.reg.pc);
+    // Every memory access takes an M-cycle, the rest of the machine runs before it
+    fn cycle<B: Bus>(&mut self, bus: &mut B) {
+        bus.cycle();
+        self.synced += 4;
+    }
+
+    fn read<B: Bus>(&mut self, bus: &mut B, add: u16) -> u8 {
+        self.cycle(bus);
+        bus.read(add)
+    }
+
+    fn read_16<B: Bus>(&mut self, bus: &mut B, add: u16) -> u16 {
+        let low: u8 = self.read(bus, add);
+        let high: u8 = self.read(bus, add.wrapping_add(1));
+        ((high as u16) << 8) | (low as u16)
+    }
+
+    fn write<B: Bus>(&mut self, bus: &mut B, add: u16, value: u8) {
+        self.cycle(bus);
+        bus.write(add, value);
+    }
+
+    fn consume_u8<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let r: u8 = self.read(bus, self.reg.pc);
         self.reg.pc = self.reg.pc.wrapping_add(1);
         r
     }
 
-    fn consume_i8(&mut self, mmu: &mut Mmu) -> i8 {
-        let r: i8 = mmu.read(self.reg.pc) as i8;
+    fn consume_i8<B: Bus>(&mut self, bus: &mut B) -> i8 {
+        let r: i8 = self.read(bus, self.reg.pc) as i8;
         self.reg.pc = self.reg.pc.wrapping_add(1);
         r
     }
 
-    fn consume_u16(&mut self, mmu: &mut Mmu) -> u16 {
-        let r: u16 = mmu.read_16(self.reg.pc);
+    fn consume_u16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let r: u16 = self.read_16(bus, self.reg.pc);
         self.reg.pc = self.reg.pc.wrapping_add(2);
         r
     }
 
-    fn stack_push_u16(&mut self, mmu: &mut Mmu, value: u16) {
+    // Pushing always starts with an internal cycle decrementing SP
+    fn stack_push_u16<B: Bus>(&mut self, bus: &mut B, value: u16) {
+        self.cycle(bus);
         let value_high: u8 = ((value & 0xff00) >> 8) as u8;
         let value_low: u8 = (value & 0x00ff) as u8;
-        self.stack_push_u8(mmu, value_high);
-        self.stack_push_u8(mmu, value_low);
+        self.stack_push_u8(bus, value_high);
+        self.stack_push_u8(bus, value_low);
     }
 
-    fn stack_pop_u16(&mut self, mmu: &Mmu) -> u16 {
-        let value_low: u8 = self.stack_pop_u8(mmu);
-        let value_high: u8 = self.stack_pop_u8(mmu);
+    fn stack_pop_u16<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let value_low: u8 = self.stack_pop_u8(bus);
+        let value_high: u8 = self.stack_pop_u8(bus);
         ((value_high as u16) << 8) + (value_low as u16)
     }
 
-    fn stack_push_u8(&mut self, mmu: &mut Mmu, value: u8) {
+    fn stack_push_u8<B: Bus>(&mut self, bus: &mut B, value: u8) {
         self.reg.sp = self.reg.sp.wrapping_sub(1);
-        mmu.write(self.reg.sp, value);
+        self.write(bus, self.reg.sp, value);
     }
 
-    fn stack_pop_u8(&mut self, mmu: &Mmu) -> u8 {
-        let value: u8 = mmu.read(self.reg.sp);
+    fn stack_pop_u8<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        let value: u8 = self.read(bus, self.reg.sp);
         self.reg.sp = self.reg.sp.wrapping_add(1);
         value
     }
@@ -699,87 +828,99 @@ impl Cpu {
         self.reg.set8(&dest, value);
     }
 
-    fn ld_r8_u8(&mut self, mmu: &mut Mmu, dest: Register8) {
-        let value: u8 = self.consume_u8(mmu);
+    fn ld_r8_u8<B: Bus>(&mut self, bus: &mut B, dest: Register8) {
+        let value: u8 = self.consume_u8(bus);
         self.reg.set8(&dest, value);
     }
 
-    fn ld_r8_ir16(&mut self, mmu: &mut Mmu, dest: Register8, src: Register16, post_op: PostOp) {
+    fn ld_r8_ir16<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        dest: Register8,
+        src: Register16,
+        post_op: PostOp,
+    ) {
         let add: u16 = self.reg.get16(&src);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.reg.set8(&dest, value);
         self.handle_post_op(&src, &post_op);
     }
 
-    fn ld_ir16_r8(&mut self, mmu: &mut Mmu, dest: Register16, src: Register8, post_op: PostOp) {
+    fn ld_ir16_r8<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        dest: Register16,
+        src: Register8,
+        post_op: PostOp,
+    ) {
         let value: u8 = self.reg.get8(&src);
         let add: u16 = self.reg.get16(&dest);
-        mmu.write(add, value);
+        self.write(bus, add, value);
         self.handle_post_op(&dest, &post_op);
     }
 
-    fn ld_ir16_u8(&mut self, mmu: &mut Mmu, dest: Register16) {
-        let value: u8 = self.consume_u8(mmu);
+    fn ld_ir16_u8<B: Bus>(&mut self, bus: &mut B, dest: Register16) {
+        let value: u8 = self.consume_u8(bus);
         let add: u16 = self.reg.get16(&dest);
-        mmu.write(add, value);
+        self.write(bus, add, value);
     }
 
-    fn ld_r8_iu16(&mut self, mmu: &mut Mmu, dest: Register8) {
-        let add: u16 = self.consume_u16(mmu);
-        let value: u8 = mmu.read(add);
+    fn ld_r8_iu16<B: Bus>(&mut self, bus: &mut B, dest: Register8) {
+        let add: u16 = self.consume_u16(bus);
+        let value: u8 = self.read(bus, add);
         self.reg.set8(&dest, value);
     }
 
-    fn ld_iu16_r8(&mut self, mmu: &mut Mmu, src: Register8) {
+    fn ld_iu16_r8<B: Bus>(&mut self, bus: &mut B, src: Register8) {
         let value: u8 = self.reg.get8(&src);
-        let add: u16 = self.consume_u16(mmu);
-        mmu.write(add, value);
+        let add: u16 = self.consume_u16(bus);
+        self.write(bus, add, value);
     }
 
-    fn ld_r8_ir8(&mut self, mmu: &mut Mmu, dest: Register8, src: Register8) {
+    fn ld_r8_ir8<B: Bus>(&mut self, bus: &mut B, dest: Register8, src: Register8) {
         let add_low: u8 = self.reg.get8(&src);
         let add: u16 = 0xff00 | (add_low as u16);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.reg.set8(&dest, value);
     }
 
-    fn ld_ir8_r8(&mut self, mmu: &mut Mmu, dest: Register8, src: Register8) {
+    fn ld_ir8_r8<B: Bus>(&mut self, bus: &mut B, dest: Register8, src: Register8) {
         let value: u8 = self.reg.get8(&src);
         let add_low: u8 = self.reg.get8(&dest);
         let add: u16 = 0xff00 | (add_low as u16);
-        mmu.write(add, value);
+        self.write(bus, add, value);
     }
 
-    fn ldh_r8_iu8(&mut self, mmu: &mut Mmu, dest: Register8) {
-        let add_low: u8 = self.consume_u8(mmu);
+    fn ldh_r8_iu8<B: Bus>(&mut self, bus: &mut B, dest: Register8) {
+        let add_low: u8 = self.consume_u8(bus);
         let add: u16 = 0xff00 | (add_low as u16);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.reg.set8(&dest, value);
     }
 
-    fn ldh_iu8_r8(&mut self, mmu: &mut Mmu, src: Register8) {
+    fn ldh_iu8_r8<B: Bus>(&mut self, bus: &mut B, src: Register8) {
         let value: u8 = self.reg.get8(&src);
-        let add_low: u8 = self.consume_u8(mmu);
+        let add_low: u8 = self.consume_u8(bus);
         let add: u16 = 0xff00 | (add_low as u16);
-        mmu.write(add, value);
+        self.write(bus, add, value);
     }
 
     //
     // load 16-bit
     //
 
-    fn ld_r16_u16(&mut self, mmu: &mut Mmu, dest: Register16) {
-        let value: u16 = self.consume_u16(mmu);
+    fn ld_r16_u16<B: Bus>(&mut self, bus: &mut B, dest: Register16) {
+        let value: u16 = self.consume_u16(bus);
         self.reg.set16(&dest, value);
     }
 
-    fn ld_iu16_r16(&mut self, mmu: &mut Mmu, src: Register16) {
+    fn ld_iu16_r16<B: Bus>(&mut self, bus: &mut B, src: Register16) {
         let value: u16 = self.reg.get16(&src);
         let value_low: u8 = (value & 0x00ff) as u8;
         let value_high: u8 = ((value & 0xff00) >> 8) as u8;
-        let add: u16 = self.consume_u16(mmu);
-        mmu.write(add, value_low);
-        mmu.write(add + 1, value_high);
+        let add: u16 = self.consume_u16(bus);
+        self.write(bus, add, value_low);
+        self.write(bus, add.wrapping_add(1), value_high);
     }
 
     fn ld_r16_r16(&mut self, dest: Register16, src: Register16) {
@@ -787,13 +928,13 @@ impl Cpu {
         self.reg.set16(&dest, value);
     }
 
-    fn push_r16(&mut self, mmu: &mut Mmu, src: Register16) {
+    fn push_r16<B: Bus>(&mut self, bus: &mut B, src: Register16) {
         let value: u16 = self.reg.get16(&src);
-        self.stack_push_u16(mmu, value);
+        self.stack_push_u16(bus, value);
     }
 
-    fn pop_r16(&mut self, mmu: &Mmu, dest: Register16) {
-        let value: u16 = self.stack_pop_u16(mmu);
+    fn pop_r16<B: Bus>(&mut self, bus: &mut B, dest: Register16) {
+        let value: u16 = self.stack_pop_u16(bus);
         self.reg.set16(&dest, value);
     }
 
@@ -866,17 +1007,17 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 
-    fn add_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn add_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.sum8_flags(accumulator, value, true, true);
         self.reg.set8(&Register8::A, res);
     }
 
-    fn add_u8(&mut self, mmu: &mut Mmu) {
+    fn add_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         let res: u8 = self.sum8_flags(accumulator, value, true, true);
         self.reg.set8(&Register8::A, res);
     }
@@ -901,14 +1042,14 @@ impl Cpu {
         self.adc_flags(value);
     }
 
-    fn adc_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn adc_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.adc_flags(value);
     }
 
-    fn adc_u8(&mut self, mmu: &mut Mmu) {
-        let value: u8 = self.consume_u8(mmu);
+    fn adc_u8<B: Bus>(&mut self, bus: &mut B) {
+        let value: u8 = self.consume_u8(bus);
         self.adc_flags(value);
     }
 
@@ -919,17 +1060,17 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 
-    fn sub_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn sub_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.sub8_flags(accumulator, value, true, true);
         self.reg.set8(&Register8::A, res);
     }
 
-    fn sub_u8(&mut self, mmu: &mut Mmu) {
+    fn sub_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         let res: u8 = self.sub8_flags(accumulator, value, true, true);
         self.reg.set8(&Register8::A, res);
     }
@@ -954,14 +1095,14 @@ impl Cpu {
         self.sbc_flags(value);
     }
 
-    fn sbc_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn sbc_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.sbc_flags(value);
     }
 
-    fn sbc_u8(&mut self, mmu: &mut Mmu) {
-        let value: u8 = self.consume_u8(mmu);
+    fn sbc_u8<B: Bus>(&mut self, bus: &mut B) {
+        let value: u8 = self.consume_u8(bus);
         self.sbc_flags(value);
     }
 
@@ -971,16 +1112,16 @@ impl Cpu {
         self.sub8_flags(accumulator, value, true, true);
     }
 
-    fn cp_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn cp_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.sub8_flags(accumulator, value, true, true);
     }
 
-    fn cp_u8(&mut self, mmu: &mut Mmu) {
+    fn cp_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         self.sub8_flags(accumulator, value, true, true);
     }
 
@@ -990,11 +1131,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn inc_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn inc_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.sum8_flags(value, 1, false, true);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn dec_r8(&mut self, reg: Register8) {
@@ -1003,11 +1144,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn dec_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn dec_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.sub8_flags(value, 1, false, true);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn and8_flags(&mut self, op1: u8, op2: u8) -> u8 {
@@ -1032,17 +1173,17 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 
-    fn and_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn and_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.and8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
 
-    fn and_u8(&mut self, mmu: &mut Mmu) {
+    fn and_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         let res: u8 = self.and8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
@@ -1069,17 +1210,17 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 
-    fn or_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn or_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.or8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
 
-    fn or_u8(&mut self, mmu: &mut Mmu) {
+    fn or_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         let res: u8 = self.or8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
@@ -1106,17 +1247,17 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 
-    fn xor_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn xor_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.xor8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
 
-    fn xor_u8(&mut self, mmu: &mut Mmu) {
+    fn xor_u8<B: Bus>(&mut self, bus: &mut B) {
         let accumulator: u8 = self.reg.get8(&Register8::A);
-        let value: u8 = self.consume_u8(mmu);
+        let value: u8 = self.consume_u8(bus);
         let res: u8 = self.xor8_flags(accumulator, value);
         self.reg.set8(&Register8::A, res);
     }
@@ -1201,16 +1342,16 @@ impl Cpu {
         res
     }
 
-    fn add_r16_i8(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn add_r16_i8<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let reg_value: u16 = self.reg.get16(&reg);
-        let op: i8 = self.consume_i8(mmu);
+        let op: i8 = self.consume_i8(bus);
         let res: u16 = self.signed_sum_flags(reg_value, op);
         self.reg.set16(&reg, res);
     }
 
-    fn ld_r16_r16_i8(&mut self, mmu: &mut Mmu, dest: Register16, src: Register16) {
+    fn ld_r16_r16_i8<B: Bus>(&mut self, bus: &mut B, dest: Register16, src: Register16) {
         let op1: u16 = self.reg.get16(&src);
-        let op2: i8 = self.consume_i8(mmu);
+        let op2: i8 = self.consume_i8(bus);
         let res: u16 = self.signed_sum_flags(op1, op2);
         self.reg.set16(&dest, res);
     }
@@ -1219,8 +1360,8 @@ impl Cpu {
     // Control Flow
     //
 
-    fn jp_u16(&mut self, mmu: &mut Mmu) {
-        let add: u16 = self.consume_u16(mmu);
+    fn jp_u16<B: Bus>(&mut self, bus: &mut B) {
+        let add: u16 = self.consume_u16(bus);
         self.reg.pc = add;
     }
 
@@ -1228,8 +1369,8 @@ impl Cpu {
         self.reg.pc = self.reg.get16(&reg);
     }
 
-    fn jp_f_u16(&mut self, mmu: &mut Mmu, flag: Flag) {
-        let add: u16 = self.consume_u16(mmu);
+    fn jp_f_u16<B: Bus>(&mut self, bus: &mut B, flag: Flag) {
+        let add: u16 = self.consume_u16(bus);
 
         if self.reg.getf(&flag) == 1 {
             self.step_cycles(4);
@@ -1245,14 +1386,14 @@ impl Cpu {
         self.signed_sum(self.reg.pc, offset)
     }
 
-    fn jr_i8(&mut self, mmu: &mut Mmu) {
-        let offset: i8 = self.consume_i8(mmu);
+    fn jr_i8<B: Bus>(&mut self, bus: &mut B) {
+        let offset: i8 = self.consume_i8(bus);
         let add: u16 = self.get_relative_add(offset);
         self.reg.pc = add;
     }
 
-    fn jr_f_i8(&mut self, mmu: &mut Mmu, flag: Flag) {
-        let offset: i8 = self.consume_i8(mmu);
+    fn jr_f_i8<B: Bus>(&mut self, bus: &mut B, flag: Flag) {
+        let offset: i8 = self.consume_i8(bus);
         let add: u16 = self.get_relative_add(offset);
         if self.reg.getf(&flag) == 1 {
             self.step_cycles(4);
@@ -1260,36 +1401,37 @@ impl Cpu {
         }
     }
 
-    fn call_u16(&mut self, mmu: &mut Mmu) {
-        let add: u16 = self.consume_u16(mmu);
-        self.stack_push_u16(mmu, self.reg.pc);
+    fn call_u16<B: Bus>(&mut self, bus: &mut B) {
+        let add: u16 = self.consume_u16(bus);
+        self.stack_push_u16(bus, self.reg.pc);
         self.reg.pc = add;
     }
 
-    fn call_f_u16(&mut self, mmu: &mut Mmu, flag: Flag) {
-        let add: u16 = self.consume_u16(mmu);
+    fn call_f_u16<B: Bus>(&mut self, bus: &mut B, flag: Flag) {
+        let add: u16 = self.consume_u16(bus);
 
         if self.reg.getf(&flag) == 1 {
             self.step_cycles(12);
-            self.stack_push_u16(mmu, self.reg.pc);
+            self.stack_push_u16(bus, self.reg.pc);
             self.reg.pc = add;
         }
     }
 
-    fn ret(&mut self, mmu: &Mmu) {
-        self.reg.pc = self.stack_pop_u16(mmu);
+    fn ret<B: Bus>(&mut self, bus: &mut B) {
+        self.reg.pc = self.stack_pop_u16(bus);
     }
 
-    fn ret_f(&mut self, mmu: &Mmu, flag: Flag) {
+    fn ret_f<B: Bus>(&mut self, bus: &mut B, flag: Flag) {
+        self.cycle(bus);
         if self.reg.getf(&flag) == 1 {
             self.step_cycles(12);
-            self.reg.pc = self.stack_pop_u16(mmu);
+            self.reg.pc = self.stack_pop_u16(bus);
         }
     }
 
-    fn rst_f8(&mut self, mmu: &mut Mmu, fixed: u8) {
+    fn rst_f8<B: Bus>(&mut self, bus: &mut B, fixed: u8) {
         let add: u16 = fixed as u16;
-        self.stack_push_u16(mmu, self.reg.pc);
+        self.stack_push_u16(bus, self.reg.pc);
         self.reg.pc = add;
     }
 
@@ -1298,20 +1440,35 @@ impl Cpu {
     }
 
     fn ei(&mut self) {
-        self.ime = Ime::Pending;
+        if self.ime == Ime::Disabled {
+            self.ime = Ime::Pending;
+        }
     }
 
-    //TODO: correct implementation
-    fn stop(&mut self) {}
+    fn stop<B: Bus>(&mut self, bus: &mut B) {
+        self.consume_u8(bus);
+        if !bus.stop() {
+            self.stopped = true;
+        }
+    }
 
-    //TODO: halt bug
-    fn halt(&mut self) {
-        self.halted = true;
+    // With an interrupt already pending HALT doesn't halt, when IME is off the next
+    // opcode byte is read twice, right after EI the interrupt returns to the HALT
+    fn halt<B: Bus>(&mut self, bus: &B) {
+        if !self.is_interrupt_pending(bus) {
+            self.halted = true;
+            return;
+        }
+        match self.ime {
+            Ime::Enabled => self.halted = true,
+            Ime::Disabled => self.halt_bug = true,
+            Ime::Pending => self.reg.pc = self.reg.pc.wrapping_sub(1),
+        }
     }
 
-    fn reti(&mut self, mmu: &Mmu) {
+    fn reti<B: Bus>(&mut self, bus: &mut B) {
         self.ime = Ime::Enabled;
-        self.reg.pc = self.stack_pop_u16(mmu);
+        self.reg.pc = self.stack_pop_u16(bus);
     }
 
     //
@@ -1334,11 +1491,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn set_b_ir16(&mut self, mmu: &mut Mmu, bit: u8, reg: Register16) {
+    fn set_b_ir16<B: Bus>(&mut self, bus: &mut B, bit: u8, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let val: u8 = mmu.read(add);
+        let val: u8 = self.read(bus, add);
         let res: u8 = Cpu::setb8(bit, val);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn resetb8(bit: u8, value: u8) -> u8 {
@@ -1351,11 +1508,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn res_b_ir16(&mut self, mmu: &mut Mmu, bit: u8, reg: Register16) {
+    fn res_b_ir16<B: Bus>(&mut self, bus: &mut B, bit: u8, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let val: u8 = mmu.read(add);
+        let val: u8 = self.read(bus, add);
         let res: u8 = Cpu::resetb8(bit, val);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn testb8_flags(&mut self, bit: u8, value: u8) {
@@ -1374,9 +1531,9 @@ impl Cpu {
         self.testb8_flags(bit, value);
     }
 
-    fn bit_b_ir16(&mut self, mmu: &mut Mmu, bit: u8, reg: Register16) {
+    fn bit_b_ir16<B: Bus>(&mut self, bus: &mut B, bit: u8, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         self.testb8_flags(bit, value);
     }
 
@@ -1404,11 +1561,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn swap_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn swap_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let val: u8 = mmu.read(add);
+        let val: u8 = self.read(bus, add);
         let res: u8 = self.swap8_flags(val);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn shiftrl8_flags(&mut self, value: u8) -> u8 {
@@ -1437,11 +1594,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn srl_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn srl_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.shiftrl8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn shiftra8_flags(&mut self, value: u8) -> u8 {
@@ -1474,11 +1631,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn sra_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn sra_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.shiftra8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn shiftla8_flags(&mut self, value: u8) -> u8 {
@@ -1507,11 +1664,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn sla_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn sla_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.shiftla8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn rotater8_flags(&mut self, value: u8) -> u8 {
@@ -1544,11 +1701,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn rr_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn rr_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.rotater8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn rotatel8_flags(&mut self, value: u8) -> u8 {
@@ -1581,11 +1738,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn rl_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn rl_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.rotatel8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn rotaterc8_flags(&mut self, value: u8) -> u8 {
@@ -1615,11 +1772,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn rrc_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn rrc_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.rotaterc8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn rotatelc8_flags(&mut self, value: u8) -> u8 {
@@ -1649,11 +1806,11 @@ impl Cpu {
         self.reg.set8(&reg, res);
     }
 
-    fn rlc_ir16(&mut self, mmu: &mut Mmu, reg: Register16) {
+    fn rlc_ir16<B: Bus>(&mut self, bus: &mut B, reg: Register16) {
         let add: u16 = self.reg.get16(&reg);
-        let value: u8 = mmu.read(add);
+        let value: u8 = self.read(bus, add);
         let res: u8 = self.rotatelc8_flags(value);
-        mmu.write(add, res);
+        self.write(bus, add, res);
     }
 
     fn rra(&mut self) {
@@ -1766,3 +1923,41 @@ impl Cpu {
         self.reg.set8(&Register8::A, res);
     }
 }
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stateful for Cpu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.reg.save_state(state);
+        state.write_u128(self.cycles);
+        state.write_u128(self.ops);
+        state.write_u8(match self.ime {
+            Ime::Enabled => 0x0,
+            Ime::Disabled => 0x1,
+            Ime::Pending => 0x2,
+        });
+        state.write_bool(self.halted);
+        state.write_bool(self.halt_bug);
+        state.write_bool(self.stopped);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.reg.load_state(state)?;
+        self.cycles = state.read_u128()?;
+        self.ops = state.read_u128()?;
+        self.ime = match state.read_u8()? {
+            0x0 => Ime::Enabled,
+            0x1 => Ime::Disabled,
+            0x2 => Ime::Pending,
+            value => return Err(format!("Invalid IME state {:#02X}", value).into()),
+        };
+        self.halted = state.read_bool()?;
+        self.halt_bug = state.read_bool()?;
+        self.stopped = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
 #[derive(Debug)]
 pub enum Register8 {
     A,
@@ -47,18 +50,6 @@ pub struct Registers {
 
 impl Registers {
     pub fn new() -> Self {
-        /*Self {
-            a: 0x11,
-            b: 0x00,
-            c: 0x00,
-            d: 0xff,
-            e: 0x56,
-            f: 0x80,
-            h: 0x0,
-            l: 0xd,
-            sp: 0xfffe,
-            pc: 0x0100,
-        }*/
         Self {
             a: 0x01,
             b: 0x00,
@@ -73,6 +64,21 @@ impl Registers {
         }
     }
 
+    pub fn new_cgb() -> Self {
+        Self {
+            a: 0x11,
+            b: 0x00,
+            c: 0x00,
+            d: 0xff,
+            e: 0x56,
+            f: 0x80,
+            h: 0x0,
+            l: 0xd,
+            sp: 0xfffe,
+            pc: 0x0100,
+        }
+    }
+
     pub fn get8(&self, name: &Register8) -> u8 {
         match name {
             Register8::A => self.a,
@@ -172,3 +178,38 @@ impl Registers {
         };
     }
 }
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stateful for Registers {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.a);
+        state.write_u8(self.b);
+        state.write_u8(self.c);
+        state.write_u8(self.d);
+        state.write_u8(self.e);
+        state.write_u8(self.h);
+        state.write_u8(self.l);
+        state.write_u8(self.f);
+        state.write_u16(self.sp);
+        state.write_u16(self.pc);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.a = state.read_u8()?;
+        self.b = state.read_u8()?;
+        self.c = state.read_u8()?;
+        self.d = state.read_u8()?;
+        self.e = state.read_u8()?;
+        self.h = state.read_u8()?;
+        self.l = state.read_u8()?;
+        self.f = state.read_u8()? & 0xF0;
+        self.sp = state.read_u16()?;
+        self.pc = state.read_u16()?;
+        Ok(())
+    }
+}
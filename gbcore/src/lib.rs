@@ -1,21 +1,35 @@
 use crate::mmu::address_spaces::io::joypad::JoypadState;
+use bus::SystemBus;
 use cpu::Cpu;
 use mmu::Mmu;
+use ppu::colors::{ColorCorrection, DmgPalette};
 use ppu::LcdBuffer;
 use ppu::Ppu;
+use state::{StateReader, StateWriter, Stateful};
 use std::error::Error;
 
-mod cpu;
+pub mod apu;
+mod bus;
+pub mod cpu;
 pub mod mmu;
+pub mod movie;
 pub mod ppu;
+pub mod rewind;
+mod state;
 
 const CYCLE_LIMIT: u32 = 70224;
+const CPU_CLOCK: u64 = 4194304;
+const STATE_MAGIC: &[u8; 4] = b"NTHS";
+const STATE_VERSION: u16 = 15;
 
 pub struct Device {
     cpu: Cpu,
     ppu: Ppu,
     mmu: Mmu,
-    tima_overflow: bool,
+    // Start of the emulated clock driving the RTC, None when it follows the host clock
+    rtc_start: Option<u64>,
+    rtc_cycles: u64,
+    unrestricted_access: bool,
 }
 
 impl Device {
@@ -24,11 +38,18 @@ impl Device {
         ram: Option<Vec<u8>>,
         rtc: Option<Vec<u8>>,
     ) -> Result<Device, Box<dyn Error>> {
+        let mmu: Mmu = Mmu::new(rom, ram, rtc)?;
         Ok(Self {
-            cpu: Cpu::new(),
+            cpu: if mmu.is_cgb() {
+                Cpu::new_cgb()
+            } else {
+                Cpu::new()
+            },
             ppu: Ppu::new(),
-            mmu: Mmu::new(rom, ram, rtc)?,
-            tima_overflow: false,
+            mmu,
+            rtc_start: None,
+            rtc_cycles: 0,
+            unrestricted_access: false,
         })
     }
 
@@ -37,33 +58,98 @@ impl Device {
 
         self.mmu.io.joypad.set_state(joypad_state);
 
-        while total_cycles < CYCLE_LIMIT {
+        if let Some(rtc_start) = self.rtc_start {
+            self.mmu
+                .cart
+                .update_rtc_now(rtc_start + self.rtc_cycles / CPU_CLOCK);
+        }
+
+        while total_cycles < CYCLE_LIMIT && !self.cpu.is_trapped() {
             if self.mmu.io.joypad.purge_interrupt() {
                 self.mmu.io.request_joypad_interrupt();
             }
-            let cycles: u8 = self.cpu.tick(&mut self.mmu);
-            self.ppu.tick(&mut self.mmu, buffer, cycles);
-            self.update_timers(cycles);
-            total_cycles += cycles as u32;
+            let stopped: bool = self.cpu.is_stopped();
+            let mut bus: SystemBus = SystemBus {
+                mmu: &mut self.mmu,
+                ppu: &mut self.ppu,
+                buffer,
+                unrestricted_access: self.unrestricted_access,
+                stalled_cycles: 0,
+            };
+            let cycles: u32 = self.cpu.tick(&mut bus) as u32 + bus.stalled_cycles;
+            // Frames are counted in dots, the CPU runs two cycles per dot in double speed
+            let dots: u32 = if self.mmu.is_double_speed() {
+                cycles / 2
+            } else {
+                cycles
+            };
+            total_cycles += dots;
+            // STOP halts the main clock on DMG, the LCD goes blank while the sound
+            // output keeps going so that frontends stay paced
+            if stopped && self.cpu.is_stopped() {
+                buffer.cleared = true;
+                let (dots, div): (u8, u8) = self.mmu.get_dots_and_div();
+                self.mmu.io.apu.tick(dots, div);
+            }
         }
+
+        self.rtc_cycles += total_cycles as u64;
+        self.mmu.io.apu.end_frame();
     }
 
-    fn update_timers(&mut self, cycles: u8) {
-        let tima_enabled: bool = self.mmu.io.timers.get_tima_enabled();
-        let tima_clock: u16 = self.mmu.io.timers.get_tima_clock();
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.mmu.io.apu.set_sample_rate(sample_rate);
+    }
 
-        if self.tima_overflow {
-            self.mmu.io.request_timer_interrupt();
-            self.tima_overflow = false;
-        }
+    pub fn get_sample_rate(&self) -> u32 {
+        self.mmu.io.apu.get_sample_rate()
+    }
 
-        for _ in 0..cycles {
-            self.mmu.io.timers.inc_sysclk();
-            let sysclk: u16 = self.mmu.io.timers.get_sysclk();
-            if tima_enabled && (sysclk % tima_clock) == 0 {
-                self.tima_overflow = self.mmu.io.timers.inc_tima();
-            }
-        }
+    // Appends the interleaved stereo samples generated since the last call
+    pub fn drain_samples(&mut self, samples: &mut Vec<i16>) {
+        self.mmu.io.apu.drain_samples(samples);
+    }
+
+    // Colors used for DMG games
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.ppu.set_dmg_palette(palette);
+    }
+
+    // How the colors of Color games are shown
+    pub fn set_color_correction(&mut self, color_correction: ColorCorrection) {
+        self.ppu.set_color_correction(color_correction);
+    }
+
+    // Whether the cart runs in Color mode
+    pub fn is_cgb(&self) -> bool {
+        self.mmu.is_cgb()
+    }
+
+    pub fn get_cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    // Makes frame return early right after the given opcode is executed
+    pub fn set_opcode_trap(&mut self, opcode: Option<u8>) {
+        self.cpu.set_trap(opcode);
+    }
+
+    // Returns whether the last frame stopped on the trap opcode, clearing it
+    pub fn take_trap(&mut self) -> bool {
+        let trapped: bool = self.cpu.is_trapped();
+        self.cpu.clear_trapped();
+        trapped
+    }
+
+    // Lets the CPU access VRAM and OAM whatever the PPU is doing, for debuggers and
+    // tools that patch memory from running code
+    pub fn set_unrestricted_access(&mut self, unrestricted: bool) {
+        self.unrestricted_access = unrestricted;
+    }
+
+    // Appends the bytes sent through the serial port since the last call
+    pub fn drain_serial(&mut self, output: &mut Vec<u8>) {
+        self.mmu.io.serial.drain_output(output);
     }
 
     pub fn dump_ram(&self) -> Option<Vec<u8>> {
@@ -74,7 +160,86 @@ impl Device {
         self.mmu.cart.dump_rtc()
     }
 
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state: StateWriter = StateWriter::new();
+        self.save_state_header(&mut state);
+        self.cpu.save_state(&mut state);
+        self.ppu.save_state(&mut state);
+        self.mmu.save_state(&mut state);
+        state.write_u64(self.rtc_cycles);
+        state.into_bytes()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.check_state_header(&mut StateReader::new(data))?;
+        let backup: Vec<u8> = self.save_state();
+
+        // A truncated or corrupted body must not leave the machine half loaded
+        if let Err(e) = self.load_state_body(data) {
+            self.load_state_body(&backup)
+                .expect("Failed to restore the state before loading");
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn save_state_header(&self, state: &mut StateWriter) {
+        state.write_bytes(STATE_MAGIC);
+        state.write_u16(STATE_VERSION);
+        state.write_bytes(self.mmu.cart.get_title().as_bytes());
+        state.write_u8(self.mmu.cart.get_header_checksum());
+        state.write_u16(self.mmu.cart.get_global_checksum());
+    }
+
+    fn check_state_header(&self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        if state.read_bytes().ok().as_deref() != Some(&STATE_MAGIC[..]) {
+            return Err("The data is not a save state".into());
+        }
+        let version: u16 = state.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(format!("Unsupported save state version {}", version).into());
+        }
+        let title: String = String::from_utf8_lossy(&state.read_bytes()?).into_owned();
+        if title != self.mmu.cart.get_title()
+            || state.read_u8()? != self.mmu.cart.get_header_checksum()
+            || state.read_u16()? != self.mmu.cart.get_global_checksum()
+        {
+            return Err(format!(
+                "The save state belongs to \"{}\", not to \"{}\"",
+                title.trim_end_matches('\0'),
+                self.mmu.cart.get_title().trim_end_matches('\0')
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn load_state_body(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut state: StateReader = StateReader::new(data);
+        self.check_state_header(&mut state)?;
+        self.cpu.load_state(&mut state)?;
+        self.ppu.load_state(&mut state)?;
+        self.mmu.load_state(&mut state)?;
+        self.ppu.check_state(&self.mmu)?;
+        self.rtc_cycles = state.read_u64()?;
+        if !state.is_empty() {
+            return Err("Unexpected data at the end of the save state".into());
+        }
+        Ok(())
+    }
+
+    // Ignored once the RTC is driven by the emulated clock
     pub fn update_rtc_now(&mut self, elapsed_secs: u64) {
-        self.mmu.cart.update_rtc_now(elapsed_secs);
+        if self.rtc_start.is_none() {
+            self.mmu.cart.update_rtc_now(elapsed_secs);
+        }
+    }
+
+    // Makes the RTC advance with the emulated cycles starting from `start_secs`
+    // instead of the host clock, so that runs are reproducible
+    pub fn use_emulated_rtc(&mut self, start_secs: u64) {
+        self.rtc_start = Some(start_secs);
+        self.rtc_cycles = 0;
+        self.mmu.cart.update_rtc_now(start_secs);
     }
 }
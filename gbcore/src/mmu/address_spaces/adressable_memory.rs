@@ -1,4 +1,6 @@
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
 pub struct AdressableMemory {
     memory: Vec<u8>,
@@ -36,3 +38,14 @@ impl Addressable for AdressableMemory {
         self.memory[(location - self.start) as usize]
     }
 }
+
+impl Stateful for AdressableMemory {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.memory);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        state.read_bytes_into(&mut self.memory)?;
+        Ok(())
+    }
+}
@@ -12,6 +12,7 @@ pub struct Header {
     pub cart_type: u8,
     pub rom_size: u8,
     ram_size: u8,
+    cgb_flag: u8,
 }
 
 impl Header {
@@ -32,9 +33,15 @@ impl Header {
             cart_type: rom[0x147],
             rom_size: rom[0x148],
             ram_size: rom[0x149],
+            cgb_flag: rom[0x143],
         })
     }
 
+    // Both the carts enhanced for the Color and the Color only ones
+    pub fn is_cgb(&self) -> bool {
+        (self.cgb_flag & 0x80) != 0
+    }
+
     pub fn get_rom_banks(&self) -> u16 {
         u16::pow(2, (self.rom_size + 1).into())
     }
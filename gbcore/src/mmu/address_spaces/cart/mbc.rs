@@ -1,6 +1,8 @@
 use crate::mmu::address_spaces::cart::header::Header;
 use crate::mmu::address_spaces::cart::header::RAM_BANK_SIZE;
 use crate::mmu::address_spaces::cart::header::ROM_BANK_SIZE;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
 pub enum ReadResult {
     Rom(usize),
@@ -272,3 +274,65 @@ impl Mbc {
         }
     }
 }
+
+impl Stateful for Mbc {
+    fn save_state(&self, state: &mut StateWriter) {
+        match self {
+            Mbc::NoMbc => state.write_u8(0x0),
+            Mbc::Mbc1(ram_enabled, s1, s2, mode) => {
+                state.write_u8(0x1);
+                state.write_bool(*ram_enabled);
+                state.write_u8(*s1);
+                state.write_u8(*s2);
+                state.write_bool(*mode);
+            }
+            Mbc::Mbc2(ram_enabled, s1) => {
+                state.write_u8(0x2);
+                state.write_bool(*ram_enabled);
+                state.write_u8(*s1);
+            }
+            Mbc::Mbc3(ram_enabled, s1, s2) => {
+                state.write_u8(0x3);
+                state.write_bool(*ram_enabled);
+                state.write_u8(*s1);
+                state.write_u8(*s2);
+            }
+            Mbc::Mbc5(ram_enabled, s1, s2) => {
+                state.write_u8(0x5);
+                state.write_bool(*ram_enabled);
+                state.write_u16(*s1);
+                state.write_u8(*s2);
+            }
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        let mbc: Mbc = match (state.read_u8()?, &self) {
+            (0x0, Mbc::NoMbc) => Mbc::NoMbc,
+            (0x1, Mbc::Mbc1(_, _, _, _)) => Mbc::Mbc1(
+                state.read_bool()?,
+                state.read_u8_max(0x1F)?,
+                state.read_u8_max(0x03)?,
+                state.read_bool()?,
+            ),
+            (0x2, Mbc::Mbc2(_, _)) => Mbc::Mbc2(state.read_bool()?, state.read_u8()?),
+            (0x3, Mbc::Mbc3(_, _, _)) => Mbc::Mbc3(
+                state.read_bool()?,
+                state.read_u8_max(0x7F)?,
+                state.read_u8_max(0x0B)?,
+            ),
+            (0x5, Mbc::Mbc5(_, _, _)) => Mbc::Mbc5(
+                state.read_bool()?,
+                state.read_u16_max(0x1FF)?,
+                state.read_u8_max(0x0F)?,
+            ),
+            (id, _) => {
+                return Err(
+                    format!("Save state MBC {:#04X} doesn't match the cartridge", id).into(),
+                )
+            }
+        };
+        *self = mbc;
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use crate::mmu::address_spaces::cart::mbc::ReadResult;
 use crate::mmu::address_spaces::cart::mbc::WriteResult;
 use crate::mmu::address_spaces::cart::rtc::Rtc;
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
 
 use std::error::Error;
 use std::str;
@@ -66,6 +67,10 @@ impl Cart {
         })
     }
 
+    pub fn is_cgb(&self) -> bool {
+        self.header.is_cgb()
+    }
+
     pub fn update_rtc_now(&mut self, elapsed_secs: u64) {
         if let Some(rtc) = &mut self.rtc {
             rtc.update_now(elapsed_secs);
@@ -80,6 +85,18 @@ impl Cart {
         }
     }
 
+    pub fn get_title(&self) -> &str {
+        &self.header.title
+    }
+
+    pub fn get_header_checksum(&self) -> u8 {
+        self.rom[0x14D]
+    }
+
+    pub fn get_global_checksum(&self) -> u16 {
+        ((self.rom[0x14E] as u16) << 8) | (self.rom[0x14F] as u16)
+    }
+
     pub fn dump_rtc(&self) -> Option<Vec<u8>> {
         if let Some(rtc) = &self.rtc {
             Some(rtc.serialize())
@@ -127,3 +144,32 @@ impl Addressable for Cart {
         }
     }
 }
+
+impl Stateful for Cart {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.mbc.save_state(state);
+        state.write_bool(self.ram.is_some());
+        if let Some(ram) = &self.ram {
+            state.write_bytes(ram);
+        }
+        state.write_bool(self.rtc.is_some());
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(state);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.mbc.load_state(state)?;
+        match (state.read_bool()?, &mut self.ram) {
+            (true, Some(ram)) => state.read_bytes_into(ram)?,
+            (false, None) => {}
+            _ => return Err("Save state RAM doesn't match the cartridge".into()),
+        }
+        match (state.read_bool()?, &mut self.rtc) {
+            (true, Some(rtc)) => rtc.load_state(state)?,
+            (false, None) => {}
+            _ => return Err("Save state RTC doesn't match the cartridge".into()),
+        }
+        Ok(())
+    }
+}
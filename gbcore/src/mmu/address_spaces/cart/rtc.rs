@@ -1,3 +1,5 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Clone)]
@@ -77,10 +79,12 @@ impl Rtc {
     }
 
     fn inc_d(&mut self, inc: u64) {
-        self.dl += inc as u16;
-        if self.dl > 0x1FF {
-            self.dl %= 0x1FF;
+        let days: u64 = (self.dl as u64).saturating_add(inc);
+        if days > 0x1FF {
+            self.dl = (days % 0x1FF) as u16;
             self.day_carry = true;
+        } else {
+            self.dl = days as u16;
         }
     }
 
@@ -237,3 +241,33 @@ impl Rtc {
         data
     }
 }
+
+impl Stateful for Rtc {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.serialize());
+        state.write_u64(
+            self.now
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_secs(),
+        );
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        let mut data: Vec<u8> = vec![0; 21];
+        state.read_bytes_into(&mut data)?;
+        let now: u64 = state.read_u64()?;
+        // Times past 2106 can't come from a real state and could overflow the clock
+        let last_update: u64 = u64::from_le_bytes(data[10..18].try_into().unwrap());
+        let dl: u16 = ((data[4] as u16) << 8) | (data[3] as u16);
+        if data[0] > 0x3F || data[1] > 0x3F || data[2] > 0x1F || dl > 0x1FF {
+            return Err("Invalid RTC registers in save state".into());
+        }
+        if now > u32::MAX as u64 || last_update > now {
+            return Err("Invalid RTC time in save state".into());
+        }
+        *self = Rtc::deserialize(&data);
+        self.now = UNIX_EPOCH + Duration::from_secs(now);
+        Ok(())
+    }
+}
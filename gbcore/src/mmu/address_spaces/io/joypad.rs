@@ -1,5 +1,8 @@
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
+#[derive(Clone, Copy)]
 pub struct JoypadState {
     pub up: bool,
     pub down: bool,
@@ -100,13 +103,8 @@ impl Joypad {
 
 impl Addressable for Joypad {
     fn write(&mut self, location: u16, byte: u8) {
-        if byte == 0x20 || byte == 0x10 {
-            self.action_selected = (byte & 0x20) == 0;
-            self.direction_selected = (byte & 0x10) == 0;
-        } else {
-            self.action_selected = false;
-            self.direction_selected = false;
-        }
+        self.action_selected = (byte & 0x20) == 0;
+        self.direction_selected = (byte & 0x10) == 0;
         self.compute_value();
     }
 
@@ -123,3 +121,44 @@ impl Addressable for Joypad {
             | self.p10
     }
 }
+
+impl Stateful for Joypad {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.state.up);
+        state.write_bool(self.state.down);
+        state.write_bool(self.state.left);
+        state.write_bool(self.state.right);
+        state.write_bool(self.state.a);
+        state.write_bool(self.state.b);
+        state.write_bool(self.state.start);
+        state.write_bool(self.state.select);
+        state.write_u8(self.p13);
+        state.write_u8(self.p12);
+        state.write_u8(self.p11);
+        state.write_u8(self.p10);
+        state.write_bool(self.interrupt);
+        state.write_bool(self.direction_selected);
+        state.write_bool(self.action_selected);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.state = JoypadState {
+            up: state.read_bool()?,
+            down: state.read_bool()?,
+            left: state.read_bool()?,
+            right: state.read_bool()?,
+            a: state.read_bool()?,
+            b: state.read_bool()?,
+            start: state.read_bool()?,
+            select: state.read_bool()?,
+        };
+        self.p13 = state.read_u8_max(1)?;
+        self.p12 = state.read_u8_max(1)?;
+        self.p11 = state.read_u8_max(1)?;
+        self.p10 = state.read_u8_max(1)?;
+        self.interrupt = state.read_bool()?;
+        self.direction_selected = state.read_bool()?;
+        self.action_selected = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
 pub struct Lcd {
     lcdc: u8,
@@ -182,7 +184,8 @@ impl Addressable for Lcd {
     fn write(&mut self, location: u16, byte: u8) {
         match location {
             0xFF40 => self.lcdc = byte,
-            0xFF41 => self.stat = byte,
+            // The mode and the LY=LYC flag are read only
+            0xFF41 => self.stat = (byte & 0x78) | (self.stat & 0x07),
             0xFF42 => self.scy = byte,
             0xFF43 => self.scx = byte,
             0xFF44 => {}
@@ -199,7 +202,7 @@ impl Addressable for Lcd {
     fn read(&self, location: u16) -> u8 {
         match location {
             0xFF40 => self.lcdc,
-            0xFF41 => self.stat,
+            0xFF41 => self.stat | 0x80,
             0xFF42 => self.scy,
             0xFF43 => self.scx,
             0xFF44 => self.ly,
@@ -213,3 +216,34 @@ impl Addressable for Lcd {
         }
     }
 }
+
+impl Stateful for Lcd {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.lcdc);
+        state.write_u8(self.stat);
+        state.write_u8(self.scy);
+        state.write_u8(self.scx);
+        state.write_u8(self.ly);
+        state.write_u8(self.lyc);
+        state.write_u8(self.bgp);
+        state.write_u8(self.obp0);
+        state.write_u8(self.obp1);
+        state.write_u8(self.wy);
+        state.write_u8(self.wx);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.lcdc = state.read_u8()?;
+        self.stat = state.read_u8()?;
+        self.scy = state.read_u8()?;
+        self.scx = state.read_u8()?;
+        self.ly = state.read_u8_max(153)?;
+        self.lyc = state.read_u8()?;
+        self.bgp = state.read_u8()?;
+        self.obp0 = state.read_u8()?;
+        self.obp1 = state.read_u8()?;
+        self.wy = state.read_u8()?;
+        self.wx = state.read_u8()?;
+        Ok(())
+    }
+}
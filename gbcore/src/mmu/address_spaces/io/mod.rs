@@ -1,34 +1,42 @@
+use crate::apu::Apu;
 use crate::mmu::address_spaces::adressable_memory::AdressableMemory;
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
 use joypad::Joypad;
 use lcd::Lcd;
+use palettes::ColorPalettes;
+use serial::Serial;
 use std::error::Error;
 use timers::Timers;
 
 pub mod joypad;
 mod lcd;
+mod palettes;
+mod serial;
 mod timers;
 
 pub struct Io {
     pub joypad: Joypad,
-    i1: AdressableMemory,
+    pub serial: Serial,
     pub timers: Timers,
-    i2: AdressableMemory,
+    pub apu: Apu,
     pub lcd: Lcd,
+    pub bg_palettes: ColorPalettes,
+    pub obj_palettes: ColorPalettes,
     i3: AdressableMemory,
     pub if_flag: u8,
 }
 
 impl Io {
     pub fn new() -> Result<Io, Box<dyn Error>> {
-        let mut i1: AdressableMemory = AdressableMemory::new(0xFF01, 0xFF02)?;
-        i1.write(0xFF02, 0x7E);
         Ok(Self {
             joypad: Joypad::new(),
-            i1: i1,
+            serial: Serial::new(),
             timers: Timers::new(),
-            i2: AdressableMemory::new(0xFF10, 0xFF3F)?,
+            apu: Apu::new(),
             lcd: Lcd::new(),
+            bg_palettes: ColorPalettes::new(),
+            obj_palettes: ColorPalettes::new(),
             i3: AdressableMemory::new(0xFF4C, 0xFF7F)?,
             if_flag: 0xE1,
         })
@@ -79,22 +87,15 @@ impl Addressable for Io {
     fn write(&mut self, location: u16, byte: u8) {
         match location {
             0xFF00 => self.joypad.write(location, byte),
-            0xFF01..=0xFF02 => {
-                //TODO serial implementation
-                if location == 0xFF02 && byte == 0x81 {
-                    self.i1.write(0xff02, 0x01);
-                    self.i1.write(0xff01, 0xFF);
-                    self.request_serial_interrupt();
-                } else {
-                    self.i1.write(location, byte);
-                }
-            }
+            0xFF01..=0xFF02 => self.serial.write(location, byte),
             0xFF03 => {}
             0xFF04..=0xFF07 => self.timers.write(location, byte),
             0xFF08..=0xFF0E => {}
             0xFF0F => self.if_flag = byte,
-            0xFF10..=0xFF3F => self.i2.write(location, byte),
+            0xFF10..=0xFF3F => self.apu.write(location, byte),
             0xFF40..=0xFF4B => self.lcd.write(location, byte),
+            0xFF68..=0xFF69 => self.bg_palettes.write(location, byte),
+            0xFF6A..=0xFF6B => self.obj_palettes.write(location, byte),
             0xFF4C..=0xFF7F => self.i3.write(location, byte),
             _ => panic!("IO unsupported write to {:#04X}", location),
         }
@@ -103,15 +104,44 @@ impl Addressable for Io {
     fn read(&self, location: u16) -> u8 {
         match location {
             0xFF00 => self.joypad.read(location),
-            0xFF01..=0xFF02 => self.i1.read(location),
+            0xFF01..=0xFF02 => self.serial.read(location),
             0xFF03 => 0x00,
             0xFF04..=0xFF07 => self.timers.read(location),
             0xFF08..=0xFF0E => 0x00,
             0xFF0F => self.if_flag,
-            0xFF10..=0xFF3F => self.i2.read(location),
+            0xFF10..=0xFF3F => self.apu.read(location),
             0xFF40..=0xFF4B => self.lcd.read(location),
+            0xFF68..=0xFF69 => self.bg_palettes.read(location),
+            0xFF6A..=0xFF6B => self.obj_palettes.read(location),
             0xFF4C..=0xFF7F => self.i3.read(location),
             _ => panic!("IO unsupported write to {:#04X}", location),
         }
     }
 }
+
+impl Stateful for Io {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        self.timers.save_state(state);
+        self.apu.save_state(state);
+        self.lcd.save_state(state);
+        self.bg_palettes.save_state(state);
+        self.obj_palettes.save_state(state);
+        self.i3.save_state(state);
+        state.write_u8(self.if_flag);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.timers.load_state(state)?;
+        self.apu.load_state(state)?;
+        self.lcd.load_state(state)?;
+        self.bg_palettes.load_state(state)?;
+        self.obj_palettes.load_state(state)?;
+        self.i3.load_state(state)?;
+        self.if_flag = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+const PALETTES_SIZE: usize = 64;
+
+// Color palette RAM holds 8 palettes of 4 little endian RGB555 colors. It's
+// accessed through an index register (BCPS/OCPS), bit 7 of which makes the
+// index increment after each write to the data register (BCPD/OCPD)
+pub struct ColorPalettes {
+    data: Vec<u8>,
+    index: u8,
+    auto_increment: bool,
+}
+
+impl ColorPalettes {
+    pub fn new() -> ColorPalettes {
+        ColorPalettes {
+            data: vec![0xFF; PALETTES_SIZE],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    pub fn get_color(&self, palette: u8, color: u8) -> u16 {
+        let index: usize = (palette as usize) * 8 + (color as usize) * 2;
+        u16::from_le_bytes([self.data[index], self.data[index + 1]]) & 0x7FFF
+    }
+}
+
+// The index register is at the even address, the data one at the odd address
+impl Addressable for ColorPalettes {
+    fn write(&mut self, location: u16, byte: u8) {
+        if location & 0x1 == 0 {
+            self.index = byte & 0x3F;
+            self.auto_increment = (byte & 0x80) != 0;
+        } else {
+            self.data[self.index as usize] = byte;
+            if self.auto_increment {
+                self.index = (self.index + 1) & 0x3F;
+            }
+        }
+    }
+
+    fn read(&self, location: u16) -> u8 {
+        if location & 0x1 == 0 {
+            0x40 | ((self.auto_increment as u8) << 7) | self.index
+        } else {
+            self.data[self.index as usize]
+        }
+    }
+}
+
+impl Stateful for ColorPalettes {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        state.write_u8(self.index);
+        state.write_bool(self.auto_increment);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        state.read_bytes_into(&mut self.data)?;
+        self.index = state.read_u8()? & 0x3F;
+        self.auto_increment = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::collections::VecDeque;
+use std::error::Error;
+
+// With the internal clock a bit is shifted every 512 cycles (8192 Hz)
+const BIT_CYCLES: u16 = 512;
+// Keep at most the last 4 KiB sent if nobody drains the output
+const OUTPUT_LIMIT: usize = 4096;
+
+pub struct Serial {
+    sb: u8,
+    sc: u8,
+    bits: u8,
+    timer: u16,
+    output: VecDeque<u8>,
+}
+
+impl Serial {
+    pub fn new() -> Serial {
+        Serial {
+            sb: 0,
+            sc: 0,
+            bits: 0,
+            timer: 0,
+            output: VecDeque::new(),
+        }
+    }
+
+    // Nothing is connected to the port, so the incoming bits are all 1.
+    // Returns true when a transfer completes
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        if (self.sc & 0x81) != 0x81 {
+            return false;
+        }
+
+        self.timer += cycles as u16;
+        while self.timer >= BIT_CYCLES {
+            self.timer -= BIT_CYCLES;
+            self.sb = (self.sb << 1) | 0x1;
+            self.bits += 1;
+            if self.bits == 8 {
+                self.sc &= 0x7F;
+                self.bits = 0;
+                self.timer = 0;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Appends the bytes sent through the port since the last call
+    pub fn drain_output(&mut self, output: &mut Vec<u8>) {
+        output.extend(self.output.drain(..));
+    }
+}
+
+impl Addressable for Serial {
+    fn write(&mut self, location: u16, byte: u8) {
+        match location {
+            0xFF01 => self.sb = byte,
+            0xFF02 => {
+                if (byte & 0x80) != 0 && (self.sc & 0x80) == 0 {
+                    if self.output.len() == OUTPUT_LIMIT {
+                        self.output.pop_front();
+                    }
+                    self.output.push_back(self.sb);
+                    self.bits = 0;
+                    self.timer = 0;
+                }
+                self.sc = byte & 0x81;
+            }
+            _ => panic!("SERIAL Unsupported write to {:#04X}", location),
+        }
+    }
+
+    fn read(&self, location: u16) -> u8 {
+        match location {
+            0xFF01 => self.sb,
+            0xFF02 => self.sc | 0x7E,
+            _ => panic!("SERIAL Unsupported read from {:#04X}", location),
+        }
+    }
+}
+
+impl Stateful for Serial {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc);
+        state.write_u8(self.bits);
+        state.write_u16(self.timer);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.sb = state.read_u8()?;
+        self.sc = state.read_u8()? & 0x81;
+        self.bits = state.read_u8_max(7)?;
+        self.timer = state.read_u16_max(BIT_CYCLES - 1)?;
+        Ok(())
+    }
+}
@@ -1,10 +1,19 @@
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
+// TIMA is incremented on the falling edge of a DIV bit selected by TAC, so writing
+// DIV or TAC can increment it too. After an overflow TIMA reads 0 for an M-cycle
+// before TMA is loaded and the interrupt is requested
 pub struct Timers {
     sysclk: u16,
     tima: u8,
     tma: u8,
     tac: u8,
+    // TIMA overflowed during the last M-cycle and is waiting to be reloaded
+    overflow: bool,
+    // TIMA was reloaded from TMA during the current M-cycle
+    reloaded: bool,
 }
 
 impl Timers {
@@ -14,49 +23,89 @@ impl Timers {
             tima: 0,
             tma: 0,
             tac: 0xF8,
+            overflow: false,
+            reloaded: false,
         }
     }
 
-    pub fn get_sysclk(&mut self) -> u16 {
-        self.sysclk
+    pub fn get_div(&self) -> u8 {
+        ((self.sysclk & 0xff00) >> 8) as u8
     }
 
-    pub fn inc_sysclk(&mut self) {
-        self.sysclk = self.sysclk.wrapping_add(1);
+    pub fn reset_div(&mut self) {
+        let signal: bool = self.get_signal();
+        self.sysclk = 0;
+        self.check_falling_edge(signal);
     }
 
-    pub fn inc_tima(&mut self) -> bool {
-        if self.tima == 0xff {
+    // Runs the given T-cycles, meant to be called once per M-cycle, returns whether
+    // the timer interrupt has to be requested
+    pub fn tick(&mut self, cycles: u8) -> bool {
+        let mut interrupt: bool = false;
+        self.reloaded = false;
+        if self.overflow {
+            self.overflow = false;
+            self.reloaded = true;
             self.tima = self.tma;
-            true
-        } else {
-            self.tima = self.tima.wrapping_add(1);
-            false
+            interrupt = true;
+        }
+
+        for _ in 0..cycles {
+            let signal: bool = self.get_signal();
+            self.sysclk = self.sysclk.wrapping_add(1);
+            self.check_falling_edge(signal);
         }
+        interrupt
+    }
+
+    fn get_signal(&self) -> bool {
+        let bit: u16 = match self.tac & 0x3 {
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            _ => 1 << 7,
+        };
+        (self.tac & 0x4) != 0 && (self.sysclk & bit) != 0
     }
 
-    pub fn get_tima_clock(&self) -> u16 {
-        match self.tac & 0x3 {
-            0b00 => 1024,
-            0b01 => 16,
-            0b10 => 64,
-            0b11 => 256,
-            _ => panic!("Unexpected tac value {:#b}", self.tac & 0x3),
+    fn check_falling_edge(&mut self, previous_signal: bool) {
+        if previous_signal && !self.get_signal() {
+            self.inc_tima();
         }
     }
 
-    pub fn get_tima_enabled(&self) -> bool {
-        (self.tac & 0x4) != 0
+    fn inc_tima(&mut self) {
+        if self.tima == 0xff {
+            self.tima = 0;
+            self.overflow = true;
+        } else {
+            self.tima = self.tima.wrapping_add(1);
+        }
     }
 }
 
 impl Addressable for Timers {
     fn write(&mut self, location: u16, byte: u8) {
         match location {
-            0xFF04 => self.sysclk = 0,
-            0xFF05 => self.tima = byte,
-            0xFF06 => self.tma = byte,
-            0xFF07 => self.tac = byte,
+            0xFF04 => self.reset_div(),
+            // Writing TIMA before the reload cancels it, while reloading the write is lost
+            0xFF05 => {
+                if !self.reloaded {
+                    self.tima = byte;
+                    self.overflow = false;
+                }
+            }
+            0xFF06 => {
+                self.tma = byte;
+                if self.reloaded {
+                    self.tima = byte;
+                }
+            }
+            0xFF07 => {
+                let signal: bool = self.get_signal();
+                self.tac = byte;
+                self.check_falling_edge(signal);
+            }
             _ => panic!("TIMERS Unsupported write to {:#04X}", location),
         }
     }
@@ -70,3 +119,24 @@ impl Addressable for Timers {
         }
     }
 }
+
+impl Stateful for Timers {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.sysclk);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+        state.write_bool(self.overflow);
+        state.write_bool(self.reloaded);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.sysclk = state.read_u16()?;
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = state.read_u8()?;
+        self.overflow = state.read_bool()?;
+        self.reloaded = state.read_bool()?;
+        Ok(())
+    }
+}
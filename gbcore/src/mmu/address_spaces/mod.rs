@@ -9,7 +9,7 @@ pub trait Addressable {
 
     fn read_16(&self, location: u16) -> u16 {
         let low: u8 = self.read(location);
-        let high: u8 = self.read(location + 1);
+        let high: u8 = self.read(location.wrapping_add(1));
         ((high as u16) << 8) + (low as u16)
     }
 }
@@ -1,5 +1,6 @@
 use crate::mmu::address_spaces::adressable_memory::AdressableMemory;
 use crate::mmu::address_spaces::Addressable;
+use crate::state::{StateReader, StateWriter, Stateful};
 use std::error::Error;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -11,6 +12,10 @@ pub struct Sprite {
     pub y_flip: bool,
     pub x_flip: bool,
     pub palette: bool,
+    // Color only attributes
+    pub bank: u8,
+    pub cgb_palette: u8,
+    pub id: u8,
 }
 
 pub struct Oam {
@@ -37,6 +42,9 @@ impl Oam {
             y_flip: (self.read(sprite_start + 3) & 0x40) != 0,
             x_flip: (self.read(sprite_start + 3) & 0x20) != 0,
             palette: (self.read(sprite_start + 3) & 0x10) != 0,
+            bank: (self.read(sprite_start + 3) & 0x08) >> 3,
+            cgb_palette: self.read(sprite_start + 3) & 0x07,
+            id: sprite_id,
         }
     }
 
@@ -54,3 +62,43 @@ impl Addressable for Oam {
         self.mem.read(location)
     }
 }
+
+impl Sprite {
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.y_position);
+        state.write_u8(self.x_position);
+        state.write_u8(self.tile_no);
+        state.write_bool(self.priority);
+        state.write_bool(self.y_flip);
+        state.write_bool(self.x_flip);
+        state.write_bool(self.palette);
+        state.write_u8(self.bank);
+        state.write_u8(self.cgb_palette);
+        state.write_u8(self.id);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> Result<Sprite, Box<dyn Error>> {
+        Ok(Sprite {
+            y_position: state.read_u8()?,
+            x_position: state.read_u8()?,
+            tile_no: state.read_u8()?,
+            priority: state.read_bool()?,
+            y_flip: state.read_bool()?,
+            x_flip: state.read_bool()?,
+            palette: state.read_bool()?,
+            bank: state.read_u8()? & 0x01,
+            cgb_palette: state.read_u8()? & 0x07,
+            id: state.read_u8_max(39)?,
+        })
+    }
+}
+
+impl Stateful for Oam {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.mem.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.mem.load_state(state)
+    }
+}
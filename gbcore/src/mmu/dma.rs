@@ -0,0 +1,104 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+const OAM_SIZE: u8 = 0xA0;
+
+// OAM DMA copies a byte per M-cycle, a transfer starts two M-cycles after the write
+// to 0xFF46 and a new write restarts it, the old one going on in the meantime
+pub struct Dma {
+    register: u8,
+    requested: Option<u8>,
+    starting: Option<u8>,
+    source: u16,
+    position: Option<u8>,
+    // Source address of the byte copied during the current M-cycle
+    current: Option<u16>,
+}
+
+impl Dma {
+    pub fn new() -> Dma {
+        Dma {
+            register: 0,
+            requested: None,
+            starting: None,
+            source: 0,
+            position: None,
+            current: None,
+        }
+    }
+
+    pub fn get_register(&self) -> u8 {
+        self.register
+    }
+
+    pub fn start(&mut self, register: u8) {
+        self.register = register;
+        self.requested = Some(register);
+    }
+
+    // Advances by an M-cycle, returns the source and destination of the byte to copy
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        if let Some(register) = self.starting.take() {
+            self.source = (register as u16) << 8;
+            self.position = Some(0);
+        }
+        self.starting = self.requested.take();
+
+        self.current = None;
+        let position: u8 = self.position?;
+        self.position = if position + 1 < OAM_SIZE {
+            Some(position + 1)
+        } else {
+            None
+        };
+        // Sources past WRAM read from its echo
+        let mut source: u16 = self.source | position as u16;
+        if source >= 0xE000 {
+            source -= 0x2000;
+        }
+        self.current = Some(source);
+        Some((source, 0xFE00 | position as u16))
+    }
+
+    pub fn get_current_source(&self) -> Option<u16> {
+        self.current
+    }
+}
+
+fn save_option(state: &mut StateWriter, value: Option<u8>) {
+    state.write_bool(value.is_some());
+    state.write_u8(value.unwrap_or(0));
+}
+
+fn load_option(state: &mut StateReader) -> Result<Option<u8>, Box<dyn Error>> {
+    let is_some: bool = state.read_bool()?;
+    let value: u8 = state.read_u8()?;
+    Ok(if is_some { Some(value) } else { None })
+}
+
+impl Stateful for Dma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        save_option(state, self.requested);
+        save_option(state, self.starting);
+        state.write_u16(self.source);
+        save_option(state, self.position);
+        state.write_bool(self.current.is_some());
+        state.write_u16(self.current.unwrap_or(0));
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.register = state.read_u8()?;
+        self.requested = load_option(state)?;
+        self.starting = load_option(state)?;
+        self.source = state.read_u16()? & 0xFF00;
+        self.position = load_option(state)?;
+        if self.position.is_some_and(|position| position >= OAM_SIZE) {
+            return Err("Invalid OAM DMA position in save state".into());
+        }
+        let is_current: bool = state.read_bool()?;
+        let current: u16 = state.read_u16()?;
+        self.current = if is_current { Some(current) } else { None };
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
+pub const HDMA_BLOCK_SIZE: u16 = 0x10;
+
+// VRAM DMA copies blocks of 16 bytes to VRAM. General purpose DMA copies all of
+// them at once, HBlank DMA a block at the start of each HBlank until it's done or
+// cancelled by writing 0 to bit 7 of HDMA5
+pub struct Hdma {
+    source: u16,
+    dest: u16,
+    // Blocks left minus one, as read from HDMA5
+    remaining: u8,
+    active: bool,
+    hblank: bool,
+    // Blocks to copy before the CPU can go on
+    pending: u8,
+}
+
+impl Hdma {
+    pub fn new() -> Hdma {
+        Hdma {
+            source: 0,
+            dest: 0x8000,
+            remaining: 0x7F,
+            active: false,
+            hblank: false,
+            pending: 0,
+        }
+    }
+
+    pub fn write(&mut self, location: u16, byte: u8) {
+        match location {
+            0xFF51 => self.source = (self.source & 0x00FF) | ((byte as u16) << 8),
+            0xFF52 => self.source = (self.source & 0xFF00) | (byte & 0xF0) as u16,
+            0xFF53 => self.dest = 0x8000 | (self.dest & 0x00FF) | (((byte & 0x1F) as u16) << 8),
+            0xFF54 => self.dest = (self.dest & 0xFF00) | (byte & 0xF0) as u16,
+            0xFF55 => self.start(byte),
+            _ => panic!("HDMA unsupported write to {:#04X}", location),
+        }
+    }
+
+    // The source and destination registers are write only
+    pub fn read(&self, location: u16) -> u8 {
+        match location {
+            0xFF51..=0xFF54 => 0xFF,
+            0xFF55 => ((!self.active as u8) << 7) | self.remaining,
+            _ => panic!("HDMA unsupported read from {:#04X}", location),
+        }
+    }
+
+    fn start(&mut self, byte: u8) {
+        if self.active && self.hblank && (byte & 0x80) == 0 {
+            self.active = false;
+            return;
+        }
+        self.remaining = byte & 0x7F;
+        self.active = true;
+        self.hblank = (byte & 0x80) != 0;
+        self.pending = if self.hblank { 0 } else { self.remaining + 1 };
+    }
+
+    // Called by the PPU when it enters HBlank
+    pub fn request_hblank_block(&mut self) {
+        if self.active && self.hblank {
+            self.pending = 1;
+        }
+    }
+
+    // Returns the source and destination of the next block to copy
+    pub fn next_block(&mut self) -> Option<(u16, u16)> {
+        if self.pending == 0 {
+            return None;
+        }
+        let block: (u16, u16) = (self.source, self.dest);
+        self.pending -= 1;
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.dest = 0x8000 | (self.dest.wrapping_add(HDMA_BLOCK_SIZE) & 0x1FF0);
+        if self.remaining == 0 {
+            self.active = false;
+            self.remaining = 0x7F;
+            self.pending = 0;
+        } else {
+            self.remaining -= 1;
+        }
+        Some(block)
+    }
+}
+
+impl Stateful for Hdma {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.source);
+        state.write_u16(self.dest);
+        state.write_u8(self.remaining);
+        state.write_bool(self.active);
+        state.write_bool(self.hblank);
+        state.write_u8(self.pending);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.source = state.read_u16()? & 0xFFF0;
+        self.dest = 0x8000 | (state.read_u16()? & 0x1FF0);
+        self.remaining = state.read_u8()? & 0x7F;
+        self.active = state.read_bool()?;
+        self.hblank = state.read_bool()?;
+        self.pending = state.read_u8()?.min(0x80);
+        Ok(())
+    }
+}
@@ -1,22 +1,37 @@
+use crate::state::{StateReader, StateWriter, Stateful};
 use address_spaces::adressable_memory::AdressableMemory;
 use address_spaces::cart::Cart;
 use address_spaces::io::Io;
 use address_spaces::oam::Oam;
 use address_spaces::Addressable;
+use dma::Dma;
+use hdma::{Hdma, HDMA_BLOCK_SIZE};
 use std::error::Error;
 use std::str;
 
 pub mod address_spaces;
+mod dma;
+mod hdma;
+
+const VRAM_BANKS: usize = 2;
+const WRAM_BANKS: usize = 8;
 
 pub struct Mmu {
     pub cart: Cart,
-    vram: AdressableMemory,
-    wram: AdressableMemory,
+    vram: Vec<AdressableMemory>,
+    // Bank 0 is fixed at 0xC000, the others are switched in at 0xD000
+    wram: Vec<AdressableMemory>,
     pub oam: Oam,
     pub io: Io,
-    dma: u8,
+    dma: Dma,
+    hdma: Hdma,
     hram: AdressableMemory,
     pub ie_flag: u8,
+    cgb: bool,
+    vram_bank: u8,
+    wram_bank: u8,
+    double_speed: bool,
+    speed_switch: bool,
 }
 
 impl Mmu {
@@ -25,26 +40,156 @@ impl Mmu {
         ram: Option<Vec<u8>>,
         rtc: Option<Vec<u8>>,
     ) -> Result<Mmu, Box<dyn Error>> {
+        let cart: Cart = Cart::new(rom, ram, rtc)?;
+        let mut vram: Vec<AdressableMemory> = Vec::new();
+        for _ in 0..VRAM_BANKS {
+            vram.push(AdressableMemory::new(0x8000, 0x9FFF)?);
+        }
+        let mut wram: Vec<AdressableMemory> = vec![AdressableMemory::new(0xC000, 0xCFFF)?];
+        for _ in 1..WRAM_BANKS {
+            wram.push(AdressableMemory::new(0xD000, 0xDFFF)?);
+        }
+
         Ok(Self {
-            cart: Cart::new(rom, ram, rtc)?,
-            vram: AdressableMemory::new(0x8000, 0x9FFF)?,
-            wram: AdressableMemory::new(0xC000, 0xDFFF)?,
+            cgb: cart.is_cgb(),
+            cart,
+            vram,
+            wram,
             oam: Oam::new()?,
             io: Io::new()?,
-            dma: 0,
+            dma: Dma::new(),
+            hdma: Hdma::new(),
             hram: AdressableMemory::new(0xFF80, 0xFFFE)?,
             ie_flag: 0,
+            vram_bank: 0,
+            wram_bank: 1,
+            double_speed: false,
+            speed_switch: false,
         })
     }
 
-    pub fn dma_run(&mut self) {
-        let source_msb: u16 = (self.dma as u16) << 8;
-        for i in 0x0..=0x9f {
-            let source_add: u16 = source_msb | i;
-            let dest_add: u16 = 0xFE00 | i;
-            self.write(dest_add, self.read(source_add));
+    // Carts made for the Color run in CGB mode, the others in DMG mode
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // In double speed mode the PPU and the APU get half of the CPU cycles of an M-cycle,
+    // their frame sequencer then follows the next DIV bit
+    pub fn get_dots_and_div(&self) -> (u8, u8) {
+        if self.double_speed {
+            (2, self.io.timers.get_div() >> 1)
+        } else {
+            (4, self.io.timers.get_div())
+        }
+    }
+
+    // Called by STOP, switches the CPU speed when it was requested through KEY1
+    pub fn switch_speed(&mut self) -> bool {
+        if self.speed_switch {
+            self.speed_switch = false;
+            self.double_speed = !self.double_speed;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Reads VRAM from the given bank, regardless of VBK, as the PPU does
+    pub fn read_vram(&self, bank: u8, location: u16) -> u8 {
+        self.vram[bank as usize].read(location)
+    }
+
+    fn get_wram_bank(&self, location: u16) -> usize {
+        if location < 0xD000 {
+            0
+        } else {
+            self.wram_bank as usize
+        }
+    }
+
+    fn write_cgb_register(&mut self, location: u16, byte: u8) {
+        if !self.cgb {
+            return;
+        }
+        match location {
+            0xFF4D => self.speed_switch = (byte & 0x01) != 0,
+            0xFF4F => self.vram_bank = byte & 0x01,
+            0xFF51..=0xFF55 => self.hdma.write(location, byte),
+            // Bank 0 can't be switched in, selecting it selects bank 1
+            0xFF70 => self.wram_bank = (byte & 0x07).max(1),
+            _ => panic!("MMU unsupported CGB write to {:#04X}", location),
+        }
+    }
+
+    fn read_cgb_register(&self, location: u16) -> u8 {
+        if !self.cgb {
+            return 0xFF;
+        }
+        match location {
+            0xFF4D => 0x7E | ((self.double_speed as u8) << 7) | (self.speed_switch as u8),
+            0xFF4F => 0xFE | self.vram_bank,
+            0xFF51..=0xFF55 => self.hdma.read(location),
+            0xFF70 => 0xF8 | self.wram_bank,
+            _ => panic!("MMU unsupported CGB read from {:#04X}", location),
+        }
+    }
+
+    // Runs an M-cycle of OAM DMA
+    pub fn dma_tick(&mut self) {
+        if let Some((source, dest)) = self.dma.tick() {
+            self.oam.write(dest, self.read(source));
         }
     }
+
+    // While OAM DMA runs the CPU can't reach OAM, and reading from the bus the DMA is
+    // using returns the byte being copied. None when the access isn't affected
+    pub fn get_dma_conflict(&self, location: u16) -> Option<u8> {
+        let source: u16 = self.dma.get_current_source()?;
+        match location {
+            0xFE00..=0xFEFF => Some(0xFF),
+            0xFF00..=0xFFFF => None,
+            _ if Mmu::is_video_bus(location) == Mmu::is_video_bus(source) => {
+                Some(self.read(source))
+            }
+            _ => None,
+        }
+    }
+
+    // Copies the next block of VRAM DMA to the selected VRAM bank, returns whether
+    // there was one
+    pub fn hdma_tick(&mut self) -> bool {
+        if let Some((source, dest)) = self.hdma.next_block() {
+            for i in 0..HDMA_BLOCK_SIZE {
+                let byte: u8 = self.read_hdma_source(source.wrapping_add(i));
+                self.vram[self.vram_bank as usize].write(dest + i, byte);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    // Sources in VRAM read open bus, the ones past WRAM read from cart RAM
+    fn read_hdma_source(&self, location: u16) -> u8 {
+        match location {
+            0x8000..=0x9FFF => 0xFF,
+            0xE000..=0xFFFF => self.cart.read(location - 0x4000),
+            _ => self.read(location),
+        }
+    }
+
+    // Called by the PPU when it enters HBlank
+    pub fn start_hblank(&mut self) {
+        self.hdma.request_hblank_block();
+    }
+
+    fn is_video_bus(location: u16) -> bool {
+        (0x8000..=0x9FFF).contains(&location)
+    }
 }
 
 impl Addressable for Mmu {
@@ -52,17 +197,19 @@ impl Addressable for Mmu {
         match location {
             0x0000..=0x3FFF => self.cart.write(location, byte),
             0x4000..=0x7FFF => self.cart.write(location, byte),
-            0x8000..=0x9FFF => self.vram.write(location, byte),
+            0x8000..=0x9FFF => self.vram[self.vram_bank as usize].write(location, byte),
             0xA000..=0xBFFF => self.cart.write(location, byte),
-            0xC000..=0xDFFF => self.wram.write(location, byte),
-            0xE000..=0xFDFF => self.wram.write(location - 0xE000 + 0xC000, byte),
+            0xC000..=0xDFFF => {
+                let bank: usize = self.get_wram_bank(location);
+                self.wram[bank].write(location, byte)
+            }
+            0xE000..=0xFDFF => self.write(location - 0x2000, byte),
             0xFE00..=0xFE9F => self.oam.write(location, byte),
             0xFEA0..=0xFEFF => {}
+            0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF70 => self.write_cgb_register(location, byte),
+            0xFF68..=0xFF6B if !self.cgb => {}
             0xFF00..=0xFF45 | 0xFF47..=0xFF7F => self.io.write(location, byte),
-            0xFF46 => {
-                self.dma = byte;
-                self.dma_run();
-            }
+            0xFF46 => self.dma.start(byte),
             0xFF80..=0xFFFE => self.hram.write(location, byte),
             0xFFFF => self.ie_flag = byte,
         }
@@ -72,16 +219,61 @@ impl Addressable for Mmu {
         match location {
             0x0000..=0x3FFF => self.cart.read(location),
             0x4000..=0x7FFF => self.cart.read(location),
-            0x8000..=0x9FFF => self.vram.read(location),
+            0x8000..=0x9FFF => self.vram[self.vram_bank as usize].read(location),
             0xA000..=0xBFFF => self.cart.read(location),
-            0xC000..=0xDFFF => self.wram.read(location),
-            0xE000..=0xFDFF => self.wram.read(location - 0xE000 + 0xC000),
+            0xC000..=0xDFFF => self.wram[self.get_wram_bank(location)].read(location),
+            0xE000..=0xFDFF => self.read(location - 0x2000),
             0xFE00..=0xFE9F => self.oam.read(location),
             0xFEA0..=0xFEFF => 0,
+            0xFF4D | 0xFF4F | 0xFF51..=0xFF55 | 0xFF70 => self.read_cgb_register(location),
+            0xFF68..=0xFF6B if !self.cgb => 0xFF,
             0xFF00..=0xFF45 | 0xFF47..=0xFF7F => self.io.read(location),
-            0xFF46 => self.dma,
+            0xFF46 => self.dma.get_register(),
             0xFF80..=0xFFFE => self.hram.read(location),
             0xFFFF => self.ie_flag,
         }
     }
 }
+
+impl Stateful for Mmu {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.cart.save_state(state);
+        for bank in &self.vram {
+            bank.save_state(state);
+        }
+        for bank in &self.wram {
+            bank.save_state(state);
+        }
+        self.oam.save_state(state);
+        self.io.save_state(state);
+        self.dma.save_state(state);
+        self.hdma.save_state(state);
+        self.hram.save_state(state);
+        state.write_u8(self.ie_flag);
+        state.write_u8(self.vram_bank);
+        state.write_u8(self.wram_bank);
+        state.write_bool(self.double_speed);
+        state.write_bool(self.speed_switch);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.cart.load_state(state)?;
+        for bank in self.vram.iter_mut() {
+            bank.load_state(state)?;
+        }
+        for bank in self.wram.iter_mut() {
+            bank.load_state(state)?;
+        }
+        self.oam.load_state(state)?;
+        self.io.load_state(state)?;
+        self.dma.load_state(state)?;
+        self.hdma.load_state(state)?;
+        self.hram.load_state(state)?;
+        self.ie_flag = state.read_u8()?;
+        self.vram_bank = state.read_u8()? & 0x01;
+        self.wram_bank = (state.read_u8()? & 0x07).max(1);
+        self.double_speed = state.read_bool()?;
+        self.speed_switch = state.read_bool()?;
+        Ok(())
+    }
+}
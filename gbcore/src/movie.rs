@@ -0,0 +1,141 @@
+use crate::mmu::address_spaces::io::joypad::JoypadState;
+use crate::state::{StateReader, StateWriter};
+use crate::Device;
+use std::error::Error;
+
+const MOVIE_MAGIC: &[u8; 4] = b"NTHM";
+const MOVIE_VERSION: u16 = 1;
+
+// The joypad inputs of every frame together with everything needed to recreate
+// the machine they were recorded on
+pub struct Movie {
+    rtc_start: u64,
+    ram: Option<Vec<u8>>,
+    rtc: Option<Vec<u8>>,
+    state: Option<Vec<u8>>,
+    inputs: Vec<u8>,
+}
+
+impl Movie {
+    // Starts recording on `device`, which from now on keeps its RTC on the emulated clock.
+    // Without `from_state` the device is expected to have just been powered on
+    pub fn new(device: &mut Device, rtc_start: u64, from_state: bool) -> Movie {
+        device.use_emulated_rtc(rtc_start);
+        Movie {
+            rtc_start,
+            ram: device.dump_ram(),
+            rtc: device.dump_rtc(),
+            state: if from_state {
+                Some(device.save_state())
+            } else {
+                None
+            },
+            inputs: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, joypad_state: &JoypadState) {
+        self.inputs.push(Movie::pack(joypad_state));
+    }
+
+    // Creates the device the movie has to be played back on
+    pub fn create_device(&self, rom: Vec<u8>) -> Result<Device, Box<dyn Error>> {
+        let mut device: Device = Device::new(rom, self.ram.clone(), self.rtc.clone())?;
+        device.use_emulated_rtc(self.rtc_start);
+        if let Some(state) = &self.state {
+            device.load_state(state)?;
+        }
+        Ok(device)
+    }
+
+    pub fn get_input(&self, frame: usize) -> Option<JoypadState> {
+        self.inputs.get(frame).map(|input| Movie::unpack(*input))
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut movie: StateWriter = StateWriter::new();
+        movie.write_bytes(MOVIE_MAGIC);
+        movie.write_u16(MOVIE_VERSION);
+        movie.write_u64(self.rtc_start);
+        for block in [&self.ram, &self.rtc, &self.state] {
+            movie.write_bool(block.is_some());
+            if let Some(data) = block {
+                movie.write_bytes(data);
+            }
+        }
+        movie.write_bytes(&self.inputs);
+        movie.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, Box<dyn Error>> {
+        let mut movie: StateReader = StateReader::new(data);
+        if movie.read_bytes().ok().as_deref() != Some(&MOVIE_MAGIC[..]) {
+            return Err("The data is not a movie".into());
+        }
+        let version: u16 = movie.read_u16()?;
+        if version != MOVIE_VERSION {
+            return Err(format!("Unsupported movie version {}", version).into());
+        }
+        let rtc_start: u64 = movie.read_u64()?;
+        let mut blocks: Vec<Option<Vec<u8>>> = Vec::new();
+        for _ in 0..3 {
+            blocks.push(if movie.read_bool()? {
+                Some(movie.read_bytes()?)
+            } else {
+                None
+            });
+        }
+        let inputs: Vec<u8> = movie.read_bytes()?;
+        if !movie.is_empty() {
+            return Err("Unexpected data at the end of the movie".into());
+        }
+        let state: Option<Vec<u8>> = blocks.pop().unwrap();
+        let rtc: Option<Vec<u8>> = blocks.pop().unwrap();
+        let ram: Option<Vec<u8>> = blocks.pop().unwrap();
+
+        Ok(Movie {
+            rtc_start,
+            ram,
+            rtc,
+            state,
+            inputs,
+        })
+    }
+
+    fn pack(joypad_state: &JoypadState) -> u8 {
+        [
+            joypad_state.up,
+            joypad_state.down,
+            joypad_state.left,
+            joypad_state.right,
+            joypad_state.a,
+            joypad_state.b,
+            joypad_state.start,
+            joypad_state.select,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |input, (i, pressed)| input | ((*pressed as u8) << i))
+    }
+
+    fn unpack(input: u8) -> JoypadState {
+        JoypadState {
+            up: (input & 0x01) != 0,
+            down: (input & 0x02) != 0,
+            left: (input & 0x04) != 0,
+            right: (input & 0x08) != 0,
+            a: (input & 0x10) != 0,
+            b: (input & 0x20) != 0,
+            start: (input & 0x40) != 0,
+            select: (input & 0x80) != 0,
+        }
+    }
+}
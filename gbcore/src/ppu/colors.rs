@@ -0,0 +1,94 @@
+use std::error::Error;
+
+// Colors shown for the four DMG shades, from the lightest to the darkest
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DmgPalette {
+    Grey,
+    GreenPeaSoup,
+    PocketGrey,
+    Light,
+    Custom([u32; 4]),
+}
+
+impl DmgPalette {
+    pub const PRESETS: [DmgPalette; 4] = [
+        DmgPalette::Grey,
+        DmgPalette::GreenPeaSoup,
+        DmgPalette::PocketGrey,
+        DmgPalette::Light,
+    ];
+
+    pub fn get_colors(&self) -> [u32; 4] {
+        match self {
+            DmgPalette::Grey => [0xFFFFFF, 0xAAAAAA, 0x555555, 0x000000],
+            DmgPalette::GreenPeaSoup => [0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F],
+            DmgPalette::PocketGrey => [0xC4CFA1, 0x8B956D, 0x4D533C, 0x1F1F1F],
+            DmgPalette::Light => [0x00B581, 0x009A71, 0x00694A, 0x004F3B],
+            DmgPalette::Custom(colors) => *colors,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            DmgPalette::Grey => "grey",
+            DmgPalette::GreenPeaSoup => "green",
+            DmgPalette::PocketGrey => "pocket",
+            DmgPalette::Light => "light",
+            DmgPalette::Custom(_) => "custom",
+        }
+    }
+
+    // Takes the name of a preset or four RRGGBB colors separated by commas, like
+    // "E0F8D0,88C070,346856,081820"
+    pub fn parse(text: &str) -> Result<DmgPalette, Box<dyn Error>> {
+        if let Some(preset) = DmgPalette::PRESETS
+            .iter()
+            .find(|preset| preset.get_name() == text)
+        {
+            return Ok(*preset);
+        }
+
+        let colors: Vec<&str> = text.split(',').map(|color| color.trim()).collect();
+        if colors.len() != 4 {
+            return Err(format!("Unknown palette \"{}\"", text).into());
+        }
+        let mut palette: [u32; 4] = [0; 4];
+        for (i, color) in colors.iter().enumerate() {
+            let color: &str = color.strip_prefix('#').unwrap_or(color);
+            palette[i] = match u32::from_str_radix(color, 16) {
+                Ok(value) if color.len() == 6 => value,
+                _ => return Err(format!("Invalid color \"{}\"", color).into()),
+            };
+        }
+        Ok(DmgPalette::Custom(palette))
+    }
+}
+
+// How Color RGB555 values are turned into RGB888
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorCorrection {
+    // Scales each component, as bright and saturated as a modern screen
+    Raw,
+    // Mixes the components as the Color LCD does, giving darker and washed out colors
+    Lcd,
+}
+
+impl ColorCorrection {
+    pub fn to_rgb888(&self, color: u16) -> u32 {
+        let r: u32 = (color & 0x1F) as u32;
+        let g: u32 = ((color >> 5) & 0x1F) as u32;
+        let b: u32 = ((color >> 10) & 0x1F) as u32;
+        match self {
+            ColorCorrection::Raw => {
+                let scale = |component: u32| -> u32 { (component << 3) | (component >> 2) };
+                (scale(r) << 16) | (scale(g) << 8) | scale(b)
+            }
+            ColorCorrection::Lcd => {
+                let mix = |component: u32| -> u32 { component.min(960) >> 2 };
+                (mix(r * 26 + g * 4 + b * 2) << 16)
+                    | (mix(g * 24 + b * 8) << 8)
+                    | mix(r * 6 + g * 4 + b * 22)
+            }
+        }
+    }
+}
@@ -1,11 +1,15 @@
 use crate::mmu::address_spaces::oam::Sprite;
 use crate::ppu::pixel_fetcher::Pixel;
 use crate::ppu::pixel_fetcher::Pixelfetcher;
+use crate::state::{StateReader, StateWriter, Stateful};
 use crate::Mmu;
+use colors::{ColorCorrection, DmgPalette};
 use pixel_fetcher::bg_fetcher::BgFetcher;
 use pixel_fetcher::sprite_fetcher::SpriteFetcher;
 use pixel_fetcher::Palette;
+use std::error::Error;
 
+pub mod colors;
 mod pixel_fetcher;
 
 pub struct LcdBuffer {
@@ -19,7 +23,6 @@ enum PpuState {
     PixelTransfer,
     HBlank,
     Vblank,
-    Quirk,
 }
 
 pub struct Ppu {
@@ -35,10 +38,26 @@ pub struct Ppu {
     bg_fetcher: BgFetcher,
     sprite_fetcher: SpriteFetcher,
     needs_reset: bool,
+    // Dots elapsed in the current line
     ticks: u16,
+    // Dots left before the next pixel is output, where the mode 3 penalties are spent
+    stall: u8,
+    // Background and window tiles in which a sprite already waited for the fetch
+    stalled_tiles: Vec<(bool, i16)>,
+    // The first line after turning the LCD on skips mode 2
+    first_line: bool,
+    // Output colors, they're settings of the frontend and aren't saved in states
+    dmg_colors: [u32; 4],
+    color_correction: ColorCorrection,
 }
 
-const PALETTE: &'static [u32] = &[0xffffff, 0xaaaaaa, 0x555555, 0x000000];
+const LINE_DOTS: u16 = 456;
+const OAM_SEARCH_DOTS: u16 = 80;
+// Mode 3 lasts a dot per pixel output or discarded for the fine scroll, plus these
+// penalties. The first tile of the line is fetched twice before any pixel comes out
+const LINE_START_DOTS: u8 = 12;
+const WINDOW_START_DOTS: u8 = 6;
+const SPRITE_FETCH_DOTS: u8 = 6;
 
 impl Ppu {
     pub fn new() -> Ppu {
@@ -56,13 +75,47 @@ impl Ppu {
             sprite_fetcher: SpriteFetcher::new(),
             needs_reset: false,
             ticks: 0,
+            stall: 0,
+            stalled_tiles: Vec::new(),
+            first_line: false,
+            dmg_colors: DmgPalette::Grey.get_colors(),
+            color_correction: ColorCorrection::Raw,
         }
     }
 
+    pub fn set_dmg_palette(&mut self, palette: DmgPalette) {
+        self.dmg_colors = palette.get_colors();
+    }
+
+    pub fn set_color_correction(&mut self, color_correction: ColorCorrection) {
+        self.color_correction = color_correction;
+    }
+
+    // The CPU can't reach VRAM while pixels are drawn
+    pub fn is_vram_blocked(&self, mmu: &Mmu) -> bool {
+        self.is_drawing(mmu) && self.state == PpuState::PixelTransfer
+    }
+
+    // OAM is also blocked while it's searched for sprites
+    pub fn is_oam_blocked(&self, mmu: &Mmu) -> bool {
+        self.is_drawing(mmu)
+            && ((self.state == PpuState::OamSearch && !self.first_line)
+                || self.state == PpuState::PixelTransfer)
+    }
+
+    // The first line after turning the LCD on reports mode 0 without being in HBlank
+    pub fn is_in_hblank(&self, mmu: &Mmu) -> bool {
+        self.is_drawing(mmu) && self.state == PpuState::HBlank
+    }
+
+    fn is_drawing(&self, mmu: &Mmu) -> bool {
+        mmu.io.lcd.is_display_enabled() && !self.needs_reset
+    }
+
     fn handle_stat(&mut self, mmu: &mut Mmu) {
         let stat: bool = (mmu.io.lcd.ly_equal_lyc_stat_enabled()
             && mmu.io.lcd.get_ly() == mmu.io.lcd.get_lyc())
-            || (mmu.io.lcd.oam_stat_enabled() && &self.state == &PpuState::OamSearch)
+            || (mmu.io.lcd.oam_stat_enabled() && self.is_oam_stat_mode(mmu))
             || (mmu.io.lcd.vblank_stat_enabled() && &self.state == &PpuState::Vblank)
             || (mmu.io.lcd.hblank_stat_enabled() && &self.state == &PpuState::HBlank);
         if stat && !self.old_stat {
@@ -71,6 +124,16 @@ impl Ppu {
         self.old_stat = stat;
     }
 
+    // Mode 2 interrupts also fire when VBlank starts, but not on the first line after
+    // turning the LCD on, where STAT reports mode 0 instead
+    fn is_oam_stat_mode(&self, mmu: &Mmu) -> bool {
+        match self.state {
+            PpuState::OamSearch => !self.first_line,
+            PpuState::Vblank => mmu.io.lcd.get_ly() == 144 && self.ticks == 0,
+            _ => false,
+        }
+    }
+
     pub fn tick(&mut self, mmu: &mut Mmu, lcd_buffer: &mut LcdBuffer, new_ticks: u8) {
         if !mmu.io.lcd.is_display_enabled() {
             self.needs_reset = true;
@@ -90,39 +153,59 @@ impl Ppu {
             match &self.state {
                 PpuState::OamSearch => self.oam_search(mmu),
                 PpuState::PixelTransfer => self.pixel_transfer(mmu, &mut lcd_buffer.buffer),
-                PpuState::HBlank => self.h_blank(mmu),
+                PpuState::HBlank => {}
                 PpuState::Vblank => self.v_blank(mmu),
-                PpuState::Quirk => self.quirk(mmu),
             }
             self.ticks += 1;
+            if self.ticks == LINE_DOTS {
+                self.ticks = 0;
+                self.end_line(mmu);
+            }
+        }
+    }
+
+    fn end_line(&mut self, mmu: &mut Mmu) {
+        self.first_line = false;
+        let ly: u8 = mmu.io.lcd.get_ly();
+        if self.state != PpuState::Vblank {
+            mmu.io.lcd.inc_ly(1);
+            if ly < 143 {
+                self.change_state(mmu, PpuState::OamSearch);
+            } else {
+                mmu.io.request_vblank_interrupt();
+                self.change_state(mmu, PpuState::Vblank);
+            }
+        } else if ly == 0 {
+            // LY already went back to 0 during line 153
+            self.window_line_counter = 0;
+            self.wy_equal_ly = false;
+            self.change_state(mmu, PpuState::OamSearch);
+        } else {
+            mmu.io.lcd.inc_ly(1);
         }
     }
 
     fn oam_search(&mut self, mmu: &mut Mmu) {
         if self.ticks % 2 == 0 && self.sprites.len() < 10 {
-            let sprite_id: u8 = if self.ticks > 0 {
-                ((self.ticks as u8) / 2) - 1
-            } else {
-                0
-            };
+            let sprite_id: u8 = (self.ticks / 2) as u8;
             let sprite: Sprite = mmu.oam.get_sprite(sprite_id);
             let sprite_height: u8 = mmu.io.lcd.get_sprite_size();
             let ly: u8 = mmu.io.lcd.get_ly() + 16;
 
-            if sprite.x_position > 0
-                && ly >= sprite.y_position
-                && ly < (sprite.y_position + sprite_height)
+            // Sprites out of the screen horizontally still count toward the limit
+            if ly >= sprite.y_position
+                && (ly as u16) < (sprite.y_position as u16 + sprite_height as u16)
             {
                 self.sprites.push(sprite);
             }
         }
 
-        if self.ticks == 80 {
-            self.change_state(mmu, PpuState::PixelTransfer, true);
-
+        if self.ticks == OAM_SEARCH_DOTS - 1 {
             if mmu.io.lcd.get_ly() == mmu.io.lcd.wy {
                 self.wy_equal_ly = true;
             }
+            self.stall = LINE_START_DOTS;
+            self.change_state(mmu, PpuState::PixelTransfer);
         }
     }
 
@@ -132,27 +215,49 @@ impl Ppu {
         }
     }
 
+    // Among the sprites reached by the output the one with the lowest X is fetched
+    // first, then the one found first in OAM
     fn get_current_sprite(&mut self) -> Option<Sprite> {
-        let target_pos = self.x_position.wrapping_add(8);
-        let mut res: Option<Sprite> = None;
-
-        for i in 0..self.sprites.len() {
-            if res == None && self.sprites[i].x_position <= target_pos {
-                res = Some(self.sprites[i]);
-                self.sprites.remove(i);
+        let target_pos: u8 = self.x_position + 8;
+        let mut current: Option<usize> = None;
+
+        for (i, sprite) in self.sprites.iter().enumerate() {
+            if sprite.x_position <= target_pos {
+                current = match current {
+                    Some(j) if self.sprites[j].x_position <= sprite.x_position => Some(j),
+                    _ => Some(i),
+                };
             }
         }
-        res
+        current.map(|i| self.sprites.remove(i))
     }
 
+    // Every dot outputs or discards a pixel unless the output is stalled, so that the
+    // registers written by the CPU apply from the pixel drawn at that dot. The mode
+    // ends with the last pixel
     fn pixel_transfer(&mut self, mmu: &mut Mmu, buffer: &mut Vec<u32>) {
-        if self.sprite_fetcher.done {
-            if let Some(sprite) = self.get_current_sprite() {
-                self.bg_fetcher.restart();
-                self.current_sprite = Some(sprite);
-                self.sprite_fetcher.done = false;
+        if self.stall == 0 {
+            while self.x_position < 160 && self.stall == 0 {
+                if self.draw(mmu, buffer) {
+                    break;
+                }
             }
         }
+        if self.stall > 0 {
+            self.stall -= 1;
+        }
+
+        if self.x_position == 160 {
+            self.handle_scanline_end();
+            self.change_state(mmu, PpuState::HBlank);
+        }
+    }
+
+    // Runs the fetchers a step, returns whether a pixel was output or discarded
+    fn draw(&mut self, mmu: &mut Mmu, buffer: &mut Vec<u32>) -> bool {
+        if self.sprite_fetcher.done {
+            self.fetch_next_sprite(mmu);
+        }
 
         if self.sprite_fetcher.done {
             if mmu.io.lcd.is_window_enabled()
@@ -162,13 +267,16 @@ impl Ppu {
             {
                 self.window_line = true;
                 self.bg_fetcher.switch_to_window_mode();
+                self.stall += WINDOW_START_DOTS;
+                return false;
             }
             self.bg_fetcher.tick(mmu, self.window_line_counter);
         } else if let Some(sprite) = self.current_sprite {
             self.sprite_fetcher.tick(mmu, &sprite);
         }
 
-        if self.sprite_fetcher.done {
+        // Every sprite at the current position is merged before a pixel is output
+        if self.sprite_fetcher.done && !self.fetch_next_sprite(mmu) {
             let pixel: Option<Pixel> = self.bg_fetcher.shift();
 
             if let Some(bg_pixel) = pixel {
@@ -181,12 +289,41 @@ impl Ppu {
                 } else {
                     self.discarded_pixels += 1;
                 }
+                return true;
             }
         }
+        false
+    }
 
-        if self.x_position == 160 {
-            self.handle_scanline_end();
-            self.change_state(mmu, PpuState::HBlank, false);
+    fn fetch_next_sprite(&mut self, mmu: &Mmu) -> bool {
+        if let Some(sprite) = self.get_current_sprite() {
+            self.stall_for_sprite(mmu, &sprite);
+            self.bg_fetcher.restart();
+            self.current_sprite = Some(sprite);
+            self.sprite_fetcher.done = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    // A sprite stalls the output while it's fetched and until the background or window
+    // tile it sits in is fetched, a tile only causes that wait for the first sprite in it
+    fn stall_for_sprite(&mut self, mmu: &Mmu, sprite: &Sprite) {
+        if !mmu.io.lcd.is_sprite_enabled() {
+            return;
+        }
+        let x: i16 = (sprite.x_position as i16) - 8;
+        let position: i16 = if self.window_line {
+            x - ((mmu.io.lcd.wx as i16) - 7)
+        } else {
+            x + mmu.io.lcd.scx as i16
+        };
+        self.stall += SPRITE_FETCH_DOTS;
+        let tile: (bool, i16) = (self.window_line, position.div_euclid(8));
+        if !self.stalled_tiles.contains(&tile) {
+            self.stalled_tiles.push(tile);
+            self.stall += (5 - position.rem_euclid(8)).max(0) as u8;
         }
     }
 
@@ -196,25 +333,31 @@ impl Ppu {
         bg_pixel: Pixel,
         option_sprite_pixel: Option<Pixel>,
     ) -> u32 {
+        if mmu.is_cgb() {
+            return self.merge_cgb_pixels(mmu, bg_pixel, option_sprite_pixel);
+        }
+
         let bg_color: u32 = if mmu.io.lcd.is_bg_window_enabled() {
-            PALETTE[mmu.io.lcd.get_bgp_index(bg_pixel.color) as usize]
+            self.dmg_colors[mmu.io.lcd.get_bgp_index(bg_pixel.color) as usize]
         } else {
-            0xffffff
+            self.dmg_colors[0]
         };
 
         if let Some(sprite_pixel) = option_sprite_pixel {
             if !mmu.io.lcd.is_sprite_enabled()
                 || sprite_pixel.color == 0
-                || (sprite_pixel.bg_priority && bg_pixel.color > 0)
+                || (sprite_pixel.bg_priority
+                    && mmu.io.lcd.is_bg_window_enabled()
+                    && bg_pixel.color > 0)
             {
                 bg_color
             } else {
                 match sprite_pixel.palette {
                     Palette::OBP0 => {
-                        PALETTE[mmu.io.lcd.get_obp0_index(sprite_pixel.color) as usize]
+                        self.dmg_colors[mmu.io.lcd.get_obp0_index(sprite_pixel.color) as usize]
                     }
                     Palette::OBP1 => {
-                        PALETTE[mmu.io.lcd.get_obp1_index(sprite_pixel.color) as usize]
+                        self.dmg_colors[mmu.io.lcd.get_obp1_index(sprite_pixel.color) as usize]
                     }
                     _ => panic!("Invalid palette for sprite"),
                 }
@@ -224,55 +367,88 @@ impl Ppu {
         }
     }
 
-    fn h_blank(&mut self, mmu: &mut Mmu) {
-        if self.ticks == 456 {
-            let new_state: PpuState = if mmu.io.lcd.get_ly() < 143 {
-                PpuState::OamSearch
-            } else {
-                PpuState::Vblank
-            };
-            mmu.io.lcd.inc_ly(1);
-            self.change_state(mmu, new_state, true);
-        }
-    }
-
-    fn v_blank(&mut self, mmu: &mut Mmu) {
-        if self.ticks == 1 {
-            mmu.io.request_vblank_interrupt();
-        }
-
-        if (self.ticks % 456) == 0 {
-            mmu.io.lcd.inc_ly(1);
+    // On the Color LCDC bit 0 doesn't hide the background, it lets sprites go over it
+    // whatever the priority set by the tile attributes and by OAM
+    fn merge_cgb_pixels(
+        &mut self,
+        mmu: &Mmu,
+        bg_pixel: Pixel,
+        option_sprite_pixel: Option<Pixel>,
+    ) -> u32 {
+        if let Some(sprite_pixel) = option_sprite_pixel {
+            let bg_over_sprite: bool = mmu.io.lcd.is_bg_window_enabled()
+                && bg_pixel.color > 0
+                && (bg_pixel.bg_priority || sprite_pixel.bg_priority);
+            if let Palette::CgbObj(palette) = sprite_pixel.palette {
+                if mmu.io.lcd.is_sprite_enabled() && sprite_pixel.color > 0 && !bg_over_sprite {
+                    let color: u16 = mmu.io.obj_palettes.get_color(palette, sprite_pixel.color);
+                    return self.color_correction.to_rgb888(color);
+                }
+            }
         }
 
-        if mmu.io.lcd.get_ly() == 153 {
-            self.change_state(mmu, PpuState::Quirk, true);
+        match bg_pixel.palette {
+            Palette::CgbBg(palette) => self
+                .color_correction
+                .to_rgb888(mmu.io.bg_palettes.get_color(palette, bg_pixel.color)),
+            _ => panic!("Invalid palette for a Color background"),
         }
     }
 
-    fn quirk(&mut self, mmu: &mut Mmu) {
-        if self.ticks == 4 {
-            self.reset(mmu);
+    // LY reads 153 only for the first M-cycle of the last line, then 0
+    fn v_blank(&mut self, mmu: &mut Mmu) {
+        if self.ticks == 3 && mmu.io.lcd.get_ly() == 153 {
+            mmu.io.lcd.set_ly(0);
         }
     }
 
-    fn change_state(&mut self, mmu: &mut Mmu, state: PpuState, reset_ticks: bool) {
+    fn change_state(&mut self, mmu: &mut Mmu, state: PpuState) {
         self.state = state;
-        if reset_ticks {
-            self.ticks = 0;
-        }
         match &self.state {
             PpuState::OamSearch => {
                 self.line_reset();
-                mmu.io.lcd.set_oam_ppu_mode()
+                if self.first_line {
+                    mmu.io.lcd.set_hblank_ppu_mode()
+                } else {
+                    mmu.io.lcd.set_oam_ppu_mode()
+                }
             }
             PpuState::PixelTransfer => mmu.io.lcd.set_draw_ppu_mode(),
-            PpuState::HBlank => mmu.io.lcd.set_hblank_ppu_mode(),
+            PpuState::HBlank => {
+                mmu.io.lcd.set_hblank_ppu_mode();
+                mmu.start_hblank();
+            }
             PpuState::Vblank => mmu.io.lcd.set_vblank_ppu_mode(),
-            _ => {}
         }
     }
 
+    // Checks the loaded state against LY, which is loaded with the MMU. The sprites found
+    // for the line have to cover it, as their row is computed from it
+    pub fn check_state(&self, mmu: &Mmu) -> Result<(), Box<dyn Error>> {
+        if !self.is_drawing(mmu) {
+            return Ok(());
+        }
+        let ly: u8 = mmu.io.lcd.get_ly();
+        let ly_valid: bool = match self.state {
+            PpuState::Vblank => ly >= 144 || ly == 0,
+            _ => ly < 144,
+        };
+        let sprites_valid: bool = match self.state {
+            PpuState::OamSearch | PpuState::PixelTransfer => self
+                .sprites
+                .iter()
+                .chain(self.current_sprite.iter())
+                .all(|sprite| {
+                    ly + 16 >= sprite.y_position && (ly as u16 + 16) < sprite.y_position as u16 + 16
+                }),
+            _ => true,
+        };
+        if !ly_valid || !sprites_valid {
+            return Err(format!("The PPU state doesn't match LY {} in save state", ly).into());
+        }
+        Ok(())
+    }
+
     fn line_reset(&mut self) {
         self.sprites.clear();
         self.current_sprite = None;
@@ -281,14 +457,100 @@ impl Ppu {
         self.discarded_pixels = 0;
         self.window_line = false;
         self.x_position = 0;
+        self.stall = 0;
+        self.stalled_tiles.clear();
     }
 
     fn reset(&mut self, mmu: &mut Mmu) {
-        self.change_state(mmu, PpuState::OamSearch, true);
-        self.line_reset();
+        self.ticks = 0;
+        self.first_line = true;
+        self.change_state(mmu, PpuState::OamSearch);
         self.wy_equal_ly = false;
         mmu.io.lcd.set_ly(0);
         self.window_line_counter = 0;
         self.needs_reset = false;
     }
 }
+
+impl Stateful for Ppu {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(match self.state {
+            PpuState::OamSearch => 0x0,
+            PpuState::PixelTransfer => 0x1,
+            PpuState::HBlank => 0x2,
+            PpuState::Vblank => 0x3,
+        });
+        state.write_u8(self.sprites.len() as u8);
+        for sprite in &self.sprites {
+            sprite.save_state(state);
+        }
+        state.write_bool(self.current_sprite.is_some());
+        if let Some(sprite) = &self.current_sprite {
+            sprite.save_state(state);
+        }
+        state.write_u8(self.window_line_counter);
+        state.write_bool(self.wy_equal_ly);
+        state.write_u8(self.x_position);
+        state.write_u8(self.discarded_pixels);
+        state.write_bool(self.window_line);
+        state.write_bool(self.old_stat);
+        self.bg_fetcher.save_state(state);
+        self.sprite_fetcher.save_state(state);
+        state.write_bool(self.needs_reset);
+        state.write_u16(self.ticks);
+        state.write_u8(self.stall);
+        state.write_u8(self.stalled_tiles.len() as u8);
+        for (window, tile) in &self.stalled_tiles {
+            state.write_bool(*window);
+            state.write_u16(*tile as u16);
+        }
+        state.write_bool(self.first_line);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.state = match state.read_u8()? {
+            0x0 => PpuState::OamSearch,
+            0x1 => PpuState::PixelTransfer,
+            0x2 => PpuState::HBlank,
+            0x3 => PpuState::Vblank,
+            value => return Err(format!("Invalid ppu state {:#04X}", value).into()),
+        };
+        self.sprites.clear();
+        for _ in 0..state.read_u8_max(10)? {
+            self.sprites.push(Sprite::load_state(state)?);
+        }
+        self.current_sprite = if state.read_bool()? {
+            Some(Sprite::load_state(state)?)
+        } else {
+            None
+        };
+        self.window_line_counter = state.read_u8_max(144)?;
+        self.wy_equal_ly = state.read_bool()?;
+        self.x_position = state.read_u8_max(160)?;
+        self.discarded_pixels = state.read_u8_max(7)?;
+        self.window_line = state.read_bool()?;
+        self.old_stat = state.read_bool()?;
+        self.bg_fetcher.load_state(state)?;
+        self.sprite_fetcher.load_state(state)?;
+        self.needs_reset = state.read_bool()?;
+        self.ticks = state.read_u16_max(LINE_DOTS - 1)?;
+        self.stall = state.read_u8_max(LINE_START_DOTS)?;
+        self.stalled_tiles.clear();
+        for _ in 0..state.read_u8_max(10)? {
+            self.stalled_tiles
+                .push((state.read_bool()?, state.read_u16()? as i16));
+        }
+        self.first_line = state.read_bool()?;
+
+        // The sprites are searched for during the first dots, then drawn
+        let ticks_valid: bool = match self.state {
+            PpuState::OamSearch => self.ticks < OAM_SEARCH_DOTS,
+            PpuState::PixelTransfer => self.ticks >= OAM_SEARCH_DOTS,
+            _ => true,
+        };
+        if !ticks_valid {
+            return Err(format!("Invalid PPU dot {} in save state", self.ticks).into());
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,19 @@
-use crate::mmu::address_spaces::Addressable;
 use crate::ppu::pixel_fetcher::pixel_fifo::standard_pixel_fifo::StandardPixelFifo;
 use crate::ppu::pixel_fetcher::pixel_fifo::PixelFifo;
 use crate::ppu::pixel_fetcher::FetchState;
 use crate::ppu::pixel_fetcher::Palette;
 use crate::ppu::pixel_fetcher::Pixel;
 use crate::ppu::pixel_fetcher::Pixelfetcher;
+use crate::state::{StateReader, StateWriter, Stateful};
 use crate::Mmu;
+use std::error::Error;
 
 pub struct BgFetcher {
     fifo: StandardPixelFifo,
     state: FetchState,
     tile_no: u8,
+    // On the Color the tile attributes are in VRAM bank 1 at the tile number address
+    attributes: u8,
     data_start_add: u16,
     data_low: u8,
     data_high: u8,
@@ -31,6 +34,7 @@ impl BgFetcher {
             fifo: StandardPixelFifo::with_capacity(8),
             state: FetchState::FetchNo,
             tile_no: 0,
+            attributes: 0,
             data_start_add: 0,
             data_low: 0,
             data_high: 0,
@@ -46,7 +50,7 @@ impl BgFetcher {
                 FetchState::FetchNo => self.fetch_no(mmu, window_line_counter),
                 FetchState::FetchDataLow => self.fetch_data_low(mmu, window_line_counter),
                 FetchState::FetchDataHigh => self.fetch_data_high(mmu),
-                FetchState::Push => self.push(),
+                FetchState::Push => self.push(mmu),
             }
         }
         self.ready = true;
@@ -82,18 +86,25 @@ impl BgFetcher {
             mmu.io.lcd.get_bg_tile_map().wrapping_add(offset)
         };
 
-        self.tile_no = mmu.read(tile_no_add);
+        self.tile_no = mmu.read_vram(0, tile_no_add);
+        self.attributes = if mmu.is_cgb() {
+            mmu.read_vram(1, tile_no_add)
+        } else {
+            0
+        };
         self.change_state(FetchState::FetchDataLow);
     }
 
     fn get_tile_data_start_address(&mut self, mmu: &Mmu, window_line_counter: u8) -> u16 {
-        let offset: u16 = if self.window {
-            (2u8.wrapping_mul(window_line_counter % 8)).into()
+        let mut line: u16 = if self.window {
+            (window_line_counter % 8).into()
         } else {
-            2u16.wrapping_mul(
-                ((mmu.io.lcd.get_ly() as u16).wrapping_add(mmu.io.lcd.scy as u16)) % 8,
-            )
+            ((mmu.io.lcd.get_ly() as u16).wrapping_add(mmu.io.lcd.scy as u16)) % 8
         };
+        if (self.attributes & 0x40) != 0 {
+            line = 7 - line;
+        }
+        let offset: u16 = 2 * line;
 
         let base_address: u16 = if mmu.io.lcd.get_tile_data() == 0x8000 {
             0x8000u16.wrapping_add(self.tile_no as u16 * 16)
@@ -106,27 +117,42 @@ impl BgFetcher {
 
     fn fetch_data_low(&mut self, mmu: &Mmu, window_line_counter: u8) {
         self.data_start_add = self.get_tile_data_start_address(mmu, window_line_counter);
-        self.data_low = mmu.read(self.data_start_add);
+        self.data_low = mmu.read_vram(self.get_bank(), self.data_start_add);
         self.change_state(FetchState::FetchDataHigh);
     }
 
     fn fetch_data_high(&mut self, mmu: &Mmu) {
-        self.data_high = mmu.read(self.data_start_add + 1);
+        self.data_high = mmu.read_vram(self.get_bank(), self.data_start_add + 1);
         self.change_state(FetchState::Push);
     }
 
-    fn push(&mut self) {
+    fn get_bank(&self) -> u8 {
+        (self.attributes & 0x08) >> 3
+    }
+
+    fn push(&mut self, mmu: &Mmu) {
         if self.fifo.len() == 0 {
+            let palette: Palette = if mmu.is_cgb() {
+                Palette::CgbBg(self.attributes & 0x07)
+            } else {
+                Palette::BGP
+            };
             for i in (0..8).rev() {
-                let mask: u8 = u8::pow(2, i);
-                let msb: u8 = (self.data_high & mask) >> i;
-                let lsb: u8 = (self.data_low & mask) >> i;
+                let bit: u8 = if (self.attributes & 0x20) != 0 {
+                    7 - i
+                } else {
+                    i
+                };
+                let mask: u8 = u8::pow(2, bit.into());
+                let msb: u8 = (self.data_high & mask) >> bit;
+                let lsb: u8 = (self.data_low & mask) >> bit;
                 let color: u8 = (msb << 1) | lsb;
                 self.fifo.push(Pixel {
                     color: color,
-                    palette: Palette::BGP,
+                    palette,
                     priority: false,
-                    bg_priority: false,
+                    bg_priority: (self.attributes & 0x80) != 0,
+                    oam_index: 0,
                 });
             }
             self.x_counter += 1;
@@ -146,6 +172,7 @@ impl BgFetcher {
         self.state = FetchState::FetchNo;
         self.data_start_add = 0;
         self.tile_no = 0;
+        self.attributes = 0;
         self.data_low = 0;
         self.data_high = 0;
         self.x_counter = 0;
@@ -158,3 +185,42 @@ impl BgFetcher {
         self.ready = false;
     }
 }
+
+impl Stateful for BgFetcher {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.fifo.save_state(state);
+        self.state.save_state(state);
+        state.write_u8(self.tile_no);
+        state.write_u8(self.attributes);
+        state.write_u16(self.data_start_add);
+        state.write_u8(self.data_low);
+        state.write_u8(self.data_high);
+        state.write_u8(self.x_counter);
+        state.write_bool(self.window);
+        state.write_bool(self.ready);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.fifo.load_state(state)?;
+        self.state.load_state(state)?;
+        self.tile_no = state.read_u8()?;
+        self.attributes = state.read_u8()?;
+        self.data_start_add = state.read_u16()?;
+        // The high byte of the tile data is read from the address saved with the low one
+        if self.state == FetchState::FetchDataHigh
+            && !(0x8000..0x9800).contains(&self.data_start_add)
+        {
+            return Err(format!(
+                "Invalid tile data address {:#06X} in save state",
+                self.data_start_add
+            )
+            .into());
+        }
+        self.data_low = state.read_u8()?;
+        self.data_high = state.read_u8()?;
+        self.x_counter = state.read_u8_max(32)?;
+        self.window = state.read_bool()?;
+        self.ready = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,3 +1,6 @@
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
+
 pub mod bg_fetcher;
 pub mod pixel_fifo;
 pub mod sprite_fetcher;
@@ -15,6 +18,8 @@ pub enum Palette {
     OBP0,
     OBP1,
     BGP,
+    CgbBg(u8),
+    CgbObj(u8),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -23,8 +28,72 @@ pub struct Pixel {
     pub palette: Palette,
     pub priority: bool,
     pub bg_priority: bool,
+    // Overlapping sprites are ordered by OAM index on the Color
+    pub oam_index: u8,
 }
 
 pub trait Pixelfetcher {
     fn shift(&mut self) -> Option<Pixel>;
 }
+
+impl Stateful for FetchState {
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(match self {
+            FetchState::FetchNo => 0x0,
+            FetchState::FetchDataLow => 0x1,
+            FetchState::FetchDataHigh => 0x2,
+            FetchState::Push => 0x3,
+        });
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        *self = match state.read_u8()? {
+            0x0 => FetchState::FetchNo,
+            0x1 => FetchState::FetchDataLow,
+            0x2 => FetchState::FetchDataHigh,
+            0x3 => FetchState::Push,
+            value => return Err(format!("Invalid fetch state {:#04X}", value).into()),
+        };
+        Ok(())
+    }
+}
+
+impl Pixel {
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.color);
+        state.write_u8(match self.palette {
+            Palette::OBP0 => 0x0,
+            Palette::OBP1 => 0x1,
+            Palette::BGP => 0x2,
+            Palette::CgbBg(_) => 0x3,
+            Palette::CgbObj(_) => 0x4,
+        });
+        state.write_u8(match self.palette {
+            Palette::CgbBg(palette) | Palette::CgbObj(palette) => palette,
+            _ => 0,
+        });
+        state.write_bool(self.priority);
+        state.write_bool(self.bg_priority);
+        state.write_u8(self.oam_index);
+    }
+
+    pub fn load_state(state: &mut StateReader) -> Result<Pixel, Box<dyn Error>> {
+        let color: u8 = state.read_u8_max(3)?;
+        let palette_type: u8 = state.read_u8()?;
+        let palette_number: u8 = state.read_u8()? & 0x07;
+        Ok(Pixel {
+            color,
+            palette: match palette_type {
+                0x0 => Palette::OBP0,
+                0x1 => Palette::OBP1,
+                0x2 => Palette::BGP,
+                0x3 => Palette::CgbBg(palette_number),
+                0x4 => Palette::CgbObj(palette_number),
+                value => return Err(format!("Invalid pixel palette {:#04X}", value).into()),
+            },
+            priority: state.read_bool()?,
+            bg_priority: state.read_bool()?,
+            oam_index: state.read_u8()?,
+        })
+    }
+}
@@ -1,5 +1,8 @@
 use crate::ppu::pixel_fetcher::pixel_fifo::PixelFifo;
+use crate::ppu::pixel_fetcher::pixel_fifo::{load_buffer, save_buffer};
 use crate::ppu::pixel_fetcher::Pixel;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
 pub struct MergePixelFifo {
     buffer: Vec<Option<Pixel>>,
@@ -7,6 +10,9 @@ pub struct MergePixelFifo {
     push_i: u8,
     pop_i: u8,
     len: u8,
+    // Pixels pushed since the current sprite started being merged
+    merged: u8,
+    by_oam_index: bool,
 }
 
 impl MergePixelFifo {
@@ -17,9 +23,19 @@ impl MergePixelFifo {
             len: 0,
             push_i: 0,
             pop_i: 0,
+            merged: 0,
+            by_oam_index: false,
         }
     }
 
+    // The pixels of a sprite are pushed over the ones still in the fifo, which
+    // belong to sprites with a lower X. On the Color the lowest OAM index wins instead
+    pub fn start_merge(&mut self, by_oam_index: bool) {
+        self.push_i = self.pop_i;
+        self.merged = 0;
+        self.by_oam_index = by_oam_index;
+    }
+
     pub fn full_clear(&mut self) {
         self.clear();
         for i in 0..self.capacity {
@@ -29,22 +45,30 @@ impl MergePixelFifo {
 }
 
 impl PixelFifo for MergePixelFifo {
+    // Only transparent pixels are overwritten, or the ones of sprites with a higher
+    // OAM index when ordering by it
     fn push(&mut self, pixel: Pixel) {
-        if self.len < self.capacity {
-            let new_pixel: Option<Pixel> =
-                if let Some(old_pixel) = self.buffer[self.push_i as usize] {
-                    if old_pixel.color == 0 {
-                        Some(pixel)
-                    } else {
-                        self.buffer[self.push_i as usize]
-                    }
-                } else {
-                    Some(pixel)
-                };
-            self.buffer[self.push_i as usize] = new_pixel;
-            self.push_i = (self.push_i + 1) % self.capacity;
+        if self.merged < self.len {
+            let overwrite: bool = match self.buffer[self.push_i as usize] {
+                Some(old_pixel) => {
+                    old_pixel.color == 0
+                        || (self.by_oam_index
+                            && pixel.color != 0
+                            && pixel.oam_index < old_pixel.oam_index)
+                }
+                None => true,
+            };
+            if overwrite {
+                self.buffer[self.push_i as usize] = Some(pixel);
+            }
+        } else if self.len < self.capacity {
+            self.buffer[self.push_i as usize] = Some(pixel);
             self.len += 1;
+        } else {
+            return;
         }
+        self.merged += 1;
+        self.push_i = (self.push_i + 1) % self.capacity;
     }
 
     fn shift(&mut self) -> Option<Pixel> {
@@ -76,9 +100,31 @@ impl PixelFifo for MergePixelFifo {
 
         self.pop_i = self.push_i;
         self.len = 0;
+        self.merged = 0;
     }
 
     fn len(&mut self) -> u8 {
         self.len
     }
 }
+
+impl Stateful for MergePixelFifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        save_buffer(&self.buffer, state);
+        state.write_u8(self.push_i);
+        state.write_u8(self.pop_i);
+        state.write_u8(self.len);
+        state.write_u8(self.merged);
+        state.write_bool(self.by_oam_index);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        load_buffer(&mut self.buffer, state)?;
+        self.push_i = state.read_u8()? % self.capacity;
+        self.pop_i = state.read_u8()? % self.capacity;
+        self.len = state.read_u8()?.min(self.capacity);
+        self.merged = state.read_u8()?.min(self.capacity);
+        self.by_oam_index = state.read_bool()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
 use crate::ppu::pixel_fetcher::Pixel;
+use crate::state::{StateReader, StateWriter};
+use std::error::Error;
 
 pub mod merge_pixel_fifo;
 pub mod standard_pixel_fifo;
@@ -9,3 +11,26 @@ pub trait PixelFifo {
     fn shift(&mut self) -> Option<Pixel>;
     fn len(&mut self) -> u8;
 }
+
+pub fn save_buffer(buffer: &[Option<Pixel>], state: &mut StateWriter) {
+    for pixel in buffer {
+        state.write_bool(pixel.is_some());
+        if let Some(pixel) = pixel {
+            pixel.save_state(state);
+        }
+    }
+}
+
+pub fn load_buffer(
+    buffer: &mut [Option<Pixel>],
+    state: &mut StateReader,
+) -> Result<(), Box<dyn Error>> {
+    for pixel in buffer.iter_mut() {
+        *pixel = if state.read_bool()? {
+            Some(Pixel::load_state(state)?)
+        } else {
+            None
+        };
+    }
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use crate::ppu::pixel_fetcher::pixel_fifo::PixelFifo;
+use crate::ppu::pixel_fetcher::pixel_fifo::{load_buffer, save_buffer};
 use crate::ppu::pixel_fetcher::Pixel;
+use crate::state::{StateReader, StateWriter, Stateful};
+use std::error::Error;
 
 pub struct StandardPixelFifo {
     buffer: Vec<Option<Pixel>>,
@@ -51,3 +54,20 @@ impl PixelFifo for StandardPixelFifo {
         self.len
     }
 }
+
+impl Stateful for StandardPixelFifo {
+    fn save_state(&self, state: &mut StateWriter) {
+        save_buffer(&self.buffer, state);
+        state.write_u8(self.push_i);
+        state.write_u8(self.pop_i);
+        state.write_u8(self.len);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        load_buffer(&mut self.buffer, state)?;
+        self.push_i = state.read_u8()? % self.capacity;
+        self.pop_i = state.read_u8()? % self.capacity;
+        self.len = state.read_u8()?.min(self.capacity);
+        Ok(())
+    }
+}
@@ -1,4 +1,3 @@
-use crate::mmu::address_spaces::Addressable;
 use crate::ppu::pixel_fetcher::pixel_fifo::merge_pixel_fifo::MergePixelFifo;
 use crate::ppu::pixel_fetcher::pixel_fifo::PixelFifo;
 use crate::ppu::pixel_fetcher::FetchState;
@@ -6,7 +5,9 @@ use crate::ppu::pixel_fetcher::Palette;
 use crate::ppu::pixel_fetcher::Pixel;
 use crate::ppu::pixel_fetcher::Pixelfetcher;
 use crate::ppu::Sprite;
+use crate::state::{StateReader, StateWriter, Stateful};
 use crate::Mmu;
+use std::error::Error;
 
 pub struct SpriteFetcher {
     fifo: MergePixelFifo,
@@ -47,7 +48,7 @@ impl SpriteFetcher {
             (FetchState::FetchDataLow, true) => self.fetch_data_low(mmu, sprite),
             (FetchState::FetchDataHigh, true) => self.fetch_data_high(mmu, sprite),
             (FetchState::Push, _) => {
-                self.push(sprite);
+                self.push(mmu, sprite);
                 self.done = true;
             }
             _ => (),
@@ -92,18 +93,33 @@ impl SpriteFetcher {
 
     fn fetch_data_low(&mut self, mmu: &Mmu, sprite: &Sprite) {
         let add: u16 = self.get_tile_data_start_address(mmu, sprite);
-        self.data_low = mmu.read(add);
+        self.data_low = mmu.read_vram(SpriteFetcher::get_bank(mmu, sprite), add);
         self.change_state(FetchState::FetchDataHigh);
     }
 
     fn fetch_data_high(&mut self, mmu: &Mmu, sprite: &Sprite) {
-        self.data_high = mmu.read(self.get_tile_data_start_address(mmu, sprite) + 1);
+        let add: u16 = self.get_tile_data_start_address(mmu, sprite) + 1;
+        self.data_high = mmu.read_vram(SpriteFetcher::get_bank(mmu, sprite), add);
         self.change_state(FetchState::Push);
     }
 
-    fn push(&mut self, sprite: &Sprite) {
-        //TODO: maybe use standard fifo? refer to GBEDG sprite fetching (pushing)
-        self.fifo.clear();
+    fn get_bank(mmu: &Mmu, sprite: &Sprite) -> u8 {
+        if mmu.is_cgb() {
+            sprite.bank
+        } else {
+            0
+        }
+    }
+
+    fn push(&mut self, mmu: &Mmu, sprite: &Sprite) {
+        self.fifo.start_merge(mmu.is_cgb());
+        let palette: Palette = if mmu.is_cgb() {
+            Palette::CgbObj(sprite.cgb_palette)
+        } else if !sprite.palette {
+            Palette::OBP0
+        } else {
+            Palette::OBP1
+        };
         for i in (0..8).rev() {
             if (sprite.x_position + (7-i)) >= 8 {
                 let exp: u32 = if sprite.x_flip {
@@ -117,13 +133,10 @@ impl SpriteFetcher {
                 let color: u8 = (msb << 1) | lsb;
                 self.fifo.push(Pixel {
                     color: color,
-                    palette: if !sprite.palette {
-                        Palette::OBP0
-                    } else {
-                        Palette::OBP1
-                    },
+                    palette,
                     priority: false,
                     bg_priority: sprite.priority,
+                    oam_index: sprite.id,
                 });
             }
         }
@@ -146,3 +159,28 @@ impl SpriteFetcher {
         self.done = true;
     }
 }
+
+impl Stateful for SpriteFetcher {
+    fn save_state(&self, state: &mut StateWriter) {
+        self.fifo.save_state(state);
+        self.state.save_state(state);
+        state.write_u8(self.tile_no);
+        state.write_u16(self.data_start_add);
+        state.write_u8(self.data_low);
+        state.write_u8(self.data_high);
+        state.write_bool(self.ready);
+        state.write_bool(self.done);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>> {
+        self.fifo.load_state(state)?;
+        self.state.load_state(state)?;
+        self.tile_no = state.read_u8()?;
+        self.data_start_add = state.read_u16()?;
+        self.data_low = state.read_u8()?;
+        self.data_high = state.read_u8()?;
+        self.ready = state.read_bool()?;
+        self.done = state.read_bool()?;
+        Ok(())
+    }
+}
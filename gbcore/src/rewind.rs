@@ -0,0 +1,150 @@
+use crate::Device;
+use std::collections::VecDeque;
+
+// Every snapshot costs a full save state and a delta encode, taking one every other
+// frame halves that work and still steps back smoothly
+pub const DEFAULT_INTERVAL: u32 = 2;
+// Ten seconds of rewind at the default interval
+pub const DEFAULT_CAPACITY: usize = 300;
+
+// Snapshots are stored as the latest full state plus a chain of deltas, each one
+// turning a snapshot into the one recorded before it
+pub struct Rewind {
+    interval: u32,
+    capacity: usize,
+    frames: u32,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    // Keeps enough snapshots to step back `capacity` times
+    pub fn new(interval: u32, capacity: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            frames: 0,
+            current: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Meant to be called after every frame, records a snapshot every `interval` frames
+    pub fn record(&mut self, device: &Device) {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+
+        let state: Vec<u8> = device.save_state();
+        if let Some(previous) = self.current.take() {
+            self.deltas.push_back(Rewind::encode(&state, &previous));
+            if self.deltas.len() > self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.current = Some(state);
+    }
+
+    // Restores the snapshot before the latest one, returns false once the buffer is exhausted.
+    // The frame emulated right after only redraws the screen and shouldn't be recorded
+    pub fn step_back(&mut self, device: &mut Device) -> bool {
+        self.frames = 0;
+        if let (Some(current), Some(delta)) = (&self.current, self.deltas.pop_back()) {
+            let previous: Vec<u8> = Rewind::decode(current, &delta);
+            if device.load_state(&previous).is_ok() {
+                self.current = Some(previous);
+                return true;
+            }
+            self.clear();
+        }
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.current = None;
+        self.deltas.clear();
+    }
+
+    // The delta is the target length followed by the XOR of the two states, with
+    // runs of unchanged bytes stored as (skip, literal count, literals)
+    pub fn encode(from: &[u8], to: &[u8]) -> Vec<u8> {
+        let mut delta: Vec<u8> = Vec::new();
+        Rewind::write_varint(&mut delta, to.len());
+
+        let mut i: usize = 0;
+        while i < to.len() {
+            let skip_start: usize = i;
+            while i < to.len() && from.get(i) == Some(&to[i]) {
+                i += 1;
+            }
+            let literal_start: usize = i;
+            while i < to.len() && from.get(i) != Some(&to[i]) {
+                i += 1;
+            }
+            Rewind::write_varint(&mut delta, literal_start - skip_start);
+            Rewind::write_varint(&mut delta, i - literal_start);
+            for (j, byte) in to.iter().enumerate().take(i).skip(literal_start) {
+                delta.push(byte ^ from.get(j).unwrap_or(&0));
+            }
+        }
+        delta
+    }
+
+    pub fn decode(from: &[u8], delta: &[u8]) -> Vec<u8> {
+        let mut position: usize = 0;
+        let len: usize = Rewind::read_varint(delta, &mut position);
+        let mut to: Vec<u8> = from.to_vec();
+        to.resize(len, 0);
+
+        let mut i: usize = 0;
+        while position < delta.len() {
+            i += Rewind::read_varint(delta, &mut position);
+            let literals: usize = Rewind::read_varint(delta, &mut position);
+            for byte in &delta[position..position + literals] {
+                to[i] ^= byte;
+                i += 1;
+            }
+            position += literals;
+        }
+        to
+    }
+
+    fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            data.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        data.push(value as u8);
+    }
+
+    fn read_varint(data: &[u8], position: &mut usize) -> usize {
+        let mut value: usize = 0;
+        let mut shift: u32 = 0;
+        loop {
+            let byte: u8 = data[*position];
+            *position += 1;
+            value |= ((byte & 0x7F) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Rewind::new(DEFAULT_INTERVAL, DEFAULT_CAPACITY)
+    }
+}
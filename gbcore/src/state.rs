@@ -0,0 +1,159 @@
+use std::error::Error;
+use std::fmt::Display;
+
+pub trait Stateful {
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), Box<dyn Error>>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(if value { 0x1 } else { 0x0 });
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    // Lengths come from the data itself, so the end is checked for overflows too
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end: Option<usize> = self.position.checked_add(len);
+        match end.and_then(|end| self.data.get(self.position..end)) {
+            Some(bytes) => {
+                self.position += len;
+                Ok(bytes)
+            }
+            None => Err(String::from("Unexpected end of save state")),
+        }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, String> {
+        match self.read_u8()? {
+            0x0 => Ok(false),
+            0x1 => Ok(true),
+            value => Err(format!("Invalid boolean {:#02X} in save state", value)),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_u128(&mut self) -> Result<u128, String> {
+        Ok(u128::from_le_bytes(self.take(16)?.try_into().unwrap()))
+    }
+
+    // The ranged readers reject values the emulator can never reach, as they can only
+    // come from a corrupted state and would index out of bounds or overflow later on
+    pub fn read_u8_max(&mut self, max: u8) -> Result<u8, String> {
+        check_max(self.read_u8()?, max)
+    }
+
+    pub fn read_u16_max(&mut self, max: u16) -> Result<u16, String> {
+        check_max(self.read_u16()?, max)
+    }
+
+    pub fn read_u32_max(&mut self, max: u32) -> Result<u32, String> {
+        check_max(self.read_u32()?, max)
+    }
+
+    pub fn read_u64_max(&mut self, max: u64) -> Result<u64, String> {
+        check_max(self.read_u64()?, max)
+    }
+
+    pub fn read_bytes(&mut self) -> Result<Vec<u8>, String> {
+        let len: usize = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    // Reads a length prefixed block that has to match the size of an existing buffer
+    pub fn read_bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), String> {
+        let bytes: Vec<u8> = self.read_bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(format!(
+                "Save state block of {} bytes where {} were expected",
+                bytes.len(),
+                buffer.len()
+            ));
+        }
+        buffer.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+}
+
+fn check_max<T: PartialOrd + Display>(value: T, max: T) -> Result<T, String> {
+    if value > max {
+        Err(format!(
+            "Invalid value {} in save state, at most {} was expected",
+            value, max
+        ))
+    } else {
+        Ok(value)
+    }
+}
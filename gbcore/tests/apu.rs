@@ -0,0 +1,122 @@
+use gbcore::apu::Apu;
+use gbcore::mmu::address_spaces::Addressable;
+
+// The frame sequencer steps on the falling edges of DIV bit 4
+fn step_frame_sequencer(apu: &mut Apu, steps: u8) {
+    for _ in 0..steps {
+        apu.tick(0, 0x10);
+        apu.tick(0, 0x00);
+    }
+}
+
+fn is_channel_enabled(apu: &Apu, channel: u8) -> bool {
+    (apu.read(0xFF26) & (1 << channel)) != 0
+}
+
+#[test]
+fn powering_off_clears_the_registers_and_ignores_writes() {
+    let mut apu: Apu = Apu::new();
+    apu.write(0xFF30, 0x5A);
+    apu.write(0xFF26, 0x00);
+
+    assert_eq!(apu.read(0xFF26), 0x70);
+    assert_eq!(apu.read(0xFF24), 0x00);
+    assert_eq!(apu.read(0xFF25), 0x00);
+    assert_eq!(apu.read(0xFF11), 0x3F);
+    assert_eq!(apu.read(0xFF12), 0x00);
+
+    apu.write(0xFF24, 0x77);
+    assert_eq!(apu.read(0xFF24), 0x00);
+    // Wave RAM is kept and still writable
+    assert_eq!(apu.read(0xFF30), 0x5A);
+    apu.write(0xFF31, 0xA5);
+    assert_eq!(apu.read(0xFF31), 0xA5);
+
+    apu.write(0xFF26, 0x80);
+    apu.write(0xFF24, 0x77);
+    assert_eq!(apu.read(0xFF24), 0x77);
+}
+
+#[test]
+fn length_counter_expiry_disables_the_channel() {
+    let mut apu: Apu = Apu::new();
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF16, 0x3E);
+    apu.write(0xFF19, 0xC0);
+    assert!(is_channel_enabled(&apu, 1));
+
+    // A length of 2 runs out after two clocks, on steps 0 and 2
+    step_frame_sequencer(&mut apu, 2);
+    assert!(is_channel_enabled(&apu, 1));
+    step_frame_sequencer(&mut apu, 1);
+    assert!(!is_channel_enabled(&apu, 1));
+}
+
+#[test]
+fn sweep_overflow_disables_channel_1() {
+    let mut apu: Apu = Apu::new();
+    apu.write(0xFF10, 0x11);
+    apu.write(0xFF12, 0xF0);
+    apu.write(0xFF13, 0x00);
+    apu.write(0xFF14, 0x85);
+    assert!(is_channel_enabled(&apu, 0));
+
+    // 0x500 sweeps to 0x780, whose next value would go past 0x7FF
+    step_frame_sequencer(&mut apu, 3);
+    assert!(!is_channel_enabled(&apu, 0));
+
+    // The overflow check on trigger disables it right away
+    apu.write(0xFF13, 0xFF);
+    apu.write(0xFF14, 0x87);
+    assert!(!is_channel_enabled(&apu, 0));
+}
+
+#[test]
+fn turning_the_dac_off_silences_the_channel() {
+    let mut apu: Apu = Apu::new();
+    for location in [0xFF12, 0xFF17, 0xFF1A, 0xFF21] {
+        apu.write(location, 0x00);
+    }
+    apu.write(0xFF17, 0xF0);
+    apu.write(0xFF19, 0x87);
+    apu.tick(4, 0);
+    assert!(is_channel_enabled(&apu, 1));
+    assert_ne!(apu.get_output(), (0, 0));
+
+    apu.write(0xFF17, 0x00);
+    apu.tick(4, 0);
+    assert!(!is_channel_enabled(&apu, 1));
+    assert_eq!(apu.get_output(), (0, 0));
+}
+
+#[test]
+fn wave_ram_accesses_the_byte_being_played() {
+    let mut apu: Apu = Apu::new();
+    for i in 0..16 {
+        apu.write(0xFF30 + i, (i as u8) * 0x11);
+    }
+    apu.write(0xFF1A, 0x80);
+    apu.write(0xFF1D, 0xFF);
+    apu.write(0xFF1E, 0x87);
+
+    // Every 2 cycles the channel moves to the next sample, 2 samples per byte
+    apu.tick(4, 0);
+    assert_eq!(apu.read(0xFF3A), 0x11);
+    apu.write(0xFF30, 0xAB);
+
+    apu.write(0xFF1A, 0x00);
+    assert_eq!(apu.read(0xFF30), 0x00);
+    assert_eq!(apu.read(0xFF31), 0xAB);
+}
+
+#[test]
+fn frames_too_short_for_a_sample_output_nothing() {
+    let mut apu: Apu = Apu::new();
+    apu.set_sample_rate(8000);
+    apu.tick(4, 0);
+    apu.end_frame();
+
+    let mut samples: Vec<i16> = Vec::new();
+    apu.drain_samples(&mut samples);
+    assert!(samples.is_empty());
+}
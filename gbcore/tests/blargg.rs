@@ -0,0 +1,81 @@
+mod common;
+
+use common::{build_rom, new_device, new_lcd_buffer, read_rom, released, run_serial_test};
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+
+fn run_blargg(path: &str, max_frames: usize) {
+    let output: String = run_serial_test(&mut new_device(read_rom(path)), max_frames);
+    assert!(output.contains("Passed"), "{} failed:\n{}", path, output);
+}
+
+#[test]
+#[ignore = "needs blargg/cpu_instrs.gb in tests/roms"]
+fn cpu_instrs() {
+    run_blargg("blargg/cpu_instrs.gb", 4000);
+}
+
+#[test]
+#[ignore = "needs blargg/instr_timing.gb in tests/roms"]
+fn instr_timing() {
+    run_blargg("blargg/instr_timing.gb", 600);
+}
+
+#[test]
+#[ignore = "needs blargg/mem_timing.gb in tests/roms"]
+fn mem_timing() {
+    run_blargg("blargg/mem_timing.gb", 600);
+}
+
+#[test]
+#[ignore = "needs blargg/halt_bug.gb in tests/roms"]
+fn halt_bug() {
+    run_blargg("blargg/halt_bug.gb", 600);
+}
+
+#[test]
+fn serial_output_is_captured() {
+    let mut program: Vec<u8> = Vec::new();
+    for byte in b"Passed\n" {
+        program.extend_from_slice(&[
+            0x3E, *byte, // LD A, byte
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x81, // LD A, 0x81
+            0xE0, 0x02, // LDH (SC), A
+            0xF0, 0x02, // LDH A, (SC)
+            0xCB, 0x7F, // BIT 7, A
+            0x20, 0xFA, // JR NZ, -6
+        ]);
+    }
+    program.extend_from_slice(&[0x18, 0xFE]);
+
+    let output: String = run_serial_test(&mut new_device(build_rom(&program)), 60);
+    assert_eq!(output, "Passed\n");
+}
+
+#[test]
+fn undrained_serial_output_is_capped() {
+    let program: &[u8] = &[
+        0x04, // INC B
+        0x78, // LD A, B
+        0xE0, 0x01, // LDH (SB), A
+        0x3E, 0x80, // LD A, 0x80, starts a transfer on the external clock
+        0xE0, 0x02, // LDH (SC), A
+        0xAF, // XOR A
+        0xE0, 0x02, // LDH (SC), A
+        0x18, 0xF3, // JR -13
+    ];
+    let mut device: Device = new_device(build_rom(program));
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    for _ in 0..10 {
+        device.frame(&mut lcd_buffer, released());
+    }
+
+    // Only the latest bytes are kept
+    let mut output: Vec<u8> = Vec::new();
+    device.drain_serial(&mut output);
+    assert_eq!(output.len(), 4096);
+    assert!(output
+        .windows(2)
+        .all(|bytes| bytes[1] == bytes[0].wrapping_add(1)));
+}
@@ -0,0 +1,379 @@
+mod common;
+
+use common::{build_cgb_rom, build_rom, build_rom_with_header, new_device, run_until_trap, Lcd};
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::mmu::address_spaces::Addressable;
+use gbcore::ppu::colors::ColorCorrection;
+use gbcore::Device;
+
+fn run_program(rom: fn(&[u8]) -> Vec<u8>, program: &[u8]) -> Device {
+    let mut code: Vec<u8> = vec![
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH (LCDC), A, VRAM is always accessible with the LCD off
+    ];
+    code.extend_from_slice(program);
+    code.extend_from_slice(&[
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+
+    let mut device: Device = new_device(rom(&code));
+    assert!(run_until_trap(&mut device, 2), "The program didn't end");
+    device
+}
+
+// Writes 0x11 and 0x22 at `location` in the two `banks` selected through `register`,
+// then reads them back into B and C and the register into D
+fn switch_banks(rom: fn(&[u8]) -> Vec<u8>, register: u8, location: u16, banks: [u8; 2]) -> Device {
+    let low: u8 = location as u8;
+    let high: u8 = (location >> 8) as u8;
+    run_program(
+        rom,
+        &[
+            0x3E, banks[0], // LD A, first
+            0xE0, register, // LDH (register), A
+            0x3E, 0x11, // LD A, 0x11
+            0xEA, low, high, // LD (location), A
+            0x3E, banks[1], // LD A, second
+            0xE0, register, // LDH (register), A
+            0x3E, 0x22, // LD A, 0x22
+            0xEA, low, high, // LD (location), A
+            0x3E, banks[0], // LD A, first
+            0xE0, register, // LDH (register), A
+            0xFA, low, high, // LD A, (location)
+            0x47, // LD B, A
+            0x3E, banks[1], // LD A, second
+            0xE0, register, // LDH (register), A
+            0xFA, low, high, // LD A, (location)
+            0x4F, // LD C, A
+            0xF0, register, // LDH A, (register)
+            0x57,     // LD D, A
+        ],
+    )
+}
+
+#[test]
+fn carts_for_the_color_run_in_cgb_mode() {
+    let device: Device = new_device(build_cgb_rom(&[]));
+    assert!(device.is_cgb());
+    assert_eq!(device.get_cpu().get_registers().get8(&Register8::A), 0x11);
+
+    let device: Device = new_device(build_rom(&[]));
+    assert!(!device.is_cgb());
+    assert_eq!(device.get_cpu().get_registers().get8(&Register8::A), 0x01);
+}
+
+#[test]
+fn only_bit_7_of_the_cgb_flag_selects_cgb_mode() {
+    for (flag, cgb) in [
+        (0x80, true),
+        (0xC0, true),
+        (0x84, true),
+        (0x40, false),
+        (0x00, false),
+    ] {
+        let device: Device = new_device(build_rom_with_header(&[], &[(0x143, flag)]));
+        assert_eq!(device.is_cgb(), cgb, "CGB flag {:#04X}", flag);
+    }
+}
+
+#[test]
+fn vbk_switches_vram_banks() {
+    let device: Device = switch_banks(build_cgb_rom, 0x4F, 0x8000, [0, 1]);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x11);
+    assert_eq!(registers.get8(&Register8::C), 0x22);
+    assert_eq!(registers.get8(&Register8::D), 0xFF);
+}
+
+#[test]
+fn svbk_switches_wram_banks_with_bank_0_selecting_bank_1() {
+    let device: Device = switch_banks(build_cgb_rom, 0x70, 0xD000, [0, 7]);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x11);
+    assert_eq!(registers.get8(&Register8::C), 0x22);
+    assert_eq!(registers.get8(&Register8::D), 0xFF);
+
+    let device: Device = switch_banks(build_cgb_rom, 0x70, 0xD000, [1, 0]);
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x22);
+    assert_eq!(registers.get8(&Register8::D), 0xF9);
+}
+
+#[test]
+fn dmg_carts_have_a_single_bank_of_each() {
+    for (register, location) in [(0x4F, 0x8000), (0x70, 0xD000)] {
+        let device: Device = switch_banks(build_rom, register, location, [0, 1]);
+
+        let registers: &Registers = device.get_cpu().get_registers();
+        assert_eq!(registers.get8(&Register8::B), 0x22);
+        assert_eq!(registers.get8(&Register8::C), 0x22);
+        assert_eq!(registers.get8(&Register8::D), 0xFF);
+    }
+}
+
+// Counts in B the DIV increments while the PPU draws 16 lines, KEY1 ends up in C
+fn count_div_over_16_lines(double_speed: bool) -> Device {
+    let wait_for_ly = |ly: u8| {
+        [
+            0xF0, 0x44, // LDH A, (LY)
+            0xFE, ly, // CP ly
+            0x20, 0xFA, // JR NZ, -6
+        ]
+    };
+    let mut program: Vec<u8> = vec![
+        0x3E, 0x91, // LD A, 0x91
+        0xE0, 0x40, // LDH (LCDC), A
+    ];
+    if double_speed {
+        program.extend_from_slice(&[
+            0x3E, 0x01, // LD A, 0x01
+            0xE0, 0x4D, // LDH (KEY1), A
+            0x10, 0x00, // STOP
+        ]);
+    }
+    program.extend_from_slice(&wait_for_ly(0x10));
+    program.extend_from_slice(&[
+        0xE0, 0x04, // LDH (DIV), A
+    ]);
+    program.extend_from_slice(&wait_for_ly(0x20));
+    program.extend_from_slice(&[
+        0xF0, 0x04, // LDH A, (DIV)
+        0x47, // LD B, A
+        0xF0, 0x4D, // LDH A, (KEY1)
+        0x4F, // LD C, A
+    ]);
+    run_program(build_cgb_rom, &program)
+}
+
+#[test]
+fn stop_switches_to_double_speed_when_requested_through_key1() {
+    // 16 lines last 7296 dots, DIV is incremented every 256 CPU cycles
+    let device: Device = count_div_over_16_lines(false);
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 28);
+    assert_eq!(registers.get8(&Register8::C), 0x7E);
+    assert!(!device.get_cpu().is_stopped());
+
+    let device: Device = count_div_over_16_lines(true);
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 57);
+    assert_eq!(registers.get8(&Register8::C), 0xFE);
+}
+
+#[test]
+fn palette_ram_index_increments_after_writes() {
+    let device: Device = run_program(
+        build_cgb_rom,
+        &[
+            0x3E, 0xBE, // LD A, 0xBE
+            0xE0, 0x68, // LDH (BCPS), A, auto-increment from the last color
+            0x3E, 0x1F, // LD A, 0x1F
+            0xE0, 0x69, // LDH (BCPD), A
+            0x3C, // INC A
+            0xE0, 0x69, // LDH (BCPD), A, wraps to the first color
+            0xF0, 0x68, // LDH A, (BCPS)
+            0x47, // LD B, A
+            0xF0, 0x69, // LDH A, (BCPD), reading doesn't increment
+            0x4F, // LD C, A
+            0xF0, 0x69, // LDH A, (BCPD)
+            0x57, // LD D, A
+            0x3E, 0x3F, // LD A, 0x3F
+            0xE0, 0x68, // LDH (BCPS), A
+            0xF0, 0x69, // LDH A, (BCPD)
+            0x5F, // LD E, A
+        ],
+    );
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0xC0);
+    assert_eq!(registers.get8(&Register8::C), 0xFF);
+    assert_eq!(registers.get8(&Register8::D), 0xFF);
+    assert_eq!(registers.get8(&Register8::E), 0x20);
+}
+
+const RED: u32 = 0xFF0000;
+const GREEN: u32 = 0x00FF00;
+const BLUE: u32 = 0x0000FF;
+const WHITE: u32 = 0xFFFFFF;
+
+fn write_color(lcd: &mut Lcd, register: u16, palette: u8, color: u8, rgb555: u16) {
+    lcd.mmu.write(register, palette * 8 + color * 2);
+    lcd.mmu.write(register + 1, rgb555 as u8);
+    lcd.mmu.write(register, palette * 8 + color * 2 + 1);
+    lcd.mmu.write(register + 1, (rgb555 >> 8) as u8);
+}
+
+fn write_vram(lcd: &mut Lcd, bank: u8, location: u16, bytes: &[u8]) {
+    lcd.mmu.write(0xFF4F, bank);
+    for (i, byte) in bytes.iter().enumerate() {
+        lcd.mmu.write(location + i as u16, *byte);
+    }
+    lcd.mmu.write(0xFF4F, 0);
+}
+
+#[test]
+fn bg_attributes_select_the_palette_and_the_tile_bank_and_flip_tiles() {
+    let mut lcd: Lcd = Lcd::with_rom(build_cgb_rom(&[]));
+    write_color(&mut lcd, 0xFF68, 1, 1, 0x001F);
+    // The top row has its leftmost pixel set, the bottom one its rightmost pixel
+    write_vram(&mut lcd, 1, 0x8000, &[0x80]);
+    write_vram(&mut lcd, 1, 0x800E, &[0x01]);
+    write_vram(&mut lcd, 1, 0x9800, &[0x09, 0x29, 0x49, 0x01]);
+    lcd.run(456);
+
+    let line: &[u32] = &lcd.buffer.buffer[0..32];
+    let red_pixels: Vec<usize> = (0..32).filter(|x| line[*x] == RED).collect();
+    assert_eq!(red_pixels, vec![0, 15, 23]);
+    assert!(line.iter().all(|pixel| *pixel == RED || *pixel == WHITE));
+}
+
+#[test]
+fn color_correction_mixes_the_components_like_the_lcd() {
+    let mut lcd: Lcd = Lcd::with_rom(build_cgb_rom(&[]));
+    write_color(&mut lcd, 0xFF68, 0, 0, 0x001F);
+    lcd.ppu.set_color_correction(ColorCorrection::Lcd);
+    lcd.run(456);
+    lcd.ppu.set_color_correction(ColorCorrection::Raw);
+    lcd.run(456);
+
+    assert_eq!(lcd.buffer.buffer[0], 0xC9002E);
+    assert_eq!(lcd.buffer.buffer[160], RED);
+}
+
+#[test]
+fn sprites_are_ordered_by_oam_index_and_lcdc_bit_0_gives_them_priority() {
+    let mut lcd: Lcd = Lcd::with_rom(build_cgb_rom(&[]));
+    lcd.mmu.write(0xFF40, 0x93);
+    write_color(&mut lcd, 0xFF68, 0, 1, 0x001F);
+    write_color(&mut lcd, 0xFF6A, 0, 1, 0x03E0);
+    write_color(&mut lcd, 0xFF6A, 1, 1, 0x7C00);
+    write_vram(&mut lcd, 0, 0x8010, &[0xFF, 0x00].repeat(8));
+    // A background tile over sprites at 24..31
+    write_vram(&mut lcd, 0, 0x9803, &[0x01]);
+    write_vram(&mut lcd, 1, 0x9803, &[0x80]);
+    for (sprite, (x, attributes)) in [(20, 0x00), (16, 0x01), (32, 0x00)].iter().enumerate() {
+        let location: u16 = 0xFE00 + (sprite as u16) * 4;
+        lcd.mmu.write(location, 16);
+        lcd.mmu.write(location + 1, *x);
+        lcd.mmu.write(location + 2, 0x01);
+        lcd.mmu.write(location + 3, *attributes);
+    }
+    lcd.run(456);
+    lcd.mmu.write(0xFF40, 0x92);
+    lcd.run(456);
+
+    let buffer: &[u32] = &lcd.buffer.buffer;
+    assert_eq!(buffer[8..12], [BLUE; 4]);
+    assert_eq!(buffer[12..20], [GREEN; 8]);
+    assert_eq!(buffer[24..32], [RED; 8]);
+    assert_eq!(buffer[160 + 24..160 + 32], [GREEN; 8]);
+}
+
+// Points VRAM DMA at the cart header from 0x0130, the title starts at 0x0134 and the
+// program at 0x0150, to the start of VRAM
+const HDMA_FROM_HEADER: &[u8] = &[
+    0x3E, 0x01, // LD A, 0x01
+    0xE0, 0x51, // LDH (HDMA1), A
+    0x3E, 0x30, // LD A, 0x30
+    0xE0, 0x52, // LDH (HDMA2), A
+    0x3E, 0x80, // LD A, 0x80
+    0xE0, 0x53, // LDH (HDMA3), A
+    0xAF, // XOR A
+    0xE0, 0x54, // LDH (HDMA4), A
+];
+
+#[test]
+fn general_purpose_dma_copies_every_block_while_the_cpu_waits() {
+    let mut program: Vec<u8> = HDMA_FROM_HEADER.to_vec();
+    program.extend_from_slice(&[
+        0xE0, 0x04, // LDH (DIV), A
+        0x3E, 0x7F, // LD A, 0x7F
+        0xE0, 0x55, // LDH (HDMA5), A
+        0xF0, 0x04, // LDH A, (DIV)
+        0x47, // LD B, A
+        0xF0, 0x55, // LDH A, (HDMA5)
+        0x4F, // LD C, A
+        0xFA, 0x04, 0x80, // LD A, (0x8004)
+        0x57, // LD D, A
+        0xFA, 0x20, 0x80, // LD A, (0x8020)
+        0x5F, // LD E, A
+    ]);
+    let device: Device = run_program(build_cgb_rom, &program);
+
+    // 128 blocks take 1024 M-cycles, DIV is incremented every 64
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 16);
+    assert_eq!(registers.get8(&Register8::C), 0xFF);
+    assert_eq!(registers.get8(&Register8::D), b'T');
+    assert_eq!(registers.get8(&Register8::E), 0xAF);
+}
+
+#[test]
+fn hblank_dma_copies_a_block_per_hblank_until_cancelled() {
+    let mut program: Vec<u8> = HDMA_FROM_HEADER.to_vec();
+    program.extend_from_slice(&[
+        0x3E, 0x91, // LD A, 0x91
+        0xE0, 0x40, // LDH (LCDC), A
+        0x3E, 0x83, // LD A, 0x83
+        0xE0, 0x55, // LDH (HDMA5), A
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x02, // CP 0x02
+        0x20, 0xFA, // JR NZ, -6
+        0xF0, 0x55, // LDH A, (HDMA5)
+        0x47, // LD B, A
+        0xAF, // XOR A
+        0xE0, 0x55, // LDH (HDMA5), A
+        0xF0, 0x55, // LDH A, (HDMA5)
+        0x4F, // LD C, A
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x04, // CP 0x04
+        0x20, 0xFA, // JR NZ, -6
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH (LCDC), A
+        0xFA, 0x13, 0x80, // LD A, (0x8013)
+        0x57, // LD D, A
+        0xFA, 0x20, 0x80, // LD A, (0x8020)
+        0x5F, // LD E, A
+    ]);
+    let device: Device = run_program(build_cgb_rom, &program);
+
+    // Two of the four blocks were copied in the HBlanks of lines 0 and 1
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x01);
+    assert_eq!(registers.get8(&Register8::C), 0x81);
+    assert_eq!(registers.get8(&Register8::D), 0x80);
+    assert_eq!(registers.get8(&Register8::E), 0x00);
+}
+
+#[test]
+fn hblank_dma_started_during_hblank_copies_the_first_block_right_away() {
+    let mut program: Vec<u8> = HDMA_FROM_HEADER.to_vec();
+    program.extend_from_slice(&[
+        0x3E, 0x91, // LD A, 0x91
+        0xE0, 0x40, // LDH (LCDC), A
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x01, // CP 0x01
+        0x20, 0xFA, // JR NZ, -6
+        0xF0, 0x41, // LDH A, (STAT)
+        0xE6, 0x03, // AND 0x03
+        0x20, 0xFA, // JR NZ, -6
+        0x3E, 0x83, // LD A, 0x83
+        0xE0, 0x55, // LDH (HDMA5), A
+        0xF0, 0x55, // LDH A, (HDMA5)
+        0x47, // LD B, A
+        0xAF, // XOR A
+        0xE0, 0x55, // LDH (HDMA5), A
+        0xE0, 0x40, // LDH (LCDC), A
+        0xFA, 0x04, 0x80, // LD A, (0x8004)
+        0x57, // LD D, A
+    ]);
+    let device: Device = run_program(build_cgb_rom, &program);
+
+    // The first of the four blocks was copied before the next instruction
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x02);
+    assert_eq!(registers.get8(&Register8::D), b'T');
+}
@@ -0,0 +1,163 @@
+#![allow(dead_code)]
+
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use gbcore::mmu::Mmu;
+use gbcore::ppu::{LcdBuffer, Ppu};
+use gbcore::Device;
+use std::fs;
+use std::path::PathBuf;
+
+pub const WIDTH: usize = 160;
+pub const HEIGHT: usize = 144;
+
+// Test ROMs aren't redistributed with the sources, the tests using them are ignored by
+// default and fail when run without the ROM
+pub fn read_rom(path: &str) -> Vec<u8> {
+    let full_path: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("roms")
+        .join(path);
+    fs::read(&full_path).unwrap_or_else(|e| {
+        panic!(
+            "{} not found, see tests/roms/README.md: {}",
+            full_path.display(),
+            e
+        )
+    })
+}
+
+// Builds a 32 KiB ROM without MBC that jumps to `program` placed at 0x150
+pub fn build_rom(program: &[u8]) -> Vec<u8> {
+    build_rom_with_header(program, &[])
+}
+
+// Same as build_rom, for a cart made for the Color
+pub fn build_cgb_rom(program: &[u8]) -> Vec<u8> {
+    build_rom_with_header(program, &[(0x143, 0x80)])
+}
+
+// Same as build_rom, with an MBC1 and 8 KiB of battery backed RAM
+pub fn build_battery_rom(program: &[u8]) -> Vec<u8> {
+    build_rom_with_header(program, &[(0x147, 0x03), (0x149, 0x02)])
+}
+
+// Same as build_rom, with the given header bytes overridden
+pub fn build_rom_with_header(program: &[u8], header: &[(usize, u8)]) -> Vec<u8> {
+    let mut rom: Vec<u8> = vec![0; 0x8000];
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+    rom[0x134..0x138].copy_from_slice(b"TEST");
+    for (location, byte) in header {
+        rom[*location] = *byte;
+    }
+    rom[0x150..0x150 + program.len()].copy_from_slice(program);
+    rom[0x14D] = rom[0x134..0x14D]
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    rom
+}
+
+pub fn new_device(rom: Vec<u8>) -> Device {
+    let mut device: Device = Device::new(rom, None, None).unwrap();
+    device.use_emulated_rtc(0);
+    device
+}
+
+pub fn new_lcd_buffer() -> LcdBuffer {
+    LcdBuffer {
+        buffer: vec![0; WIDTH * HEIGHT],
+        cleared: false,
+    }
+}
+
+// Drives the PPU dot by dot, without a CPU
+pub struct Lcd {
+    pub mmu: Mmu,
+    pub ppu: Ppu,
+    pub buffer: LcdBuffer,
+}
+
+impl Lcd {
+    pub fn new() -> Lcd {
+        Lcd::with_rom(build_rom(&[]))
+    }
+
+    // Starts at the first dot of line 0, after a whole frame so that STAT reports the
+    // PPU mode rather than the one left by the boot ROM
+    pub fn with_rom(rom: Vec<u8>) -> Lcd {
+        let mut lcd: Lcd = Lcd {
+            mmu: Mmu::new(rom, None, None).unwrap(),
+            ppu: Ppu::new(),
+            buffer: new_lcd_buffer(),
+        };
+        lcd.run(154 * 456);
+        lcd
+    }
+
+    pub fn run(&mut self, dots: u32) {
+        for _ in 0..dots {
+            self.ppu.tick(&mut self.mmu, &mut self.buffer, 1);
+        }
+    }
+}
+
+pub fn released() -> JoypadState {
+    JoypadState {
+        up: false,
+        down: false,
+        left: false,
+        right: false,
+        a: false,
+        b: false,
+        start: false,
+        select: false,
+    }
+}
+
+// Runs until the serial output contains "Passed" or "Failed", or the frames run out
+pub fn run_serial_test(device: &mut Device, max_frames: usize) -> String {
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    let mut output: Vec<u8> = Vec::new();
+
+    for _ in 0..max_frames {
+        device.frame(&mut lcd_buffer, released());
+        device.drain_serial(&mut output);
+        let text: String = String::from_utf8_lossy(&output).into_owned();
+        if text.contains("Passed") || text.contains("Failed") {
+            return text;
+        }
+    }
+    String::from_utf8_lossy(&output).into_owned()
+}
+
+// Test programs end with LD B,B, runs `device` until it executes one and returns
+// whether it did within `max_frames`
+pub fn run_until_trap(device: &mut Device, max_frames: usize) -> bool {
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    device.set_opcode_trap(Some(0x40));
+
+    for _ in 0..max_frames {
+        device.frame(&mut lcd_buffer, released());
+        if device.take_trap() {
+            return true;
+        }
+    }
+    false
+}
+
+// Mooneye ROMs execute LD B,B once done and report success with the Fibonacci
+// numbers in B, C, D, E, H and L
+pub fn run_mooneye_test(device: &mut Device, max_frames: usize) -> Option<[u8; 6]> {
+    if !run_until_trap(device, max_frames) {
+        return None;
+    }
+    let registers: &Registers = device.get_cpu().get_registers();
+    Some([
+        registers.get8(&Register8::B),
+        registers.get8(&Register8::C),
+        registers.get8(&Register8::D),
+        registers.get8(&Register8::E),
+        registers.get8(&Register8::H),
+        registers.get8(&Register8::L),
+    ])
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{build_rom, new_device, new_lcd_buffer, released, run_until_trap};
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+
+#[test]
+fn stop_waits_for_a_button_and_resets_div() {
+    let program: &[u8] = &[
+        0x3E, 0xFF, // LD A, 0xFF
+        0x3D, // DEC A
+        0x20, 0xFD, // JR NZ, -3
+        0xE0, 0x00, // LDH (P1), A
+        0x06, 0x00, // LD B, 0
+        0x10, 0x04, // STOP, the INC B after it is skipped
+        0xF0, 0x04, // LDH A, (DIV)
+        0x4F, // LD C, A
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ];
+    let mut device: Device = new_device(build_rom(program));
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    device.set_opcode_trap(Some(0x40));
+
+    for _ in 0..10 {
+        lcd_buffer.cleared = false;
+        device.frame(&mut lcd_buffer, released());
+        assert!(!device.take_trap());
+        assert!(device.get_cpu().is_stopped());
+        assert!(lcd_buffer.cleared);
+    }
+
+    let pressed: JoypadState = JoypadState {
+        a: true,
+        ..released()
+    };
+    device.frame(&mut lcd_buffer, pressed);
+    assert!(device.take_trap());
+    assert!(!device.get_cpu().is_stopped());
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0);
+    assert_eq!(registers.get8(&Register8::C), 0);
+}
+
+// Requests a timer interrupt with IE and IF before running `program`
+fn with_pending_timer_interrupt(program: &[u8]) -> Vec<u8> {
+    let mut code: Vec<u8> = vec![
+        0x06, 0x00, // LD B, 0
+        0x0E, 0x00, // LD C, 0
+        0x3E, 0x04, // LD A, 0x04
+        0xE0, 0xFF, // LDH (IE), A
+        0xE0, 0x0F, // LDH (IF), A
+    ];
+    code.extend_from_slice(program);
+    let mut rom: Vec<u8> = build_rom(&code);
+    // The timer handler leaves B + 1 in C to tell when it ran
+    rom[0x50..0x53].copy_from_slice(&[
+        0x48, // LD C, B
+        0x0C, // INC C
+        0xC9, // RET
+    ]);
+    rom
+}
+
+#[test]
+fn halt_bug_reads_the_next_byte_twice() {
+    let rom: Vec<u8> = with_pending_timer_interrupt(&[
+        0xF3, // DI
+        0x76, // HALT
+        0x04, // INC B
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut device: Device = new_device(rom);
+    assert!(run_until_trap(&mut device, 1));
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 2);
+    assert_eq!(registers.get8(&Register8::C), 0);
+}
+
+#[test]
+fn interrupt_after_ei_halt_returns_to_the_halt() {
+    let rom: Vec<u8> = with_pending_timer_interrupt(&[
+        0xFB, // EI
+        0x76, // HALT
+        0x04, // INC B
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut device: Device = new_device(rom);
+    // The handler returns with IME off and nothing pending, so the second HALT never ends
+    assert!(!run_until_trap(&mut device, 1));
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0);
+    assert_eq!(registers.get8(&Register8::C), 1);
+    assert_eq!(registers.pc, 0x150 + 12);
+}
+
+#[test]
+fn instruction_after_ei_runs_before_the_interrupt() {
+    let rom: Vec<u8> = with_pending_timer_interrupt(&[
+        0xFB, // EI
+        0x04, // INC B
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+    let mut device: Device = new_device(rom);
+    assert!(run_until_trap(&mut device, 1));
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 1);
+    assert_eq!(registers.get8(&Register8::C), 2);
+}
+
+#[test]
+fn memory_accesses_happen_at_their_m_cycle() {
+    let program: &[u8] = &[
+        0x3E, 0x05, // LD A, 0x05
+        0xE0, 0x07, // LDH (TAC), A, TIMA counts every 16 cycles
+        0xAF, // XOR A
+        0xEA, 0x04, 0xFF, // LD (DIV), A, written on the 4th M-cycle
+        0xE0, 0x05, // LDH (TIMA), A, written 12 cycles later
+        0xF0, 0x05, // LDH A, (TIMA), read 12 cycles later
+        0x47, // LD B, A
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ];
+    let mut device: Device = new_device(build_rom(program));
+    assert!(run_until_trap(&mut device, 1));
+    assert_eq!(device.get_cpu().get_registers().get8(&Register8::B), 1);
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{build_rom, new_device, run_until_trap};
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::Device;
+
+fn run_program(program: &[u8]) -> Device {
+    let mut code: Vec<u8> = program.to_vec();
+    code.extend_from_slice(&[
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+
+    let mut device: Device = new_device(build_rom(&code));
+    assert!(run_until_trap(&mut device, 1));
+    device
+}
+
+// Copies `routine` to HRAM, a transfer from the external bus has to be waited from there
+fn copy_to_hram(routine: &[u8]) -> Vec<u8> {
+    let mut code: Vec<u8> = vec![0x21, 0x80, 0xFF]; // LD HL, 0xFF80
+    for byte in routine {
+        code.extend_from_slice(&[
+            0x3E, *byte, // LD A, byte
+            0x22,  // LD (HL+), A
+        ]);
+    }
+    code
+}
+
+#[test]
+fn oam_dma_takes_160_m_cycles() {
+    let device: Device = run_program(&[
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH (LCDC), A
+        0x3E, 0x5A, // LD A, 0x5A
+        0xEA, 0x00, 0x80, // LD (0x8000), A
+        0x3E, 0x80, // LD A, 0x80
+        0xE0, 0x46, // LDH (DMA), A, copying from VRAM doesn't disturb the ROM
+        0xFA, 0x00, 0xFE, // LD A, (0xFE00)
+        0x47, // LD B, A
+        0x0E, 0x28, // LD C, 40
+        0x0D, // DEC C
+        0x20, 0xFD, // JR NZ, -3
+        0xFA, 0x00, 0xFE, // LD A, (0xFE00)
+        0x4F, // LD C, A
+    ]);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0xFF);
+    assert_eq!(registers.get8(&Register8::C), 0x5A);
+}
+
+#[test]
+fn reads_from_the_dma_bus_return_the_byte_being_copied() {
+    // Fills 0xC000-0xC0FF with its low address byte
+    let mut program: Vec<u8> = vec![
+        0x21, 0x00, 0xC0, // LD HL, 0xC000
+        0xAF, // XOR A
+        0x22, // LD (HL+), A
+        0x3C, // INC A
+        0x20, 0xFC, // JR NZ, -4
+    ];
+    program.extend(copy_to_hram(&[
+        0xE0, 0x46, // LDH (DMA), A
+        0xFA, 0x10, 0xC0, // LD A, (0xC010), the DMA is copying its third byte
+        0x47, // LD B, A
+        0x3E, 0x28, // LD A, 40
+        0x3D, // DEC A
+        0x20, 0xFD, // JR NZ, -3
+        0xFA, 0x10, 0xC0, // LD A, (0xC010)
+        0x4F, // LD C, A
+        0xC9, // RET
+    ]));
+    program.extend_from_slice(&[
+        0x3E, 0xC0, // LD A, 0xC0
+        0xCD, 0x80, 0xFF, // CALL 0xFF80
+    ]);
+    let device: Device = run_program(&program);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x02);
+    assert_eq!(registers.get8(&Register8::C), 0x10);
+}
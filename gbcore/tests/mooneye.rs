@@ -0,0 +1,118 @@
+mod common;
+
+use common::{build_rom, new_device, read_rom, run_mooneye_test};
+
+const FIBONACCI: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+fn run_mooneye(path: &str) {
+    let registers: Option<[u8; 6]> = run_mooneye_test(&mut new_device(read_rom(path)), 1200);
+    assert_eq!(registers, Some(FIBONACCI), "{} failed", path);
+}
+
+macro_rules! mooneye_tests {
+    ($($name:ident: $path:expr,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the Mooneye Test Suite ROMs in tests/roms"]
+            fn $name() {
+                run_mooneye($path);
+            }
+        )*
+    };
+}
+
+mooneye_tests! {
+    add_sp_e_timing: "mooneye/acceptance/add_sp_e_timing.gb",
+    call_cc_timing: "mooneye/acceptance/call_cc_timing.gb",
+    call_cc_timing2: "mooneye/acceptance/call_cc_timing2.gb",
+    call_timing: "mooneye/acceptance/call_timing.gb",
+    call_timing2: "mooneye/acceptance/call_timing2.gb",
+    di_timing_gs: "mooneye/acceptance/di_timing-GS.gb",
+    div_timing: "mooneye/acceptance/div_timing.gb",
+    ei_sequence: "mooneye/acceptance/ei_sequence.gb",
+    ei_timing: "mooneye/acceptance/ei_timing.gb",
+    halt_ime0_ei: "mooneye/acceptance/halt_ime0_ei.gb",
+    halt_ime0_nointr_timing: "mooneye/acceptance/halt_ime0_nointr_timing.gb",
+    halt_ime1_timing: "mooneye/acceptance/halt_ime1_timing.gb",
+    halt_ime1_timing2_gs: "mooneye/acceptance/halt_ime1_timing2-GS.gb",
+    if_ie_registers: "mooneye/acceptance/if_ie_registers.gb",
+    intr_timing: "mooneye/acceptance/intr_timing.gb",
+    jp_cc_timing: "mooneye/acceptance/jp_cc_timing.gb",
+    jp_timing: "mooneye/acceptance/jp_timing.gb",
+    ld_hl_sp_e_timing: "mooneye/acceptance/ld_hl_sp_e_timing.gb",
+    oam_dma_restart: "mooneye/acceptance/oam_dma_restart.gb",
+    oam_dma_start: "mooneye/acceptance/oam_dma_start.gb",
+    oam_dma_timing: "mooneye/acceptance/oam_dma_timing.gb",
+    pop_timing: "mooneye/acceptance/pop_timing.gb",
+    push_timing: "mooneye/acceptance/push_timing.gb",
+    rapid_di_ei: "mooneye/acceptance/rapid_di_ei.gb",
+    ret_cc_timing: "mooneye/acceptance/ret_cc_timing.gb",
+    ret_timing: "mooneye/acceptance/ret_timing.gb",
+    reti_intr_timing: "mooneye/acceptance/reti_intr_timing.gb",
+    reti_timing: "mooneye/acceptance/reti_timing.gb",
+    rst_timing: "mooneye/acceptance/rst_timing.gb",
+    bits_mem_oam: "mooneye/acceptance/bits/mem_oam.gb",
+    bits_reg_f: "mooneye/acceptance/bits/reg_f.gb",
+    bits_unused_hwio_gs: "mooneye/acceptance/bits/unused_hwio-GS.gb",
+    instr_daa: "mooneye/acceptance/instr/daa.gb",
+    interrupts_ie_push: "mooneye/acceptance/interrupts/ie_push.gb",
+    oam_dma_basic: "mooneye/acceptance/oam_dma/basic.gb",
+    oam_dma_reg_read: "mooneye/acceptance/oam_dma/reg_read.gb",
+    oam_dma_sources_gs: "mooneye/acceptance/oam_dma/sources-GS.gb",
+    ppu_hblank_ly_scx_timing_gs: "mooneye/acceptance/ppu/hblank_ly_scx_timing-GS.gb",
+    ppu_intr_1_2_timing_gs: "mooneye/acceptance/ppu/intr_1_2_timing-GS.gb",
+    ppu_intr_2_0_timing: "mooneye/acceptance/ppu/intr_2_0_timing.gb",
+    ppu_intr_2_mode0_timing: "mooneye/acceptance/ppu/intr_2_mode0_timing.gb",
+    ppu_intr_2_mode0_timing_sprites: "mooneye/acceptance/ppu/intr_2_mode0_timing_sprites.gb",
+    ppu_intr_2_mode3_timing: "mooneye/acceptance/ppu/intr_2_mode3_timing.gb",
+    ppu_intr_2_oam_ok_timing: "mooneye/acceptance/ppu/intr_2_oam_ok_timing.gb",
+    ppu_lcdon_timing_gs: "mooneye/acceptance/ppu/lcdon_timing-GS.gb",
+    ppu_lcdon_write_timing_gs: "mooneye/acceptance/ppu/lcdon_write_timing-GS.gb",
+    ppu_stat_irq_blocking: "mooneye/acceptance/ppu/stat_irq_blocking.gb",
+    ppu_stat_lyc_onoff: "mooneye/acceptance/ppu/stat_lyc_onoff.gb",
+    ppu_vblank_stat_intr_gs: "mooneye/acceptance/ppu/vblank_stat_intr-GS.gb",
+    timer_div_write: "mooneye/acceptance/timer/div_write.gb",
+    timer_rapid_toggle: "mooneye/acceptance/timer/rapid_toggle.gb",
+    timer_tim00: "mooneye/acceptance/timer/tim00.gb",
+    timer_tim00_div_trigger: "mooneye/acceptance/timer/tim00_div_trigger.gb",
+    timer_tim01: "mooneye/acceptance/timer/tim01.gb",
+    timer_tim01_div_trigger: "mooneye/acceptance/timer/tim01_div_trigger.gb",
+    timer_tim10: "mooneye/acceptance/timer/tim10.gb",
+    timer_tim10_div_trigger: "mooneye/acceptance/timer/tim10_div_trigger.gb",
+    timer_tim11: "mooneye/acceptance/timer/tim11.gb",
+    timer_tim11_div_trigger: "mooneye/acceptance/timer/tim11_div_trigger.gb",
+    timer_tima_reload: "mooneye/acceptance/timer/tima_reload.gb",
+    timer_tima_write_reloading: "mooneye/acceptance/timer/tima_write_reloading.gb",
+    timer_tma_write_reloading: "mooneye/acceptance/timer/tma_write_reloading.gb",
+}
+
+fn fibonacci_program(values: [u8; 6]) -> Vec<u8> {
+    vec![
+        0x06, values[0], // LD B, n
+        0x0E, values[1], // LD C, n
+        0x16, values[2], // LD D, n
+        0x1E, values[3], // LD E, n
+        0x26, values[4], // LD H, n
+        0x2E, values[5], // LD L, n
+        0x40,      // LD B, B
+        0x18, 0xFE, // JR -2
+    ]
+}
+
+#[test]
+fn breakpoint_reports_pass_registers() {
+    let rom: Vec<u8> = build_rom(&fibonacci_program(FIBONACCI));
+    assert_eq!(run_mooneye_test(&mut new_device(rom), 10), Some(FIBONACCI));
+}
+
+#[test]
+fn breakpoint_reports_fail_registers() {
+    let rom: Vec<u8> = build_rom(&fibonacci_program([0x42; 6]));
+    assert_eq!(run_mooneye_test(&mut new_device(rom), 10), Some([0x42; 6]));
+}
+
+#[test]
+fn missing_breakpoint_times_out() {
+    let rom: Vec<u8> = build_rom(&[0x18, 0xFE]);
+    assert_eq!(run_mooneye_test(&mut new_device(rom), 10), None);
+}
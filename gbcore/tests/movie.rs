@@ -0,0 +1,154 @@
+mod common;
+
+use common::{build_rom, new_device, new_lcd_buffer};
+use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use gbcore::movie::Movie;
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+
+// Adds the pressed directions to a counter every loop, writes it to the first tile
+// and plays it as the frequency of channel 1, so that the inputs change the output
+const INPUT_COUNTER: &[u8] = &[
+    0x3E, 0xF0, // LD A, 0xF0
+    0xE0, 0x12, // LDH (NR12), A
+    0x21, 0x00, 0xC0, // LD HL, 0xC000
+    0x3E, 0x20, // LD A, 0x20, selects the directions
+    0xE0, 0x00, // LDH (P1), A
+    0xF0, 0x00, // LDH A, (P1)
+    0x2F, // CPL
+    0xE6, 0x0F, // AND 0x0F
+    0x86, // ADD A, (HL)
+    0x77, // LD (HL), A
+    0xE0, 0x13, // LDH (NR13), A
+    0xEA, 0x00, 0x80, // LD (0x8000), A
+    0x3E, 0x87, // LD A, 0x87
+    0xE0, 0x14, // LDH (NR14), A
+    0x18, 0xEA, // JR -22
+];
+
+fn joypad(input: u8) -> JoypadState {
+    JoypadState {
+        up: (input & 0x01) != 0,
+        down: (input & 0x02) != 0,
+        left: (input & 0x04) != 0,
+        right: (input & 0x08) != 0,
+        a: (input & 0x10) != 0,
+        b: (input & 0x20) != 0,
+        start: (input & 0x40) != 0,
+        select: (input & 0x80) != 0,
+    }
+}
+
+fn buttons(joypad_state: &JoypadState) -> [bool; 8] {
+    [
+        joypad_state.up,
+        joypad_state.down,
+        joypad_state.left,
+        joypad_state.right,
+        joypad_state.a,
+        joypad_state.b,
+        joypad_state.start,
+        joypad_state.select,
+    ]
+}
+
+fn recorded_movie() -> Movie {
+    let mut device: Device = new_device(build_rom(INPUT_COUNTER));
+    let mut movie: Movie = Movie::new(&mut device, 1000, false);
+    for input in [0x00, 0x01, 0x80, 0xFF, 0x5A] {
+        movie.record(&joypad(input));
+    }
+    movie
+}
+
+// Plays `frames` frames with the inputs given for each frame
+fn play(
+    device: &mut Device,
+    frames: usize,
+    input: impl Fn(usize) -> JoypadState,
+) -> (Vec<u32>, Vec<i16>) {
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    let mut samples: Vec<i16> = Vec::new();
+    for frame in 0..frames {
+        device.frame(&mut lcd_buffer, input(frame));
+        device.drain_samples(&mut samples);
+    }
+    (lcd_buffer.buffer, samples)
+}
+
+#[test]
+fn recorded_inputs_are_played_back() {
+    let movie: Movie = recorded_movie();
+
+    assert_eq!(movie.len(), 5);
+    for (frame, input) in [0x00, 0x01, 0x80, 0xFF, 0x5A].iter().enumerate() {
+        assert_eq!(
+            buttons(&movie.get_input(frame).unwrap()),
+            buttons(&joypad(*input))
+        );
+    }
+    assert!(movie.get_input(5).is_none());
+}
+
+#[test]
+fn movies_survive_a_round_trip_through_bytes() {
+    let movie: Movie = recorded_movie();
+    let bytes: Vec<u8> = movie.to_bytes();
+    let loaded: Movie = Movie::from_bytes(&bytes).unwrap();
+
+    assert_eq!(loaded.len(), movie.len());
+    for frame in 0..movie.len() {
+        assert_eq!(
+            buttons(&loaded.get_input(frame).unwrap()),
+            buttons(&movie.get_input(frame).unwrap())
+        );
+    }
+    assert_eq!(loaded.to_bytes(), bytes);
+}
+
+#[test]
+fn invalid_movies_are_rejected() {
+    let bytes: Vec<u8> = recorded_movie().to_bytes();
+
+    let mut magic: Vec<u8> = bytes.clone();
+    magic[4] = b'X';
+    assert!(Movie::from_bytes(&magic).is_err());
+
+    // The version follows the length prefixed magic
+    let mut version: Vec<u8> = bytes.clone();
+    version[8] += 1;
+    let error: String = Movie::from_bytes(&version).err().unwrap().to_string();
+    assert!(error.contains("version"), "{}", error);
+
+    assert!(Movie::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut trailing: Vec<u8> = bytes.clone();
+    trailing.push(0x00);
+    assert!(Movie::from_bytes(&trailing).is_err());
+    assert!(Movie::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn playing_a_movie_back_reproduces_the_frames_and_audio() {
+    let inputs: Vec<JoypadState> = (0..30).map(|frame| joypad((frame * 37) as u8)).collect();
+
+    let mut device: Device = new_device(build_rom(INPUT_COUNTER));
+    let mut movie: Movie = Movie::new(&mut device, 1000, false);
+    for input in &inputs {
+        movie.record(input);
+    }
+    let recorded = play(&mut device, inputs.len(), |frame| inputs[frame]);
+
+    let movie: Movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+    let mut device: Device = movie.create_device(build_rom(INPUT_COUNTER)).unwrap();
+    let replayed = play(&mut device, movie.len(), |frame| {
+        movie.get_input(frame).unwrap()
+    });
+
+    assert!(recorded.1.iter().any(|sample| *sample != 0));
+    assert_eq!(recorded, replayed);
+
+    // Without the inputs the same frames come out differently
+    let mut device: Device = new_device(build_rom(INPUT_COUNTER));
+    let idle = play(&mut device, inputs.len(), |_| joypad(0x00));
+    assert_ne!(recorded, idle);
+}
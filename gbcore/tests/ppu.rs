@@ -0,0 +1,277 @@
+mod common;
+
+use common::{build_rom, new_device, run_until_trap, Lcd};
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::mmu::address_spaces::Addressable;
+use gbcore::ppu::colors::DmgPalette;
+use gbcore::Device;
+
+// Waits for the STAT `mode`, writes 0x5A to `location` and reads it back into B, then
+// reads it again into C during VBlank
+fn access_during_mode(mode: u8, location: u16, unrestricted: bool) -> Device {
+    let mut code: Vec<u8> = vec![0x21, location as u8, (location >> 8) as u8]; // LD HL, location
+    code.extend_from_slice(&wait_for_mode(mode));
+    code.extend_from_slice(&[
+        0x36, 0x5A, // LD (HL), 0x5A
+        0x7E, // LD A, (HL)
+        0x47, // LD B, A
+    ]);
+    code.extend_from_slice(&wait_for_mode(1));
+    code.extend_from_slice(&[
+        0x4E, // LD C, (HL)
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+
+    let mut device: Device = new_device(build_rom(&code));
+    device.set_unrestricted_access(unrestricted);
+    // The program can start after VBlank, waiting for the next one
+    assert!(
+        run_until_trap(&mut device, 2),
+        "The program didn't reach VBlank"
+    );
+    device
+}
+
+fn wait_for_mode(mode: u8) -> [u8; 8] {
+    [
+        0xF0, 0x41, // LDH A, (STAT)
+        0xE6, 0x03, // AND 0x03
+        0xFE, mode, // CP mode
+        0x20, 0xF8, // JR NZ, -8
+    ]
+}
+
+#[test]
+fn vram_is_blocked_during_pixel_transfer() {
+    let device: Device = access_during_mode(3, 0x8000, false);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0xFF);
+    assert_eq!(registers.get8(&Register8::C), 0x00);
+}
+
+#[test]
+fn oam_is_blocked_during_oam_search() {
+    let device: Device = access_during_mode(2, 0xFE00, false);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0xFF);
+    assert_eq!(registers.get8(&Register8::C), 0x00);
+}
+
+#[test]
+fn unrestricted_access_ignores_the_ppu_mode() {
+    for (mode, location) in [(3, 0x8000), (2, 0xFE00)] {
+        let device: Device = access_during_mode(mode, location, true);
+
+        let registers: &Registers = device.get_cpu().get_registers();
+        assert_eq!(registers.get8(&Register8::B), 0x5A);
+        assert_eq!(registers.get8(&Register8::C), 0x5A);
+    }
+}
+
+impl Lcd {
+    fn get_mode(&self) -> u8 {
+        self.mmu.read(0xFF41) & 0x03
+    }
+
+    // Runs until the end of mode 3 of the current line, which has to be in mode 2
+    fn get_pixel_transfer_length(&mut self) -> u32 {
+        assert_eq!(self.get_mode(), 2);
+        self.run(80);
+        let mut length: u32 = 0;
+        while self.get_mode() == 3 {
+            self.run(1);
+            length += 1;
+        }
+        length
+    }
+}
+
+#[test]
+fn mode_3_is_lengthened_by_the_fine_scroll() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF41, 0x00);
+    assert_eq!(lcd.get_pixel_transfer_length(), 172);
+    lcd.run(456 - 80 - 172);
+
+    lcd.mmu.write(0xFF43, 0x0D);
+    assert_eq!(lcd.mmu.read(0xFF44), 1);
+    assert_eq!(lcd.get_pixel_transfer_length(), 177);
+}
+
+#[test]
+fn mode_3_is_lengthened_by_the_window_and_sprites() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF40, 0xB1);
+    lcd.mmu.write(0xFF4B, 0x07);
+    assert_eq!(lcd.get_pixel_transfer_length(), 178);
+    lcd.run(456 - 80 - 178);
+
+    // The first sprite in a tile stalls until the tile is fetched, the next one doesn't
+    lcd.mmu.write(0xFF40, 0x93);
+    for (sprite, x) in [(0, 8), (1, 8), (2, 13)] {
+        lcd.mmu.write(0xFE00 + sprite * 4, 17);
+        lcd.mmu.write(0xFE00 + sprite * 4 + 1, x);
+    }
+    assert_eq!(lcd.get_pixel_transfer_length(), 172 + 11 + 6 + 6);
+}
+
+#[test]
+fn palette_writes_apply_from_the_pixel_drawn_at_that_dot() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF40, 0x93);
+    lcd.mmu.write(0xFF47, 0x00);
+    lcd.run(80 + 12 + 50);
+    lcd.mmu.write(0xFF47, 0xFF);
+    lcd.run(456 - 80 - 12 - 50);
+
+    // On line 1 a sprite at the left edge stalls the output for 11 dots
+    lcd.mmu.write(0xFE00, 17);
+    lcd.mmu.write(0xFE01, 8);
+    lcd.mmu.write(0xFF47, 0x00);
+    lcd.run(80 + 12 + 11 + 50);
+    lcd.mmu.write(0xFF47, 0xFF);
+    lcd.run(456 - 80 - 12 - 11 - 50);
+
+    for line in lcd.buffer.buffer[0..320].chunks(160) {
+        assert_eq!(line[0..50], [WHITE; 50]);
+        assert_eq!(line[50..160], [BLACK; 110]);
+    }
+}
+
+#[test]
+fn ly_reads_zero_after_the_first_m_cycle_of_line_153() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.run(153 * 456);
+    assert_eq!(lcd.mmu.read(0xFF44), 153);
+    lcd.run(4);
+    assert_eq!(lcd.mmu.read(0xFF44), 0);
+    assert_eq!(lcd.mmu.read(0xFF41) & 0x07, 0x05);
+    lcd.run(456 - 4);
+    assert_eq!(lcd.mmu.read(0xFF44), 0);
+    assert_eq!(lcd.mmu.read(0xFF41) & 0x07, 0x06);
+}
+
+#[test]
+fn mode_2_interrupt_is_requested_when_vblank_starts() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF41, 0x20);
+    lcd.run(144 * 456 - 1);
+    lcd.mmu.write(0xFF0F, 0x00);
+    lcd.run(2);
+    assert_eq!(lcd.mmu.read(0xFF0F) & 0x03, 0x03);
+}
+
+#[test]
+fn first_line_after_turning_the_lcd_on_skips_mode_2() {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF40, 0x11);
+    lcd.run(1000);
+    lcd.mmu.write(0xFF40, 0x91);
+    lcd.run(1);
+    assert_eq!(lcd.get_mode(), 0);
+    lcd.run(79);
+    assert_eq!(lcd.get_mode(), 3);
+    lcd.run(456 - 80);
+    assert_eq!(lcd.mmu.read(0xFF44), 1);
+    assert_eq!(lcd.get_mode(), 2);
+}
+
+const WHITE: u32 = 0xFFFFFF;
+const LIGHT: u32 = 0xAAAAAA;
+const BLACK: u32 = 0x000000;
+
+// Draws line 0 over a blank background with the given sprites as (X, tile), tile 1
+// is black, tile 2 light grey and tile 3 has its left half transparent
+fn draw_sprites(sprites: &[(u8, u8)]) -> Vec<u32> {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.mmu.write(0xFF40, 0x93);
+    lcd.mmu.write(0xFF48, 0xE4);
+    for row in 0..8 {
+        lcd.mmu.write(0x8010 + row * 2, 0xFF);
+        lcd.mmu.write(0x8011 + row * 2, 0xFF);
+        lcd.mmu.write(0x8020 + row * 2, 0xFF);
+        lcd.mmu.write(0x8030 + row * 2, 0x0F);
+    }
+    for (i, (x, tile)) in sprites.iter().enumerate() {
+        let sprite: u16 = 0xFE00 + (i as u16) * 4;
+        lcd.mmu.write(sprite, 16);
+        lcd.mmu.write(sprite + 1, *x);
+        lcd.mmu.write(sprite + 2, *tile);
+    }
+    lcd.run(456);
+    lcd.buffer.buffer[0..160].to_vec()
+}
+
+#[test]
+fn overlapping_sprites_are_ordered_by_x_then_oam_index() {
+    let line: Vec<u32> = draw_sprites(&[(5, 2), (3, 1), (20, 2), (16, 1), (40, 2), (40, 1)]);
+
+    assert_eq!(line[0..5], [BLACK, BLACK, BLACK, LIGHT, LIGHT]);
+    assert_eq!(
+        line[12..20],
+        [BLACK, BLACK, BLACK, BLACK, LIGHT, LIGHT, LIGHT, LIGHT]
+    );
+    assert_eq!(line[32..40], [LIGHT; 8]);
+}
+
+#[test]
+fn sprites_below_show_through_transparent_pixels() {
+    let line: Vec<u32> = draw_sprites(&[(60, 3), (60, 1)]);
+
+    assert_eq!(
+        line[52..60],
+        [BLACK, BLACK, BLACK, BLACK, LIGHT, LIGHT, LIGHT, LIGHT]
+    );
+    assert_eq!(line[60], WHITE);
+}
+
+#[test]
+fn hidden_sprites_count_toward_the_limit_of_ten_per_line() {
+    let mut sprites: Vec<(u8, u8)> = vec![(0, 1); 10];
+    sprites.push((80, 1));
+    let line: Vec<u32> = draw_sprites(&sprites);
+
+    assert_eq!(line, vec![WHITE; 160]);
+}
+
+// Draws the four shades at the start of line 0 with the given palette
+fn draw_shades(palette: DmgPalette) -> Vec<u32> {
+    let mut lcd: Lcd = Lcd::new();
+    lcd.ppu.set_dmg_palette(palette);
+    lcd.mmu.write(0xFF47, 0xE4);
+    lcd.mmu.write(0x8000, 0x50);
+    lcd.mmu.write(0x8001, 0x30);
+    lcd.run(456);
+    lcd.buffer.buffer[0..4].to_vec()
+}
+
+#[test]
+fn shades_follow_the_selected_palette() {
+    assert_eq!(
+        draw_shades(DmgPalette::Grey),
+        vec![WHITE, LIGHT, 0x555555, BLACK]
+    );
+    assert_eq!(
+        draw_shades(DmgPalette::GreenPeaSoup),
+        vec![0x9BBC0F, 0x8BAC0F, 0x306230, 0x0F380F]
+    );
+
+    let custom: DmgPalette = DmgPalette::parse("E0F8D0,88C070,#346856,081820").unwrap();
+    assert_eq!(
+        draw_shades(custom),
+        vec![0xE0F8D0, 0x88C070, 0x346856, 0x081820]
+    );
+}
+
+#[test]
+fn palettes_are_parsed_from_preset_names_or_four_colors() {
+    for preset in DmgPalette::PRESETS {
+        assert_eq!(DmgPalette::parse(preset.get_name()).unwrap(), preset);
+    }
+    assert!(DmgPalette::parse("sepia").is_err());
+    assert!(DmgPalette::parse("E0F8D0,88C070,346856").is_err());
+    assert!(DmgPalette::parse("E0F8D0,88C070,346856,08182G").is_err());
+}
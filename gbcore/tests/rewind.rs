@@ -0,0 +1,58 @@
+mod common;
+
+use common::{build_rom, new_device, new_lcd_buffer, released};
+use gbcore::rewind::Rewind;
+use gbcore::Device;
+
+fn assert_round_trip(from: &[u8], to: &[u8]) {
+    let delta: Vec<u8> = Rewind::encode(from, to);
+    assert_eq!(Rewind::decode(from, &delta), to);
+}
+
+#[test]
+fn deltas_round_trip_states_of_the_same_length() {
+    let from: Vec<u8> = (0..=255).collect();
+    let mut to: Vec<u8> = from.clone();
+    to[0] = 0xAA;
+    to[100..110].fill(0x00);
+    to[255] = 0x55;
+
+    assert_round_trip(&from, &to);
+    assert_round_trip(&from, &from);
+    // Unchanged runs are only stored as their length
+    assert!(Rewind::encode(&from, &from).len() < 8);
+}
+
+#[test]
+fn deltas_round_trip_states_of_different_lengths() {
+    let short: Vec<u8> = vec![0x11; 200];
+    let long: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+
+    assert_round_trip(&short, &long);
+    assert_round_trip(&long, &short);
+    assert_round_trip(&[], &long);
+    assert_round_trip(&long, &[]);
+}
+
+#[test]
+fn step_back_restores_earlier_frames_until_the_capacity() {
+    // INC A, JR -3 changes the machine state every frame
+    let mut device: Device = new_device(build_rom(&[0x3C, 0x18, 0xFD]));
+    let mut rewind: Rewind = Rewind::new(1, 3);
+    let mut states: Vec<Vec<u8>> = Vec::new();
+    for _ in 0..6 {
+        device.frame(&mut new_lcd_buffer(), released());
+        rewind.record(&device);
+        states.push(device.save_state());
+    }
+    assert_eq!(rewind.len(), 3);
+
+    // Exactly `capacity` steps back succeed, the oldest frames were dropped
+    for frame in [4, 3, 2] {
+        assert!(rewind.step_back(&mut device));
+        assert_eq!(device.save_state(), states[frame]);
+    }
+    assert!(!rewind.step_back(&mut device));
+    assert_eq!(device.save_state(), states[2]);
+    assert!(rewind.is_empty());
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use common::{build_rom, new_device, new_lcd_buffer, read_rom, released, HEIGHT, WIDTH};
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+// Runs until the ROM executes LD B,B, then one more frame so the screen is complete
+fn run_screenshot_test(device: &mut Device, max_frames: usize) -> Vec<u32> {
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    device.set_opcode_trap(Some(0x40));
+
+    for _ in 0..max_frames {
+        lcd_buffer.cleared = false;
+        device.frame(&mut lcd_buffer, released());
+        if device.take_trap() {
+            device.set_opcode_trap(None);
+            lcd_buffer.cleared = false;
+            device.frame(&mut lcd_buffer, released());
+            break;
+        }
+    }
+
+    if lcd_buffer.cleared {
+        vec![0xffffff; WIDTH * HEIGHT]
+    } else {
+        lcd_buffer.buffer
+    }
+}
+
+// Reference images come from different palettes, so pixels are compared by shade
+fn shade(color: u32) -> u8 {
+    let luminance: u32 =
+        (((color >> 16) & 0xff) * 299 + ((color >> 8) & 0xff) * 587 + (color & 0xff) * 114) / 1000;
+    ((255 - luminance + 42) / 85) as u8
+}
+
+fn read_png(data: &[u8]) -> Vec<u32> {
+    let mut reader = png::Decoder::new(data).read_info().unwrap();
+    let mut bytes: Vec<u8> = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut bytes).unwrap();
+    assert_eq!((info.width as usize, info.height as usize), (WIDTH, HEIGHT));
+    assert_eq!(info.bit_depth, png::BitDepth::Eight);
+
+    let channels: usize = info.color_type.samples();
+    bytes[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| match channels {
+            1 | 2 => (pixel[0] as u32) * 0x010101,
+            _ => ((pixel[0] as u32) << 16) | ((pixel[1] as u32) << 8) | (pixel[2] as u32),
+        })
+        .collect()
+}
+
+// The diff shows the emulator output dimmed with the mismatching pixels in red
+fn write_diff(name: &str, actual: &[u32], expected: &[u32]) -> PathBuf {
+    let directory: PathBuf = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("screenshot-diffs");
+    fs::create_dir_all(&directory).unwrap();
+    let path: PathBuf = directory.join(format!("{}.png", name));
+
+    let mut data: Vec<u8> = Vec::with_capacity(WIDTH * HEIGHT * 3);
+    for (actual, expected) in actual.iter().zip(expected) {
+        if shade(*actual) != shade(*expected) {
+            data.extend_from_slice(&[0xff, 0x00, 0x00]);
+        } else {
+            let grey: u8 = 0x80 + (((actual & 0xff) as u8) >> 1);
+            data.extend_from_slice(&[grey, grey, grey]);
+        }
+    }
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(&path).unwrap()),
+        WIDTH as u32,
+        HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&data)
+        .unwrap();
+    path
+}
+
+fn assert_screen(name: &str, actual: &[u32], expected: &[u32]) {
+    let mismatches: usize = actual
+        .iter()
+        .zip(expected)
+        .filter(|(actual, expected)| shade(**actual) != shade(**expected))
+        .count();
+    if mismatches > 0 {
+        let path: PathBuf = write_diff(name, actual, expected);
+        panic!(
+            "{}: {} pixels differ from the reference, see {}",
+            name,
+            mismatches,
+            path.display()
+        );
+    }
+}
+
+fn run_reference(name: &str, rom_path: &str, reference_path: &str) {
+    let reference: Vec<u32> = read_png(&read_rom(reference_path));
+    let actual: Vec<u32> = run_screenshot_test(&mut new_device(read_rom(rom_path)), 600);
+    assert_screen(name, &actual, &reference);
+}
+
+#[test]
+#[ignore = "needs dmg-acid2 and its reference in tests/roms"]
+fn dmg_acid2() {
+    run_reference(
+        "dmg-acid2",
+        "dmg-acid2/dmg-acid2.gb",
+        "dmg-acid2/reference-dmg.png",
+    );
+}
+
+macro_rules! mealybug_tests {
+    ($($name:ident,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the Mealybug Tearoom ROMs and references in tests/roms"]
+            fn $name() {
+                run_reference(
+                    stringify!($name),
+                    concat!("mealybug/", stringify!($name), ".gb"),
+                    concat!("mealybug/", stringify!($name), ".png"),
+                );
+            }
+        )*
+    };
+}
+
+mealybug_tests! {
+    m2_win_en_toggle,
+    m3_bgp_change,
+    m3_bgp_change_sprites,
+    m3_lcdc_bg_en_change,
+    m3_lcdc_bg_map_change,
+    m3_lcdc_obj_en_change,
+    m3_lcdc_obj_en_change_variant,
+    m3_lcdc_obj_size_change,
+    m3_lcdc_obj_size_change_scx,
+    m3_lcdc_tile_sel_change,
+    m3_lcdc_tile_sel_win_change,
+    m3_lcdc_win_en_change_multiple,
+    m3_lcdc_win_en_change_multiple_wx,
+    m3_lcdc_win_map_change,
+    m3_obp0_change,
+    m3_scx_high_5_bits,
+    m3_scx_low_3_bits,
+    m3_scy_change,
+    m3_window_timing,
+    m3_window_timing_wx_0,
+    m3_wx_4_change,
+    m3_wx_4_change_sprites,
+    m3_wx_5_change,
+    m3_wx_6_change,
+}
+
+#[test]
+fn filled_background_matches_black_reference() {
+    // Turns the LCD off during VBlank, fills the first tile with colour 3, points the whole map
+    // at it, turns the LCD back on and signals LD B,B once a frame has been drawn
+    let program: Vec<u8> = vec![
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x90, // CP 0x90
+        0x38, 0xFA, // JR C, -6
+        0xAF, // XOR A
+        0xE0, 0x40, // LDH (LCDC), A
+        0x21, 0x00, 0x80, // LD HL, 0x8000
+        0x3E, 0xFF, // LD A, 0xFF
+        0x0E, 0x10, // LD C, 0x10
+        0x22, // LD (HL+), A
+        0x0D, // DEC C
+        0x20, 0xFC, // JR NZ, -4
+        0x21, 0x00, 0x98, // LD HL, 0x9800
+        0x01, 0x00, 0x04, // LD BC, 0x0400
+        0xAF, // XOR A
+        0x22, // LD (HL+), A
+        0x0B, // DEC BC
+        0x78, // LD A, B
+        0xB1, // OR C
+        0x20, 0xF9, // JR NZ, -7
+        0x3E, 0x91, // LD A, 0x91
+        0xE0, 0x40, // LDH (LCDC), A
+        0xF0, 0x44, // LDH A, (LY)
+        0xFE, 0x90, // CP 0x90
+        0x20, 0xFA, // JR NZ, -6
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ];
+    let actual: Vec<u32> = run_screenshot_test(&mut new_device(build_rom(&program)), 60);
+    assert_screen(
+        "filled_background",
+        &actual,
+        &vec![0x000000; WIDTH * HEIGHT],
+    );
+}
+
+#[test]
+fn png_references_are_compared_by_shade() {
+    let mut data: Vec<u8> = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let pixels: Vec<u8> = (0..WIDTH * HEIGHT)
+            .map(|i| [0xff, 0xad, 0x63, 0x00][i % 4])
+            .collect();
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&pixels)
+            .unwrap();
+    }
+    let expected: Vec<u32> = (0..WIDTH * HEIGHT)
+        .map(|i| [0xffffff, 0xaaaaaa, 0x555555, 0x000000][i % 4])
+        .collect();
+    assert_screen("shades", &read_png(&data), &expected);
+}
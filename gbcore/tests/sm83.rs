@@ -0,0 +1,258 @@
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::cpu::{Bus, Cpu};
+use gbcore::mmu::address_spaces::Addressable;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// STOP and HALT don't finish within a single step, the vectors can't describe them
+const SKIPPED_OPCODES: &[&str] = &["10", "76"];
+
+#[derive(Debug, PartialEq)]
+enum Access {
+    Cycle,
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// A flat 64 KiB memory that records every access and M-cycle, interrupts are never requested
+struct TestBus {
+    ram: Vec<u8>,
+    accesses: RefCell<Vec<Access>>,
+}
+
+impl TestBus {
+    fn new() -> TestBus {
+        TestBus {
+            ram: vec![0; 0x10000],
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl Addressable for TestBus {
+    fn write(&mut self, location: u16, byte: u8) {
+        self.accesses
+            .borrow_mut()
+            .push(Access::Write(location, byte));
+        self.ram[location as usize] = byte;
+    }
+
+    fn read(&self, location: u16) -> u8 {
+        let byte: u8 = self.ram[location as usize];
+        self.accesses
+            .borrow_mut()
+            .push(Access::Read(location, byte));
+        byte
+    }
+}
+
+impl Bus for TestBus {
+    fn get_ie(&self) -> u8 {
+        0
+    }
+
+    fn get_if(&self) -> u8 {
+        0
+    }
+
+    fn set_if(&mut self, _value: u8) {}
+
+    fn cycle(&mut self) {
+        self.accesses.borrow_mut().push(Access::Cycle);
+    }
+
+    fn stop(&mut self) -> bool {
+        false
+    }
+
+    fn is_joypad_low(&self) -> bool {
+        false
+    }
+}
+
+const REGISTERS: &[(&str, Register8)] = &[
+    ("a", Register8::A),
+    ("b", Register8::B),
+    ("c", Register8::C),
+    ("d", Register8::D),
+    ("e", Register8::E),
+    ("f", Register8::F),
+    ("h", Register8::H),
+    ("l", Register8::L),
+];
+
+fn field(state: &Value, name: &str) -> u16 {
+    state[name]
+        .as_u64()
+        .unwrap_or_else(|| panic!("Missing \"{}\" in {}", name, state)) as u16
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .expect("Missing \"ram\"")
+        .iter()
+        .map(|entry| {
+            (
+                entry[0].as_u64().unwrap() as u16,
+                entry[1].as_u64().unwrap() as u8,
+            )
+        })
+        .collect()
+}
+
+// Cycles are [address, value, activity] triples or null for internal cycles, the activity
+// being either "read"/"write" or the "r-m"/"-wm"/"---" pin notation. Each M-cycle runs
+// before the access made in it
+fn accesses(cycles: &Value) -> Vec<Access> {
+    let mut accesses: Vec<Access> = Vec::new();
+    for cycle in cycles.as_array().expect("Missing \"cycles\"") {
+        accesses.push(Access::Cycle);
+        let activity: &str = cycle[2].as_str().unwrap_or("");
+        let (address, value) = (cycle[0].as_u64(), cycle[1].as_u64());
+        if let (Some(address), Some(value)) = (address, value) {
+            if activity == "read" || activity.starts_with('r') {
+                accesses.push(Access::Read(address as u16, value as u8));
+            } else if activity == "write" || activity.contains('w') {
+                accesses.push(Access::Write(address as u16, value as u8));
+            }
+        }
+    }
+    accesses
+}
+
+fn run_vector(test: &Value) -> Result<(), String> {
+    let name: &str = test["name"].as_str().unwrap_or("unnamed");
+    let (initial, expected) = (&test["initial"], &test["final"]);
+
+    let mut bus: TestBus = TestBus::new();
+    for (address, value) in ram(initial) {
+        bus.ram[address as usize] = value;
+    }
+    let mut cpu: Cpu = Cpu::new();
+    let registers: &mut Registers = cpu.get_registers_mut();
+    for (name, register) in REGISTERS {
+        registers.set8(register, field(initial, name) as u8);
+    }
+    registers.pc = field(initial, "pc");
+    registers.sp = field(initial, "sp");
+    cpu.set_ime(field(initial, "ime") != 0);
+
+    let cycles: u8 = cpu.tick(&mut bus);
+
+    let mut errors: Vec<String> = Vec::new();
+    let registers: &Registers = cpu.get_registers();
+    for (name, register) in REGISTERS {
+        let (actual, wanted) = (registers.get8(register), field(expected, name) as u8);
+        if actual != wanted {
+            errors.push(format!(
+                "{} is {:#04x}, expected {:#04x}",
+                name, actual, wanted
+            ));
+        }
+    }
+    for (name, actual) in [("pc", registers.pc), ("sp", registers.sp)] {
+        if actual != field(expected, name) {
+            errors.push(format!(
+                "{} is {:#06x}, expected {:#06x}",
+                name,
+                actual,
+                field(expected, name)
+            ));
+        }
+    }
+    if expected["ime"].is_u64() && cpu.get_ime() != (field(expected, "ime") != 0) {
+        errors.push(format!(
+            "ime is {}, expected {}",
+            cpu.get_ime(),
+            !cpu.get_ime()
+        ));
+    }
+    for (address, value) in ram(expected) {
+        if bus.ram[address as usize] != value {
+            errors.push(format!(
+                "{:#06x} is {:#04x}, expected {:#04x}",
+                address, bus.ram[address as usize], value
+            ));
+        }
+    }
+    let wanted: Vec<Access> = accesses(&test["cycles"]);
+    if *bus.accesses.borrow() != wanted {
+        errors.push(format!(
+            "accesses are {:?}, expected {:?}",
+            bus.accesses.borrow(),
+            wanted
+        ));
+    }
+    let wanted_cycles: usize = test["cycles"].as_array().map_or(0, |cycles| cycles.len()) * 4;
+    if cycles as usize != wanted_cycles {
+        errors.push(format!(
+            "took {} cycles, expected {}",
+            cycles, wanted_cycles
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{}: {}", name, errors.join(", ")))
+    }
+}
+
+// Runs every vector of every json file in `dir`, returns the number of vectors run
+fn run_vectors(dir: &Path) -> usize {
+    let mut paths: Vec<_> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect(),
+        Err(_) => return 0,
+    };
+    paths.sort();
+
+    let mut count: usize = 0;
+    let mut failures: Vec<String> = Vec::new();
+    for path in paths {
+        let stem: String = path.file_stem().unwrap().to_string_lossy().into_owned();
+        if SKIPPED_OPCODES.contains(&stem.as_str()) {
+            continue;
+        }
+        let tests: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("Invalid test file {}: {}", path.display(), e));
+        for test in tests.as_array().expect("A test file holds a list of tests") {
+            if let Err(e) = run_vector(test) {
+                failures.push(e);
+            }
+            count += 1;
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} vectors failed:\n{}",
+        failures.len(),
+        count,
+        failures
+            .iter()
+            .take(20)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    count
+}
+
+#[test]
+#[ignore = "needs the SM83 single step tests in tests/roms"]
+fn sm83_vectors() {
+    let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/sm83/v1");
+    assert!(run_vectors(&dir) > 0, "No vectors in {}", dir.display());
+}
+
+#[test]
+fn handwritten_vectors() {
+    let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sm83");
+    assert!(run_vectors(&dir) > 0);
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use common::{
+    build_battery_rom, build_cgb_rom, new_device, new_lcd_buffer, released, run_until_trap,
+};
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+use std::panic::{self, AssertUnwindSafe};
+
+// Counts in the first byte of cart RAM, writes the count to the first tile and plays
+// it as the frequency of channel 1, so that frames, audio and RAM all change
+const COUNTER: &[u8] = &[
+    0x3E, 0x0A, // LD A, 0x0A
+    0xEA, 0x00, 0x00, // LD (0x0000), A, enables cart RAM
+    0x3E, 0xF0, // LD A, 0xF0
+    0xE0, 0x12, // LDH (NR12), A
+    0x21, 0x00, 0xA0, // LD HL, 0xA000
+    0x34, // INC (HL)
+    0x7E, // LD A, (HL)
+    0xE0, 0x13, // LDH (NR13), A
+    0x3E, 0x87, // LD A, 0x87
+    0xE0, 0x14, // LDH (NR14), A
+    0x7E, // LD A, (HL)
+    0xEA, 0x00, 0x80, // LD (0x8000), A
+    0x18, 0xEF, // JR -17
+];
+
+struct Output {
+    frame: Vec<u32>,
+    samples: Vec<i16>,
+    ram: Option<Vec<u8>>,
+}
+
+fn run_frames(device: &mut Device, frames: usize) -> Output {
+    let mut lcd_buffer: LcdBuffer = new_lcd_buffer();
+    let mut samples: Vec<i16> = Vec::new();
+    for _ in 0..frames {
+        device.frame(&mut lcd_buffer, released());
+        device.drain_samples(&mut samples);
+    }
+    Output {
+        frame: lcd_buffer.buffer,
+        samples,
+        ram: device.dump_ram(),
+    }
+}
+
+fn counter_device() -> Device {
+    let mut device: Device = new_device(build_battery_rom(COUNTER));
+    run_frames(&mut device, 10);
+    device
+}
+
+#[test]
+fn loading_a_state_replays_the_same_frames_audio_and_ram() {
+    let mut device: Device = counter_device();
+    let state: Vec<u8> = device.save_state();
+    let first: Output = run_frames(&mut device, 5);
+
+    device.load_state(&state).unwrap();
+    let second: Output = run_frames(&mut device, 5);
+
+    assert!(first.samples.iter().any(|sample| *sample != 0));
+    assert_eq!(first.frame, second.frame);
+    assert_eq!(first.samples, second.samples);
+    assert_eq!(first.ram, second.ram);
+}
+
+#[test]
+fn states_of_other_roms_are_rejected() {
+    let state: Vec<u8> = counter_device().save_state();
+
+    let mut rom: Vec<u8> = build_battery_rom(COUNTER);
+    rom[0x134..0x138].copy_from_slice(b"GAME");
+    let mut device: Device = new_device(rom);
+    let error: String = device.load_state(&state).unwrap_err().to_string();
+    assert!(error.contains("belongs to"), "{}", error);
+}
+
+#[test]
+fn states_of_other_versions_are_rejected() {
+    let mut state: Vec<u8> = counter_device().save_state();
+    // The version follows the length prefixed magic
+    state[8] = state[8].wrapping_add(1);
+
+    let error: String = counter_device().load_state(&state).unwrap_err().to_string();
+    assert!(
+        error.contains("Unsupported save state version"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn states_made_at_another_sample_rate_are_rejected() {
+    let state: Vec<u8> = counter_device().save_state();
+
+    let mut device: Device = new_device(build_battery_rom(COUNTER));
+    device.set_sample_rate(44100);
+    let error: String = device.load_state(&state).unwrap_err().to_string();
+    assert!(error.contains("48000 Hz"), "{}", error);
+}
+
+#[test]
+fn truncated_and_corrupted_states_are_rejected() {
+    let state: Vec<u8> = counter_device().save_state();
+    let mut device: Device = counter_device();
+    assert!(device.load_state(&state[..state.len() - 1]).is_err());
+    assert!(device.load_state(&state[..20]).is_err());
+    assert!(device.load_state(b"NTHS").is_err());
+
+    // A block length running past the end of the data
+    let mut corrupted: Vec<u8> = state[..10].to_vec();
+    corrupted.extend_from_slice(&u32::MAX.to_le_bytes());
+    assert!(device.load_state(&corrupted).is_err());
+}
+
+#[test]
+fn a_failed_load_leaves_the_machine_as_it_was() {
+    let old_state: Vec<u8> = counter_device().save_state();
+    let mut device: Device = counter_device();
+    run_frames(&mut device, 3);
+    let state: Vec<u8> = device.save_state();
+
+    assert!(device
+        .load_state(&old_state[..old_state.len() - 1])
+        .is_err());
+    assert_eq!(device.save_state(), state);
+
+    let mut expected: Device = counter_device();
+    run_frames(&mut expected, 3);
+    assert_eq!(
+        run_frames(&mut device, 2).frame,
+        run_frames(&mut expected, 2).frame
+    );
+}
+
+// Plays all four channels with sprites enabled, then traps about every two lines
+const BUSY: &[u8] = &[
+    0x3E, 0xF0, // LD A, 0xF0
+    0xE0, 0x12, // LDH (NR12), A
+    0xE0, 0x17, // LDH (NR22), A
+    0xE0, 0x21, // LDH (NR42), A
+    0x3E, 0x80, // LD A, 0x80
+    0xE0, 0x1A, // LDH (NR30), A
+    0x3E, 0x87, // LD A, 0x87
+    0xE0, 0x14, // LDH (NR14), A
+    0xE0, 0x19, // LDH (NR24), A
+    0xE0, 0x1E, // LDH (NR34), A
+    0xE0, 0x23, // LDH (NR44), A
+    0x3E, 0x93, // LD A, 0x93
+    0xE0, 0x40, // LDH (LCDC), A
+    0x3E, 0x3C, // LD A, 60
+    0x3D, // DEC A
+    0x20, 0xFD, // JR NZ, -3
+    0x40, // LD B, B
+    0x18, 0xF8, // JR -8
+];
+
+// Offsets of the bytes to corrupt, skipping the contents of the memory blocks which
+// hold arbitrary data anyway
+fn register_offsets(state: &[u8]) -> Vec<usize> {
+    let mut offsets: Vec<usize> = Vec::new();
+    let mut position: usize = 0;
+    while position < state.len() {
+        let len: usize = state
+            .get(position..position + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+            .unwrap_or(0);
+        if len >= 0x1000 && position + 4 + len <= state.len() {
+            offsets.extend(position..position + 4);
+            position += 4 + len;
+        } else {
+            offsets.push(position);
+            position += 1;
+        }
+    }
+    offsets
+}
+
+#[test]
+fn corrupted_states_are_rejected_without_panicking() {
+    let mut device: Device = new_device(build_cgb_rom(BUSY));
+    assert!(run_until_trap(&mut device, 1));
+    let state: Vec<u8> = device.save_state();
+
+    let mut panics: Vec<String> = Vec::new();
+    let mut rejected: usize = 0;
+    for position in register_offsets(&state) {
+        for flip in [0x01, 0x80, 0xFF] {
+            let mut corrupted: Vec<u8> = state.clone();
+            corrupted[position] ^= flip;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                if device.load_state(&corrupted).is_err() {
+                    return false;
+                }
+                // Whatever was accepted has to run too
+                run_until_trap(&mut device, 1);
+                true
+            }));
+            match result {
+                Ok(true) => {}
+                Ok(false) => rejected += 1,
+                Err(_) => panics.push(format!("{}^{:#04X}", position, flip)),
+            }
+            device.load_state(&state).unwrap();
+        }
+    }
+    assert!(panics.is_empty(), "Panicked at {}", panics.join(", "));
+    assert!(rejected > 0);
+}
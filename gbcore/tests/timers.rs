@@ -0,0 +1,107 @@
+mod common;
+
+use common::{build_rom, new_device, run_until_trap};
+use gbcore::cpu::registers::{Register8, Registers};
+use gbcore::Device;
+
+// Sets TAC, clears IF and resets DIV right before `program`, which therefore starts
+// with the system clock at 0. Accesses happen at the end of their M-cycle
+fn run_timer_program(tac: u8, program: &[u8]) -> Device {
+    let mut code: Vec<u8> = vec![
+        0x3E, tac, // LD A, tac
+        0xE0, 0x07, // LDH (TAC), A
+        0x3E, 0x42, // LD A, 0x42
+        0xE0, 0x06, // LDH (TMA), A
+        0xAF, // XOR A
+        0xE0, 0x0F, // LDH (IF), A
+        0xEA, 0x04, 0xFF, // LD (DIV), A
+    ];
+    code.extend_from_slice(program);
+    code.extend_from_slice(&[
+        0x40, // LD B, B
+        0x18, 0xFE, // JR -2
+    ]);
+
+    let mut device: Device = new_device(build_rom(&code));
+    assert!(run_until_trap(&mut device, 1));
+    device
+}
+
+#[test]
+fn writing_div_with_the_selected_bit_set_increments_tima() {
+    let device: Device = run_timer_program(
+        0x06,
+        &[
+            0xE0, 0x05, // LDH (TIMA), A at 12
+            0x00, 0x00, 0x00, 0x00, 0x00, // NOPs up to 32
+            0xE0, 0x04, // LDH (DIV), A at 44, bit 5 is set
+            0xF0, 0x05, // LDH A, (TIMA)
+            0x47, // LD B, A
+        ],
+    );
+    assert_eq!(device.get_cpu().get_registers().get8(&Register8::B), 1);
+}
+
+// With TAC set to 0x05 TIMA is set to 0xFE at 28, it's incremented at 32 and overflows at 48
+const OVERFLOW_AT_48: &[u8] = &[
+    0x01, 0x05, 0xFF, // LD BC, TIMA
+    0x3E, 0xFE, // LD A, 0xFE
+    0x02, // LD (BC), A at 28
+];
+
+#[test]
+fn tima_reads_zero_for_an_m_cycle_after_overflowing() {
+    let mut program: Vec<u8> = OVERFLOW_AT_48.to_vec();
+    program.extend_from_slice(&[
+        0x0A, // LD A, (BC) at 36
+        0x67, // LD H, A
+        0x0A, // LD A, (BC) at 48
+        0x6F, // LD L, A
+        0x0A, // LD A, (BC) at 60
+        0x57, // LD D, A
+        0xF0, 0x0F, // LDH A, (IF)
+        0x5F, // LD E, A
+    ]);
+    let device: Device = run_timer_program(0x05, &program);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::H), 0xFF);
+    assert_eq!(registers.get8(&Register8::L), 0x00);
+    assert_eq!(registers.get8(&Register8::D), 0x42);
+    assert_ne!(registers.get8(&Register8::E) & 0x04, 0);
+}
+
+#[test]
+fn writing_tima_after_an_overflow_cancels_the_reload() {
+    let mut program: Vec<u8> = OVERFLOW_AT_48.to_vec();
+    program.extend_from_slice(&[
+        0x3E, 0x10, // LD A, 0x10
+        0x00, // NOP
+        0x02, // LD (BC), A at 48
+        0x0A, // LD A, (BC)
+        0x47, // LD B, A
+        0xF0, 0x0F, // LDH A, (IF)
+        0x4F, // LD C, A
+    ]);
+    let device: Device = run_timer_program(0x05, &program);
+
+    let registers: &Registers = device.get_cpu().get_registers();
+    assert_eq!(registers.get8(&Register8::B), 0x10);
+    assert_eq!(registers.get8(&Register8::C) & 0x04, 0);
+}
+
+#[test]
+fn writing_tima_while_it_is_reloaded_is_ignored() {
+    let mut program: Vec<u8> = OVERFLOW_AT_48.to_vec();
+    program.extend_from_slice(&[
+        0x3E, 0x10, // LD A, 0x10
+        0x00, // NOP
+        0x00, // NOP
+        0x02, // LD (BC), A at 52
+        0x0A, // LD A, (BC)
+        0x47, // LD B, A
+    ]);
+    let device: Device = run_timer_program(0x05, &program);
+
+    assert_eq!(device.get_cpu().get_registers().get8(&Register8::B), 0x42);
+}
@@ -0,0 +1,87 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, Stream, StreamConfig};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+pub struct AudioOutput {
+    _stream: Stream,
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+}
+
+impl AudioOutput {
+    pub fn new() -> Option<AudioOutput> {
+        let device = cpal::default_host().default_output_device()?;
+        let supported_config = device.default_output_config().ok()?;
+        let sample_format: SampleFormat = supported_config.sample_format();
+        let config: StreamConfig = supported_config.config();
+        let queue: Arc<Mutex<VecDeque<i16>>> = Arc::new(Mutex::new(VecDeque::new()));
+
+        let stream: Stream = match sample_format {
+            SampleFormat::F32 => AudioOutput::build_stream::<f32>(&device, &config, &queue),
+            SampleFormat::I16 => AudioOutput::build_stream::<i16>(&device, &config, &queue),
+            SampleFormat::U16 => AudioOutput::build_stream::<u16>(&device, &config, &queue),
+            _ => None,
+        }?;
+        stream.play().ok()?;
+
+        Some(AudioOutput {
+            _stream: stream,
+            queue,
+            sample_rate: config.sample_rate.0,
+        })
+    }
+
+    fn build_stream<T>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        queue: &Arc<Mutex<VecDeque<i16>>>,
+    ) -> Option<Stream>
+    where
+        T: SizedSample + FromSample<i16>,
+    {
+        let channels: usize = config.channels as usize;
+        let queue: Arc<Mutex<VecDeque<i16>>> = Arc::clone(queue);
+
+        device
+            .build_output_stream(
+                config,
+                move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                    let mut queue = queue.lock().unwrap();
+                    for frame in data.chunks_mut(channels) {
+                        // Play silence when the emulator falls behind
+                        let (left, right): (i16, i16) = if queue.len() >= 2 {
+                            (queue.pop_front().unwrap(), queue.pop_front().unwrap())
+                        } else {
+                            (0, 0)
+                        };
+                        for (i, sample) in frame.iter_mut().enumerate() {
+                            let value: i16 = match (channels, i) {
+                                (1, _) => ((left as i32 + right as i32) / 2) as i16,
+                                (_, 0) => left,
+                                (_, 1) => right,
+                                _ => 0,
+                            };
+                            *sample = T::from_sample(value);
+                        }
+                    }
+                },
+                |err| eprintln!("Audio stream error: {}", err),
+                None,
+            )
+            .ok()
+    }
+
+    pub fn get_sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn queue(&self, samples: &[i16]) {
+        self.queue.lock().unwrap().extend(samples.iter());
+    }
+
+    // Number of stereo samples still waiting to be played
+    pub fn get_buffered(&self) -> usize {
+        self.queue.lock().unwrap().len() / 2
+    }
+}
@@ -1,14 +1,38 @@
+use audio::AudioOutput;
 use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use gbcore::movie::Movie;
+use gbcore::ppu::colors::{ColorCorrection, DmgPalette};
 use gbcore::ppu::LcdBuffer;
+use gbcore::rewind::Rewind;
 use gbcore::Device;
-use minifb::{Key, Scale, Window, WindowOptions};
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use slots::Slot;
 use std::env;
 use std::error::Error;
 use std::fs;
+use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+mod audio;
+mod slots;
+
 const WIDTH: usize = 160;
 const HEIGHT: usize = 144;
+const FRAME_CYCLES: usize = 70224;
+const CPU_CLOCK: usize = 4194304;
+// Frames of audio queued ahead of the playback position
+const AUDIO_LATENCY_FRAMES: usize = 3;
+const SLOT_KEYS: [Key; 9] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+];
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
@@ -19,6 +43,35 @@ fn main() -> Result<(), Box<dyn Error>> {
         fs::read(format!("{}.{}", &args[1], "rtc")).ok(),
     )?;
 
+    // Movies start from power on and keep the RTC on the emulated clock
+    let mut recording: Option<Movie> = None;
+    let mut playback: Option<Movie> = None;
+    let mut movie_path: &str = "";
+    if let Some(mode @ ("--record" | "--play")) = args.get(2).map(|arg| arg.as_str()) {
+        movie_path = args.get(3).ok_or("Missing movie path")?;
+        if movie_path.starts_with("--") {
+            return Err(format!("Invalid movie path {}", movie_path).into());
+        }
+        if mode == "--record" {
+            recording = Some(Movie::new(&mut emulator, host_secs(), false));
+        } else {
+            let movie: Movie = Movie::from_bytes(&fs::read(movie_path)?)?;
+            emulator = movie.create_device(fs::read(&args[1])?)?;
+            playback = Some(movie);
+        }
+    }
+    let movie_active: bool = recording.is_some() || playback.is_some();
+
+    // A preset name or four RRGGBB colors, P cycles through the presets and C toggles
+    // the color correction while playing
+    let mut palette: DmgPalette = match args.iter().position(|arg| arg == "--palette") {
+        Some(i) => DmgPalette::parse(args.get(i + 1).ok_or("Missing palette")?)?,
+        None => DmgPalette::Grey,
+    };
+    let mut color_correction: ColorCorrection = ColorCorrection::Raw;
+    emulator.set_dmg_palette(palette);
+    let mut movie_frame: usize = 0;
+
     let mut window = Window::new(
         "nth-boy",
         WIDTH,
@@ -30,7 +83,22 @@ fn main() -> Result<(), Box<dyn Error>> {
     )
     .expect("Unable to create window");
 
-    window.limit_update_rate(Some(Duration::from_micros(16742)));
+    // When an audio device is available its playback rate paces the emulation,
+    // otherwise fall back to limiting the window update rate
+    let audio: Option<AudioOutput> = AudioOutput::new();
+    let audio_target: usize = match &audio {
+        Some(audio) => {
+            emulator.set_sample_rate(audio.get_sample_rate());
+            window.limit_update_rate(None);
+            (audio.get_sample_rate() as usize) * FRAME_CYCLES * AUDIO_LATENCY_FRAMES / CPU_CLOCK
+        }
+        None => {
+            window.limit_update_rate(Some(Duration::from_micros(16742)));
+            0
+        }
+    };
+    let mut samples: Vec<i16> = Vec::new();
+    let mut rewind: Rewind = Rewind::default();
 
     let empty_buffer: Vec<u32> = vec![0xffffff; WIDTH * HEIGHT];
 
@@ -38,30 +106,102 @@ fn main() -> Result<(), Box<dyn Error>> {
         buffer: vec![0; WIDTH * HEIGHT],
         cleared: false,
     };
+    // Holding shift to load a slot shows the thumbnails of the slots instead of the game
+    let mut picker: Option<Vec<u32>> = None;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
+        if let Some(audio) = &audio {
+            if audio.get_buffered() >= audio_target {
+                window.update();
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+        }
+
+        let rewinding: bool =
+            !movie_active && window.is_key_down(Key::R) && rewind.step_back(&mut emulator);
+
         let pressed_keys: Vec<Key> = window.get_keys();
-        emulator.update_rtc_now(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_secs(),
-        );
-        emulator.frame(
-            &mut lcd_buffer,
-            JoypadState {
-                up: pressed_keys.contains(&Key::W),
-                down: pressed_keys.contains(&Key::S),
-                left: pressed_keys.contains(&Key::A),
-                right: pressed_keys.contains(&Key::D),
-                a: pressed_keys.contains(&Key::J),
-                b: pressed_keys.contains(&Key::K),
-                start: pressed_keys.contains(&Key::Enter),
-                select: pressed_keys.contains(&Key::Delete),
-            },
-        );
-
-        if !lcd_buffer.cleared {
+        let mut joypad_state: JoypadState = JoypadState {
+            up: pressed_keys.contains(&Key::W),
+            down: pressed_keys.contains(&Key::S),
+            left: pressed_keys.contains(&Key::A),
+            right: pressed_keys.contains(&Key::D),
+            a: pressed_keys.contains(&Key::J),
+            b: pressed_keys.contains(&Key::K),
+            start: pressed_keys.contains(&Key::Enter),
+            select: pressed_keys.contains(&Key::Delete),
+        };
+        // Once the movie is over the keyboard takes control again
+        if let Some(input) = playback
+            .as_ref()
+            .and_then(|movie| movie.get_input(movie_frame))
+        {
+            joypad_state = input;
+        }
+        if let Some(movie) = &mut recording {
+            movie.record(&joypad_state);
+        }
+        movie_frame += 1;
+
+        emulator.update_rtc_now(host_secs());
+        emulator.frame(&mut lcd_buffer, joypad_state);
+
+        if !rewinding {
+            rewind.record(&emulator);
+        }
+
+        let shift_down: bool =
+            window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        if shift_down && !movie_active && picker.is_none() {
+            let mut screen: Vec<u32> = vec![0; WIDTH * HEIGHT];
+            Slot::draw_picker(&args[1], &mut screen, WIDTH);
+            picker = Some(screen);
+        } else if !shift_down {
+            picker = None;
+        }
+        for key in window.get_keys_pressed(KeyRepeat::No) {
+            if let Some(slot) = SLOT_KEYS.iter().position(|slot_key| *slot_key == key) {
+                let message: String = if shift_down && movie_active {
+                    String::from("Loading slots is disabled during movies")
+                } else if shift_down {
+                    rewind.clear();
+                    load_slot(&mut emulator, &args[1], slot + 1)
+                } else {
+                    save_slot(&emulator, &lcd_buffer, &args[1], slot + 1)
+                };
+                window.set_title(&format!("nth-boy - {}", message));
+            } else if key == Key::P {
+                palette = next_palette(palette);
+                emulator.set_dmg_palette(palette);
+                window.set_title(&format!("nth-boy - Palette {}", palette.get_name()));
+            } else if key == Key::C {
+                color_correction = match color_correction {
+                    ColorCorrection::Raw => ColorCorrection::Lcd,
+                    ColorCorrection::Lcd => ColorCorrection::Raw,
+                };
+                emulator.set_color_correction(color_correction);
+                window.set_title(&format!(
+                    "nth-boy - Color correction {:?}",
+                    color_correction
+                ));
+            }
+        }
+
+        samples.clear();
+        emulator.drain_samples(&mut samples);
+        // Silence keeps the audio clock pacing the frames while rewinding
+        if rewinding {
+            samples.fill(0);
+        }
+        if let Some(audio) = &audio {
+            audio.queue(&samples);
+        }
+
+        if let Some(screen) = &picker {
+            window.update_with_buffer(screen, WIDTH, HEIGHT).unwrap();
+            lcd_buffer.cleared = false;
+        } else if !lcd_buffer.cleared {
             window
                 .update_with_buffer(&lcd_buffer.buffer, WIDTH, HEIGHT)
                 .unwrap();
@@ -73,6 +213,15 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    if let Some(movie) = &recording {
+        fs::write(movie_path, movie.to_bytes())?;
+    }
+
+    // Playing a movie back must not touch the saves of the game
+    if playback.is_some() {
+        return Ok(());
+    }
+
     if let Some(save) = emulator.dump_ram() {
         fs::write(format!("{}.{}", &args[1], "sav"), &save)?;
     }
@@ -83,3 +232,43 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+fn host_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+// A custom palette is followed by the first preset
+fn next_palette(palette: DmgPalette) -> DmgPalette {
+    let presets: &[DmgPalette] = &DmgPalette::PRESETS;
+    match presets.iter().position(|preset| *preset == palette) {
+        Some(i) => presets[(i + 1) % presets.len()],
+        None => presets[0],
+    }
+}
+
+// A disabled display is saved as a blank white thumbnail
+fn save_slot(emulator: &Device, lcd_buffer: &LcdBuffer, rom_path: &str, slot: usize) -> String {
+    let path: String = Slot::get_path(rom_path, slot);
+    let blank: Vec<u32>;
+    let screen: &[u32] = if lcd_buffer.cleared {
+        blank = vec![0xffffff; WIDTH * HEIGHT];
+        &blank
+    } else {
+        &lcd_buffer.buffer
+    };
+    match Slot::new(emulator.save_state(), screen, WIDTH).write(&path) {
+        Ok(()) => format!("Saved slot {}", slot),
+        Err(e) => format!("Unable to save slot {}: {}", slot, e),
+    }
+}
+
+fn load_slot(emulator: &mut Device, rom_path: &str, slot: usize) -> String {
+    let path: String = Slot::get_path(rom_path, slot);
+    match Slot::read(&path).and_then(|save| emulator.load_state(&save.state)) {
+        Ok(()) => format!("Loaded slot {}", slot),
+        Err(e) => format!("Unable to load slot {}: {}", slot, e),
+    }
+}
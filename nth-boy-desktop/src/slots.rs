@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::fs;
+
+const SLOT_MAGIC: &[u8; 4] = b"NTHT";
+pub const THUMBNAIL_WIDTH: usize = 80;
+pub const THUMBNAIL_HEIGHT: usize = 72;
+// The picker lays the slots out in a 3x3 grid
+const PICKER_COLUMNS: usize = 3;
+
+pub struct Slot {
+    pub thumbnail: Vec<u32>,
+    pub state: Vec<u8>,
+}
+
+impl Slot {
+    // Builds a half resolution thumbnail by averaging each 2x2 block of the screen
+    pub fn new(state: Vec<u8>, screen: &[u32], screen_width: usize) -> Slot {
+        let mut thumbnail: Vec<u32> = vec![0; THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT];
+
+        for y in 0..THUMBNAIL_HEIGHT {
+            for x in 0..THUMBNAIL_WIDTH {
+                let mut color: u32 = 0;
+                for shift in [0, 8, 16] {
+                    let mut channel: u32 = 0;
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let pixel: u32 = screen[(y * 2 + dy) * screen_width + x * 2 + dx];
+                        channel += (pixel >> shift) & 0xff;
+                    }
+                    color |= (channel / 4) << shift;
+                }
+                thumbnail[y * THUMBNAIL_WIDTH + x] = color;
+            }
+        }
+
+        Slot { thumbnail, state }
+    }
+
+    // Draws the thumbnails of the slots 1 to 9 over `screen` in reading order,
+    // shrinking them to fit. Empty slots are left black
+    pub fn draw_picker(rom_path: &str, screen: &mut [u32], screen_width: usize) {
+        let screen_height: usize = screen.len() / screen_width;
+        let cell_width: usize = screen_width / PICKER_COLUMNS;
+        let cell_height: usize = screen_height / PICKER_COLUMNS;
+        screen.fill(0);
+
+        for slot in 0..PICKER_COLUMNS * PICKER_COLUMNS {
+            let thumbnail: Vec<u32> = match Slot::read(&Slot::get_path(rom_path, slot + 1)) {
+                Ok(save) => save.thumbnail,
+                Err(_) => continue,
+            };
+            let left: usize = (slot % PICKER_COLUMNS) * cell_width;
+            let top: usize = (slot / PICKER_COLUMNS) * cell_height;
+            // One pixel of the cell is kept as a border
+            for y in 0..cell_height - 1 {
+                for x in 0..cell_width - 1 {
+                    let source_x: usize = x * THUMBNAIL_WIDTH / (cell_width - 1);
+                    let source_y: usize = y * THUMBNAIL_HEIGHT / (cell_height - 1);
+                    screen[(top + y) * screen_width + left + x] =
+                        thumbnail[source_y * THUMBNAIL_WIDTH + source_x];
+                }
+            }
+        }
+    }
+
+    pub fn get_path(rom_path: &str, slot: usize) -> String {
+        format!("{}.ss{}", rom_path, slot)
+    }
+
+    pub fn read(path: &str) -> Result<Slot, Box<dyn Error>> {
+        let data: Vec<u8> = fs::read(path)?;
+        let thumbnail_end: usize = SLOT_MAGIC.len() + THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 3;
+
+        if data.len() < thumbnail_end || &data[..SLOT_MAGIC.len()] != SLOT_MAGIC {
+            return Err(format!("{} is not a save slot", path).into());
+        }
+
+        let thumbnail: Vec<u32> = data[SLOT_MAGIC.len()..thumbnail_end]
+            .chunks(3)
+            .map(|rgb| ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | (rgb[2] as u32))
+            .collect();
+
+        Ok(Slot {
+            thumbnail,
+            state: data[thumbnail_end..].to_vec(),
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        let mut data: Vec<u8> = Vec::from(&SLOT_MAGIC[..]);
+        for pixel in &self.thumbnail {
+            data.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8]);
+        }
+        data.extend_from_slice(&self.state);
+        fs::write(path, &data)?;
+        Ok(())
+    }
+}
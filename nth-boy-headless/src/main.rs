@@ -0,0 +1,162 @@
+use gbcore::movie::Movie;
+use gbcore::ppu::LcdBuffer;
+use gbcore::Device;
+use script::InputScript;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+
+mod script;
+
+const WIDTH: usize = 160;
+const HEIGHT: usize = 144;
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+const USAGE: &str = "Usage: nth-boy-headless <rom> --frames <n> [--movie <file> | [--input <file>] [--ram <file>]] [--png <file>]";
+
+struct Options {
+    rom: String,
+    frames: usize,
+    movie: Option<String>,
+    input: Option<String>,
+    ram: Option<String>,
+    png: Option<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, Box<dyn Error>> {
+        let mut frames: Option<usize> = None;
+        let mut options: Options = Options {
+            rom: args.get(1).ok_or(USAGE)?.clone(),
+            frames: 0,
+            movie: None,
+            input: None,
+            ram: None,
+            png: None,
+        };
+
+        let mut i: usize = 2;
+        while i < args.len() {
+            let value: String = args.get(i + 1).ok_or(USAGE)?.clone();
+            match args[i].as_str() {
+                "--frames" => frames = Some(value.parse().map_err(|_| USAGE)?),
+                "--movie" => options.movie = Some(value),
+                "--input" => options.input = Some(value),
+                "--ram" => options.ram = Some(value),
+                "--png" => options.png = Some(value),
+                _ => return Err(USAGE.into()),
+            }
+            i += 2;
+        }
+
+        // A movie starts from its own RAM, so a RAM file would be silently ignored
+        if options.movie.is_some() && (options.input.is_some() || options.ram.is_some()) {
+            return Err(USAGE.into());
+        }
+        options.frames = frames.ok_or("Missing --frames")?;
+        Ok(options)
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().collect();
+    let options: Options = Options::parse(&args)?;
+    let rom: Vec<u8> = fs::read(&options.rom)?;
+
+    let movie: Option<Movie> = match &options.movie {
+        Some(path) => Some(Movie::from_bytes(&fs::read(path)?)?),
+        None => None,
+    };
+    let script: InputScript = match &options.input {
+        Some(path) => InputScript::parse(&fs::read_to_string(path)?)?,
+        None => InputScript::parse("")?,
+    };
+
+    // Without a movie the RTC starts from the epoch so that every run is identical
+    let mut emulator: Device = match &movie {
+        Some(movie) => movie.create_device(rom)?,
+        None => {
+            let ram: Option<Vec<u8>> = match &options.ram {
+                Some(path) => Some(fs::read(path)?),
+                None => None,
+            };
+            let mut emulator: Device = Device::new(rom, ram, None)?;
+            emulator.use_emulated_rtc(0);
+            emulator
+        }
+    };
+
+    let mut lcd_buffer: LcdBuffer = LcdBuffer {
+        buffer: vec![0; WIDTH * HEIGHT],
+        cleared: false,
+    };
+    let mut samples: Vec<i16> = Vec::new();
+    let mut audio_hash: u64 = FNV_OFFSET;
+
+    for frame in 0..options.frames {
+        let joypad_state = match &movie {
+            Some(movie) => movie.get_input(frame).unwrap_or(InputScript::released()),
+            None => script.get_input(frame),
+        };
+        lcd_buffer.cleared = false;
+        emulator.frame(&mut lcd_buffer, joypad_state);
+
+        samples.clear();
+        emulator.drain_samples(&mut samples);
+        for sample in &samples {
+            audio_hash = fnv_update(audio_hash, &sample.to_le_bytes());
+        }
+    }
+
+    // A disabled display shows up as a blank white screen
+    let screen: Vec<u32> = if lcd_buffer.cleared {
+        vec![0xffffff; WIDTH * HEIGHT]
+    } else {
+        lcd_buffer.buffer
+    };
+
+    let mut frame_hash: u64 = FNV_OFFSET;
+    for pixel in &screen {
+        frame_hash = fnv_update(frame_hash, &pixel.to_le_bytes());
+    }
+    println!("frame {:016x}", frame_hash);
+    println!("audio {:016x}", audio_hash);
+    match emulator.dump_ram() {
+        Some(ram) => println!("sram {:016x}", fnv_update(FNV_OFFSET, &ram)),
+        None => println!("sram none"),
+    }
+
+    if let Some(path) = &options.png {
+        write_png(path, &screen)?;
+    }
+
+    Ok(())
+}
+
+// 64 bit FNV-1a, stable across platforms and releases unlike the std hashers
+fn fnv_update(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn write_png(path: &str, screen: &[u32]) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        WIDTH as u32,
+        HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data: Vec<u8> = Vec::with_capacity(WIDTH * HEIGHT * 3);
+    for pixel in screen {
+        data.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8]);
+    }
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use std::error::Error;
+
+// An input script is a list of lines in the form `<frame> [buttons...]`, each one
+// holding the listed buttons from that frame until the next line. `#` starts a comment
+pub struct InputScript {
+    changes: Vec<(usize, JoypadState)>,
+}
+
+impl InputScript {
+    pub fn parse(script: &str) -> Result<InputScript, Box<dyn Error>> {
+        let mut changes: Vec<(usize, JoypadState)> = Vec::new();
+
+        for (line_no, line) in script.lines().enumerate() {
+            let line: &str = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let frame: usize = tokens
+                .next()
+                .unwrap_or("")
+                .parse()
+                .map_err(|_| format!("Invalid frame number at line {}", line_no + 1))?;
+            if changes.last().is_some_and(|(last, _)| *last > frame) {
+                return Err(format!("Frames are not in order at line {}", line_no + 1).into());
+            }
+
+            let mut joypad_state: JoypadState = InputScript::released();
+            for button in tokens {
+                match button {
+                    "up" => joypad_state.up = true,
+                    "down" => joypad_state.down = true,
+                    "left" => joypad_state.left = true,
+                    "right" => joypad_state.right = true,
+                    "a" => joypad_state.a = true,
+                    "b" => joypad_state.b = true,
+                    "start" => joypad_state.start = true,
+                    "select" => joypad_state.select = true,
+                    _ => {
+                        return Err(format!(
+                            "Unknown button \"{}\" at line {}",
+                            button,
+                            line_no + 1
+                        )
+                        .into())
+                    }
+                }
+            }
+            changes.push((frame, joypad_state));
+        }
+
+        Ok(InputScript { changes })
+    }
+
+    pub fn get_input(&self, frame: usize) -> JoypadState {
+        self.changes
+            .iter()
+            .rev()
+            .find(|(start, _)| *start <= frame)
+            .map(|(_, joypad_state)| *joypad_state)
+            .unwrap_or(InputScript::released())
+    }
+
+    pub fn released() -> JoypadState {
+        JoypadState {
+            up: false,
+            down: false,
+            left: false,
+            right: false,
+            a: false,
+            b: false,
+            start: false,
+            select: false,
+        }
+    }
+}
@@ -1,7 +1,9 @@
 mod utils;
 
 use gbcore::mmu::address_spaces::io::joypad::JoypadState;
+use gbcore::ppu::colors::{ColorCorrection, DmgPalette};
 use gbcore::ppu::LcdBuffer;
+use gbcore::rewind::Rewind;
 use gbcore::Device;
 use wasm_bindgen::prelude::*;
 use web_time::{SystemTime, UNIX_EPOCH};
@@ -23,6 +25,10 @@ const EMPTY_BUFFER: &'static [u32] = &[0xffffff; WIDTH * HEIGHT];
 struct Emulator {
     device: Device,
     lcd_buffer: LcdBuffer,
+    samples: Vec<i16>,
+    audio_buffer: Vec<f32>,
+    rewind: Rewind,
+    rewinding: bool,
     up: bool,
     down: bool,
     left: bool,
@@ -56,6 +62,10 @@ impl Emulator {
                 buffer: vec![0; WIDTH * HEIGHT],
                 cleared: false,
             },
+            samples: Vec::new(),
+            audio_buffer: Vec::new(),
+            rewind: Rewind::default(),
+            rewinding: false,
             up: false,
             down: false,
             left: false,
@@ -68,6 +78,7 @@ impl Emulator {
     }
 
     pub fn next_frame(&mut self) {
+        let rewinding: bool = self.rewinding && self.rewind.step_back(&mut self.device);
         self.device.update_rtc_now(
             SystemTime::now()
                 .duration_since(UNIX_EPOCH)
@@ -88,6 +99,47 @@ impl Emulator {
                 select: self.select,
             },
         );
+
+        if !rewinding {
+            self.rewind.record(&self.device);
+        }
+
+        self.samples.clear();
+        self.device.drain_samples(&mut self.samples);
+        if rewinding {
+            self.samples.fill(0);
+        }
+        self.audio_buffer.clear();
+        self.audio_buffer
+            .extend(self.samples.iter().map(|sample| (*sample as f32) / 32768.0));
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.device.set_sample_rate(sample_rate);
+    }
+
+    // Takes a preset name or four RRGGBB colors separated by commas
+    pub fn set_palette(&mut self, palette: &str) -> Result<(), String> {
+        let palette: DmgPalette = DmgPalette::parse(palette).map_err(|e| e.to_string())?;
+        self.device.set_dmg_palette(palette);
+        Ok(())
+    }
+
+    pub fn set_color_correction(&mut self, enabled: bool) {
+        self.device.set_color_correction(if enabled {
+            ColorCorrection::Lcd
+        } else {
+            ColorCorrection::Raw
+        });
+    }
+
+    // Interleaved stereo samples generated by the last call to next_frame
+    pub fn audio_buffer(&self) -> *const f32 {
+        self.audio_buffer.as_ptr()
+    }
+
+    pub fn audio_buffer_len(&self) -> usize {
+        self.audio_buffer.len()
     }
 
     pub fn buffer(&self) -> *const u32 {
@@ -177,4 +229,12 @@ impl Emulator {
     pub fn unset_select(&mut self) {
         self.select = false;
     }
+
+    pub fn set_rewind(&mut self) {
+        self.rewinding = true;
+    }
+
+    pub fn unset_rewind(&mut self) {
+        self.rewinding = false;
+    }
 }
@@ -0,0 +1,46 @@
+// Plays the interleaved stereo chunks posted by index.js after every frame
+const MAX_QUEUED_SAMPLES = sampleRate / 5;
+
+class NthBoyAudioProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.chunks = [];
+        this.offset = 0;
+        this.queued = 0;
+        this.port.onmessage = (e) => {
+            this.chunks.push(e.data);
+            this.queued += e.data.length / 2;
+            // Drop the oldest audio instead of letting the latency grow
+            while (this.queued > MAX_QUEUED_SAMPLES && this.chunks.length > 1) {
+                let dropped = this.chunks.shift();
+                this.queued -= (dropped.length - this.offset) / 2;
+                this.offset = 0;
+            }
+        };
+    }
+
+    process(inputs, outputs) {
+        const left = outputs[0][0];
+        const right = outputs[0][1];
+
+        for (let i = 0; i < left.length; ++i) {
+            if (this.chunks.length == 0) {
+                left[i] = 0;
+                right[i] = 0;
+                continue;
+            }
+            const chunk = this.chunks[0];
+            left[i] = chunk[this.offset];
+            right[i] = chunk[this.offset + 1];
+            this.offset += 2;
+            this.queued--;
+            if (this.offset >= chunk.length) {
+                this.chunks.shift();
+                this.offset = 0;
+            }
+        }
+        return true;
+    }
+}
+
+registerProcessor("nth-boy-audio", NthBoyAudioProcessor);
@@ -3,6 +3,8 @@ import { memory } from "nth-boy-wasm/nth_boy_wasm_bg.wasm";
 
 var emulator = null;
 var rom_name = null;
+var audioContext = null;
+var audioNode = null;
 var startTime = performance.now();
 var frames = 0;
 
@@ -13,21 +15,32 @@ const canvas = document.getElementById("lcd-canvas");
 const romSelect = document.getElementById("rom-select");
 const rom = document.getElementById("rom");
 const fps = document.getElementById("fps");
+const paletteSelect = document.getElementById("palette");
+const customPalette = document.getElementById("custom-palette");
+const colorCorrection = document.getElementById("color-correction");
 const ctx = canvas.getContext('2d');
-const palette = {
-    0x00: 0xFF000000,
-    0x55: 0xFF555555,
-    0xAA: 0xFFAAAAAA,
-    0xFF: 0xFFFFFFFF,
-};
 canvas.height = SCALE * height;
 canvas.width = SCALE * width;
 
 romSelect.addEventListener("click", (e) => {
     romSelect.blur();
+    startAudio();
     rom.click();
 });
 
+// Browsers only allow starting audio from a user gesture
+const startAudio = async () => {
+    if (audioContext == null) {
+        audioContext = new AudioContext();
+        await audioContext.audioWorklet.addModule("audio-processor.js");
+        audioNode = new AudioWorkletNode(audioContext, "nth-boy-audio", {
+            outputChannelCount: [2],
+        });
+        audioNode.connect(audioContext.destination);
+    }
+    await audioContext.resume();
+};
+
 rom.addEventListener("change", (e) => {
     if (rom.files.length > 0) {
         let reader = new FileReader();
@@ -46,6 +59,10 @@ rom.addEventListener("change", (e) => {
                     ram != null ? new Uint8Array(JSON.parse(ram)) : new Uint8Array(),
                     rtc != null ? new Uint8Array(JSON.parse(rtc)) : new Uint8Array(),
                 );
+                if (audioContext != null) {
+                    emulator.set_sample_rate(audioContext.sampleRate);
+                }
+                applyColors();
                 requestAnimationFrame(renderLoop);
             }, 100);
         }
@@ -53,6 +70,27 @@ rom.addEventListener("change", (e) => {
 
 });
 
+// Custom palettes are four RRGGBB colors separated by commas, from the lightest
+const applyColors = () => {
+    customPalette.style.display = paletteSelect.value == "custom" ? "" : "none";
+    if (emulator == null) {
+        return;
+    }
+    try {
+        emulator.set_palette(
+            paletteSelect.value == "custom" ? customPalette.value : paletteSelect.value
+        );
+        customPalette.setCustomValidity("");
+    } catch (error) {
+        customPalette.setCustomValidity(error);
+    }
+    emulator.set_color_correction(colorCorrection.checked);
+};
+
+paletteSelect.addEventListener("change", applyColors);
+customPalette.addEventListener("change", applyColors);
+colorCorrection.addEventListener("change", applyColors);
+
 window.addEventListener("beforeunload", (e) => {
     saveData();
 });
@@ -83,6 +121,9 @@ document.addEventListener("keydown", (e) => {
       case "Backspace":
         emulator.set_select();
         break;
+      case "r":
+        emulator.set_rewind();
+        break;
   }
 });
 
@@ -112,6 +153,9 @@ document.addEventListener("keyup", (e) => {
       case "Backspace":
         emulator.unset_select();
         break;
+      case "r":
+        emulator.unset_rewind();
+        break;
   }
 });
 
@@ -134,6 +178,7 @@ const renderLoop = () => {
       emulator.next_frame();
 
       drawFrame();
+      queueAudio();
       frames++;
 
       let now = performance.now();
@@ -148,16 +193,29 @@ const renderLoop = () => {
   }
 };
 
+const queueAudio = () => {
+  if (audioNode != null) {
+    const samples = new Float32Array(
+        memory.buffer,
+        emulator.audio_buffer(),
+        emulator.audio_buffer_len(),
+    ).slice();
+    audioNode.port.postMessage(samples, [samples.buffer]);
+  }
+};
+
 const drawFrame = () => {
   const framePtr = emulator.buffer();
-  const pixels = new Uint8Array(memory.buffer);
+  const pixels = new Uint32Array(memory.buffer, framePtr, width * height);
   const imageData = ctx.createImageData(width*SCALE, height*SCALE);
   const data = new Uint32Array(imageData.data.buffer);
 
   for(let r=0; r<(height*SCALE); ++r) {
     for(let c=0; c<(width*SCALE); ++c) {
         let i = Math.floor(c/SCALE) + (Math.floor(r/SCALE) * width);
-        let color = palette[pixels[framePtr + (i * 4)]];
+        // 0xRRGGBB pixels are stored as RGBA bytes in the image
+        let pixel = pixels[i];
+        let color = 0xFF000000 | ((pixel & 0xFF) << 16) | (pixel & 0xFF00) | ((pixel >> 16) & 0xFF);
         data[(r*width*SCALE) + c] = color;
     }
   }
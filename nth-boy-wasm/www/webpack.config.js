@@ -10,7 +10,7 @@ module.exports = {
   mode: "development",
   plugins: [
     new CopyWebpackPlugin({
-        patterns: ['index.html'],
+        patterns: ['index.html', 'audio-processor.js'],
     })
   ],
   experiments: {